@@ -0,0 +1,344 @@
+// level_tool.rs
+// Command line conversions between level files, generated terrain and interchange formats.
+
+use anyhow::{bail, Context, Result};
+use binary_greedy_mesher_demo_rs as demo;
+use demo::data::gltf::{self, GltfExportOptions};
+use demo::data::level_file::LevelFile;
+use demo::data::schematic::{self, BlockMapping, Schematic};
+use demo::data::volume::ChunkVolume;
+use demo::data::vox::{self, VoxScene};
+use demo::mapgen::generators::{
+    chunk_density_box, classify_box, generate_chunk_voxels, generator_from_config, DensityClass, Generator,
+};
+use demo::mapgen::sampling::{DensityLattice, SamplingConfig};
+use demo::mapgen::{bake, biome::BiomeMap, erosion, MapGenConfig};
+use demo::{CS, CS_P};
+use glam::IVec3;
+use rayon::prelude::*;
+use std::time::Instant;
+
+const USAGE: &str = "\
+Usage:
+  level_tool bake <config.toml> <out-level> [--min x,y,z] [--max x,y,z]
+  level_tool gltf <level-file> <out.gltf|out.glb> [--ao]
+  level_tool gltf-mapgen <config.toml> <out.gltf|out.glb> [--radius N] [--ao]
+  level_tool vox-import <in.vox> <out-level>
+  level_tool vox-export <level-file> <out.vox> [--min x,y,z --max x,y,z | --chunk-min x,y,z --chunk-max x,y,z]
+  level_tool schem-import <in.schem> <mapping.toml> <out-level>
+  level_tool biome <config.toml> <x,z>
+  level_tool bench <config.toml> [--radius N] [--spacing a,b,..] [--column-channels]";
+
+struct Args {
+    positional: Vec<String>,
+    flags: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    fn parse(raw: impl Iterator<Item = String>) -> Self {
+        let mut positional = Vec::new();
+        let mut flags = Vec::new();
+        let mut raw = raw.peekable();
+        while let Some(arg) = raw.next() {
+            if let Some(name) = arg.strip_prefix("--") {
+                let value = raw.next_if(|v| !v.starts_with("--"));
+                flags.push((name.to_string(), value));
+            } else {
+                positional.push(arg);
+            }
+        }
+        Self { positional, flags }
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.flags.iter().any(|(n, _)| n == name)
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.flags
+            .iter()
+            .find(|(n, _)| n == name)
+            .and_then(|(_, v)| v.as_deref())
+    }
+
+    fn ivec3(&self, name: &str) -> Result<Option<IVec3>> {
+        let Some(v) = self.value(name) else {
+            return Ok(None);
+        };
+        let parts: Vec<i32> = v
+            .split(',')
+            .map(|p| p.trim().parse())
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("Invalid --{name} '{v}', expected x,y,z"))?;
+        anyhow::ensure!(parts.len() == 3, "Invalid --{name} '{v}', expected x,y,z");
+        Ok(Some(IVec3::new(parts[0], parts[1], parts[2])))
+    }
+
+    fn positional(&self, index: usize, what: &str) -> Result<&str> {
+        self.positional
+            .get(index)
+            .map(String::as_str)
+            .with_context(|| format!("Missing {what}\n{USAGE}"))
+    }
+}
+
+fn main() -> Result<()> {
+    let mut raw = std::env::args().skip(1);
+    let Some(command) = raw.next() else {
+        bail!("{USAGE}");
+    };
+    let args = Args::parse(raw);
+
+    match command.as_str() {
+        "bake" => {
+            let input = args.positional(0, "mapgen config")?;
+            let output = args.positional(1, "output level file")?;
+            // Defaults match the region shown by mapgen_view.
+            let min = args.ivec3("min")?.unwrap_or(IVec3::splat(-2));
+            let max = args.ivec3("max")?.unwrap_or(IVec3::splat(2));
+            let config = MapGenConfig::load_from_file(input)?;
+            let generator = generator_from_config(config)?;
+
+            let level = match &generator.config().erosion_sim {
+                Some(erosion_sim) => {
+                    let eroded = erosion::erode_region(generator.as_ref(), erosion_sim, min, max);
+                    bake::bake_level(&eroded, min, max)?
+                }
+                None => bake::bake_level(generator.as_ref(), min, max)?,
+            };
+            level.save_to_file(output)?;
+            println!("Baked chunks {min}..={max} from {input} into {output}");
+        }
+        "gltf" => {
+            let input = args.positional(0, "level file")?;
+            let output = args.positional(1, "output path")?;
+            let mut level = LevelFile::default();
+            level.load_from_file(input)?;
+
+            let options = GltfExportOptions { bake_ao: args.has("ao") };
+            gltf::export_level(&level, options).write(output)?;
+            println!("Exported {} chunks from {} to {}", level.chunk_table.len(), input, output);
+        }
+        "gltf-mapgen" => {
+            let input = args.positional(0, "mapgen config")?;
+            let output = args.positional(1, "output path")?;
+            let radius: i32 = match args.value("radius") {
+                Some(r) => r.parse().with_context(|| format!("Invalid --radius: {r}"))?,
+                None => 2,
+            };
+            let config = MapGenConfig::load_from_file(input)?;
+            let generator = generator_from_config(config)?;
+
+            let options = GltfExportOptions { bake_ao: args.has("ao") };
+            gltf::export_generated_region(generator.as_ref(), IVec3::splat(-radius), IVec3::splat(radius), options)
+                .write(output)?;
+            println!("Exported generated region -{radius}..{radius} from {input} to {output}");
+        }
+        "vox-import" => {
+            let input = args.positional(0, ".vox file")?;
+            let output = args.positional(1, "output level file")?;
+            let scene = VoxScene::load_from_file(input)?;
+            let materials = vox::palette_to_materials(&scene.palette);
+            let level = vox::import_vox(&scene, &materials).into_level_file()?;
+            level.save_to_file(output)?;
+            println!(
+                "Imported {} models ({} instances) from {} into {} ({}x{} chunk table)",
+                scene.models.len(),
+                scene.instances.len(),
+                input,
+                output,
+                level.size(),
+                level.size()
+            );
+        }
+        "vox-export" => {
+            let input = args.positional(0, "level file")?;
+            let output = args.positional(1, "output .vox path")?;
+            let mut level = LevelFile::default();
+            level.load_from_file(input)?;
+            let volume = ChunkVolume::from_level(&level);
+
+            let cs = CS as i32;
+            let (min, max) = match (args.ivec3("min")?, args.ivec3("max")?, args.ivec3("chunk-min")?, args.ivec3("chunk-max")?) {
+                (Some(min), Some(max), None, None) => (min, max),
+                (None, None, Some(cmin), Some(cmax)) => (cmin * cs, (cmax + IVec3::ONE) * cs),
+                (None, None, None, None) => {
+                    let cmin = volume.chunk_positions().fold(IVec3::MAX, IVec3::min);
+                    let cmax = volume.chunk_positions().fold(IVec3::MIN, IVec3::max);
+                    (cmin * cs, (cmax + IVec3::ONE) * cs)
+                }
+                _ => bail!("Use either --min/--max or --chunk-min/--chunk-max\n{USAGE}"),
+            };
+            anyhow::ensure!(max.cmpgt(min).all(), "Empty export box {min}..{max}");
+
+            let scene = vox::export_region(&volume, min, max);
+            scene.save_to_file(output)?;
+            println!("Exported box {min}..{max} from {input} to {output} as {} models", scene.models.len());
+        }
+        "schem-import" => {
+            let input = args.positional(0, "schematic")?;
+            let mapping_path = args.positional(1, "block mapping")?;
+            let output = args.positional(2, "output level file")?;
+            let schem = Schematic::load_from_file(input)?;
+            let mapping = BlockMapping::load_from_file(mapping_path)?;
+
+            let import = schematic::import_schematic(&schem, &mapping);
+            if !import.unmapped.is_empty() {
+                println!("Unmapped block states (left as air):");
+                for (state, count) in &import.unmapped {
+                    println!("  {count:>8}  {state}");
+                }
+            }
+            let level = import.volume.into_level_file()?;
+            level.save_to_file(output)?;
+            println!(
+                "Imported {}x{}x{} schematic (v{}) from {} into {}",
+                schem.width, schem.height, schem.length, schem.version, input, output
+            );
+        }
+        "biome" => {
+            let input = args.positional(0, "mapgen config")?;
+            let column = args.positional(1, "x,z column")?;
+            let (x, z) = column
+                .split_once(',')
+                .and_then(|(x, z)| Some((x.trim().parse::<f32>().ok()?, z.trim().parse::<f32>().ok()?)))
+                .with_context(|| format!("Invalid column '{column}', expected x,z"))?;
+            let config = MapGenConfig::load_from_file(input)?;
+            let biomes = BiomeMap::new(&config).with_context(|| format!("{input} defines no [[biomes]]"))?;
+
+            let (temperature, humidity) = biomes.climate(x, z);
+            println!("{} at ({x}, {z}): temperature {temperature:.3}, humidity {humidity:.3}", biomes.biome_at(x, z).name);
+            for (biome, weight) in biomes.biomes().iter().zip(biomes.blend_weights(x, z)) {
+                if weight > 0.0 {
+                    println!("  {:>5.1}%  {}", weight * 100.0, biome.name);
+                }
+            }
+        }
+        "bench" => {
+            let input = args.positional(0, "mapgen config")?;
+            let radius: i32 = match args.value("radius") {
+                Some(r) => r.parse().with_context(|| format!("Invalid --radius: {r}"))?,
+                None => 1,
+            };
+            let spacings: Vec<u32> = args
+                .value("spacing")
+                .unwrap_or("4,8")
+                .split(',')
+                .map(|v| v.trim().parse())
+                .collect::<Result<_, _>>()
+                .context("Invalid --spacing, expected a list like 4,8")?;
+            let mut config = MapGenConfig::load_from_file(input)?;
+            config.sampling = None;
+            bench(input, config, radius, &spacings, args.has("column-channels"))?;
+        }
+        _ => bail!("Unknown command '{command}'\n{USAGE}"),
+    }
+
+    Ok(())
+}
+
+/// Forwards only `generate_density`, so chunks are generated one voxel at a time as
+/// without column caching; the reference for `bench`.
+struct PerVoxel<'a>(&'a dyn Generator);
+
+impl Generator for PerVoxel<'_> {
+    fn config(&self) -> &MapGenConfig {
+        self.0.config()
+    }
+
+    fn biomes(&self) -> Option<&BiomeMap> {
+        self.0.biomes()
+    }
+
+    fn generate_density(&self, wx: f32, wy: f32, wz: f32) -> f32 {
+        self.0.generate_density(wx, wy, wz)
+    }
+}
+
+/// Times chunk generation over a cube of chunks for the exact path and each sampling
+/// variant, and reports how far each variant's voxels and densities are from exact.
+fn bench(input: &str, config: MapGenConfig, radius: i32, spacings: &[u32], column_channels: bool) -> Result<()> {
+    let mut chunks = Vec::new();
+    for cz in -radius..=radius {
+        for cy in -radius..=radius {
+            for cx in -radius..=radius {
+                chunks.push(IVec3::new(cx, cy, cz));
+            }
+        }
+    }
+    let generate = |generator: &dyn Generator| {
+        let start = Instant::now();
+        let voxels: Vec<Vec<u8>> = chunks.par_iter().map(|&c| generate_chunk_voxels(c, generator).0).collect();
+        (voxels, start.elapsed().as_secs_f64() * 1000.0 / chunks.len() as f64)
+    };
+
+    let exact = generator_from_config(config.clone())?;
+    let (reference, reference_ms) = generate(&PerVoxel(exact.as_ref()));
+    let skipped = chunks
+        .iter()
+        .filter(|&&c| {
+            let (lo, hi) = chunk_density_box(c, exact.as_ref());
+            classify_box(exact.as_ref(), lo, hi) != DensityClass::Mixed
+        })
+        .count();
+    println!("{} chunks of {input}, {skipped} skipped as all air or all solid", chunks.len());
+    println!("{:<28} {:>10} {:>8} {:>10} {:>10} {:>10}", "variant", "ms/chunk", "speedup", "voxels", "rms err", "max err");
+    println!("{:<28} {reference_ms:>10.2} {:>8} {:>10} {:>10} {:>10}", "per-voxel (reference)", "1.00x", "-", "-", "-");
+
+    let mut variants = vec![("column cache".to_string(), None)];
+    let channel_options: &[bool] = if column_channels { &[false, true] } else { &[false] };
+    for &channels in channel_options {
+        let suffix = if channels { " + column channels" } else { "" };
+        if channels {
+            variants.push((format!("exact{suffix}"), Some((1, true))));
+        }
+        for &spacing in spacings {
+            variants.push((format!("lattice {spacing}{suffix}"), Some((spacing, channels))));
+        }
+    }
+
+    // Density error is measured over every voxel of the chunk at the origin
+    let lo = -IVec3::ONE;
+    let hi = lo + IVec3::splat(CS_P as i32 - 1);
+    for (name, sampling) in variants {
+        let mut variant_config = config.clone();
+        variant_config.sampling = sampling.map(|(spacing, column_channels)| SamplingConfig { spacing, column_channels });
+        let generator = generator_from_config(variant_config)?;
+        let (voxels, ms) = generate(generator.as_ref());
+
+        let total = reference.len() * reference[0].len();
+        let differing: usize = reference
+            .iter()
+            .zip(&voxels)
+            .map(|(a, b)| a.iter().zip(b).filter(|(x, y)| x != y).count())
+            .sum();
+
+        let lattice = sampling
+            .filter(|&(spacing, _)| spacing > 1)
+            .map(|(spacing, _)| DensityLattice::new(generator.as_ref(), spacing, lo, hi));
+        let (mut sum_sq, mut max_err, mut count) = (0.0f64, 0.0f32, 0usize);
+        for z in lo.z..=hi.z {
+            for y in lo.y..=hi.y {
+                for x in lo.x..=hi.x {
+                    let (fx, fy, fz) = (x as f32, y as f32, z as f32);
+                    let approx = match &lattice {
+                        Some(lattice) => lattice.sample(x, y, z),
+                        None => generator.generate_density(fx, fy, fz),
+                    };
+                    let err = (approx - exact.generate_density(fx, fy, fz)).abs();
+                    sum_sq += (err * err) as f64;
+                    max_err = max_err.max(err);
+                    count += 1;
+                }
+            }
+        }
+
+        println!(
+            "{name:<28} {ms:>10.2} {:>7.2}x {:>9.3}% {:>10.4} {max_err:>10.4}",
+            reference_ms / ms,
+            differing as f64 * 100.0 / total as f64,
+            (sum_sq / count as f64).sqrt()
+        );
+    }
+    Ok(())
+}
@@ -4,11 +4,10 @@
 
 use anyhow::{Context, Result};
 use binary_greedy_mesher_demo_rs as demo;
-use demo::mapgen::{MapGenConfig, generators::{MultiNoiseGenerator, generate_chunk_voxels}};
-use demo::mesher::{mesh, MeshData, QuadData};
+use demo::mapgen::{MapGenConfig, generators::{generate_chunk, generator_from_config, ChunkVoxels}};
+use demo::mesher::{build_opaque_mask, mesh, MeshData, QuadData};
 use demo::misc::{camera::Camera, shader::ShaderProgram};
 use demo::rendering::chunk_renderer::{ChunkRenderer, DrawElementsIndirectCommand};
-use demo::{CS, CS_P3};
 use glam::{IVec3, Vec3};
 use glutin::config::ConfigTemplateBuilder;
 use glutin::context::{ContextApi, ContextAttributesBuilder, Version};
@@ -253,7 +252,7 @@ fn main() -> Result<()> {
     let mut renderer = ChunkRenderer::new(&gl).context("create renderer")?;
 
     // --- Generate test chunks using config ---
-    let generator = MultiNoiseGenerator::new(config);
+    let generator = generator_from_config(config)?;
 
     println!("Generating procedural test chunks in range -{}..{}", CHUNK_RADIUS, CHUNK_RADIUS);
 
@@ -269,26 +268,14 @@ fn main() -> Result<()> {
     let chunk_meshes: Vec<(ChunkMesh, usize)> = chunk_positions
         .par_iter()
         .map(|&chunk_pos| {
-            let (voxels, solid_count) = generate_chunk_voxels(chunk_pos, &generator);
+            let (voxels, solid_count) = generate_chunk(chunk_pos, generator.as_ref());
+            // A single material, padding included, has no faces to mesh
+            let ChunkVoxels::Dense(voxels) = voxels else {
+                return (ChunkMesh { chunk_pos, faces: Default::default() }, solid_count);
+            };
 
             let mut mesh_data = MeshData::new(10_000);
-            mesh_data.opaque_mask.fill(0);
-
-            // Build opaque mask using bitfield (u64 per 64 voxels in XY plane)
-            // opaque_mask layout: [CS_P × CS_P] u64s, each u64 covers Z dimension
-            for ly in 0..CS + 2 {
-                for lx in 0..CS + 2 {
-                    let mut mask = 0u64;
-                    for lz in 0..CS + 2 {
-                        let idx = lz * (CS + 2) * (CS + 2) + ly * (CS + 2) + lx;
-                        if voxels[idx] != 0 {
-                            mask |= 1u64 << lz;
-                        }
-                    }
-                    let mask_idx = ly * (CS + 2) + lx;
-                    mesh_data.opaque_mask[mask_idx] = mask;
-                }
-            }
+            build_opaque_mask(&voxels, &mut mesh_data.opaque_mask);
 
             mesh(&voxels, &mut mesh_data);
 
@@ -0,0 +1,734 @@
+// glTF 2.0 export of meshed chunks (.gltf + .bin or a single .glb)
+use crate::data::level_file::LevelFile;
+use crate::data::rle;
+use crate::mapgen::generators::{generate_chunk_voxels, Generator};
+use crate::mesher::{build_opaque_mask, mesh, MeshData};
+use crate::rendering::palette::{material_color, srgb_to_linear};
+use crate::{get_zxy_index, parse_xyz_key, CS, CS_P2, CS_P3};
+use anyhow::{Context, Result};
+use glam::IVec3;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+const NORMAL_LOOKUP: [[f32; 3]; 6] = [
+    [0.0, 1.0, 0.0],
+    [0.0, -1.0, 0.0],
+    [1.0, 0.0, 0.0],
+    [-1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [0.0, 0.0, -1.0],
+];
+
+const FLIP_LOOKUP: [i32; 6] = [1, -1, -1, 1, -1, 1];
+
+// Vertex brightness by number of unoccluded neighbours (0 = fully occluded corner).
+const AO_CURVE: [f32; 4] = [0.45, 0.65, 0.85, 1.0];
+
+const COMPONENT_FLOAT: u32 = 5126;
+const COMPONENT_UNSIGNED_INT: u32 = 5125;
+const TARGET_ARRAY_BUFFER: u32 = 34962;
+const TARGET_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GltfExportOptions {
+    /// Bake per-vertex ambient occlusion into `COLOR_0`.
+    pub bake_ao: bool,
+}
+
+/// Triangles of one material inside one chunk, in chunk-local voxel units.
+#[derive(Debug, Default)]
+pub struct Primitive {
+    pub material: u8,
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub colors: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+}
+
+#[derive(Debug)]
+pub struct ChunkGeometry {
+    pub chunk_pos: IVec3,
+    pub primitives: Vec<Primitive>,
+}
+
+/// Greedy-meshes a padded chunk (`get_zxy_index` layout) and expands the quads into triangles,
+/// decoding them exactly like the viewer's vertex shader.
+pub fn mesh_chunk(chunk_pos: IVec3, voxels: &[u8], options: GltfExportOptions) -> ChunkGeometry {
+    let mut mesh_data = MeshData::new(10_000);
+    build_opaque_mask(voxels, &mut mesh_data.opaque_mask);
+    mesh(voxels, &mut mesh_data);
+
+    let mut by_material: BTreeMap<u8, Primitive> = BTreeMap::new();
+
+    for face in 0..6usize {
+        let begin = mesh_data.face_vertex_begin[face];
+        let len = mesh_data.face_vertex_length[face];
+        let normal = NORMAL_LOOKUP[face];
+        let w_dir = (face & 2) >> 1;
+        let h_dir = 2 - (face >> 2);
+
+        for quad in &mesh_data.vertices[begin..begin + len] {
+            let q = quad.quad_data1;
+            let material = (quad.quad_data2 & 255) as u8;
+            let base = [(q & 63) as i32, ((q >> 6) & 63) as i32, ((q >> 12) & 63) as i32];
+            let w = ((q >> 18) & 63) as i32;
+            let h = ((q >> 24) & 63) as i32;
+
+            let corners: [[i32; 3]; 4] = std::array::from_fn(|vertex_id| {
+                let w_mod = (vertex_id >> 1) as i32;
+                let h_mod = (vertex_id & 1) as i32;
+                let mut p = base;
+                p[w_dir] += w * w_mod * FLIP_LOOKUP[face];
+                p[h_dir] += h * h_mod;
+                p
+            });
+
+            let prim = by_material.entry(material).or_insert_with(|| Primitive {
+                material,
+                ..Default::default()
+            });
+            let first = prim.positions.len() as u32;
+
+            for (vertex_id, corner) in corners.iter().enumerate() {
+                prim.positions.push(corner.map(|c| c as f32));
+                prim.normals.push(normal);
+                if options.bake_ao {
+                    let ao = AO_CURVE[vertex_ao(voxels, face, *corner, vertex_id)];
+                    prim.colors.push([ao, ao, ao]);
+                }
+            }
+
+            // Same index pattern as the renderer's IBO, flipped where needed so that
+            // the front face points along the face normal.
+            let winding = if triangle_faces_normal(&corners, normal) {
+                [2, 0, 1, 1, 3, 2]
+            } else {
+                [1, 0, 2, 2, 3, 1]
+            };
+            prim.indices.extend(winding.iter().map(|i| first + i));
+        }
+    }
+
+    ChunkGeometry {
+        chunk_pos,
+        primitives: by_material.into_values().collect(),
+    }
+}
+
+fn triangle_faces_normal(corners: &[[i32; 3]; 4], normal: [f32; 3]) -> bool {
+    let [p0, p1, p2, _] = corners.map(|c| glam::Vec3::new(c[0] as f32, c[1] as f32, c[2] as f32));
+    (p0 - p2).cross(p1 - p2).dot(glam::Vec3::from(normal)) >= 0.0
+}
+
+/// Classic voxel AO level (0..=3) for a quad corner, sampled in the layer just outside the face.
+fn vertex_ao(voxels: &[u8], face: usize, corner: [i32; 3], vertex_id: usize) -> usize {
+    let n_dir = face / 2;
+    let w_dir = (face & 2) >> 1;
+    let h_dir = 2 - (face >> 2);
+
+    // Cell coordinate along an axis, on the side (+1 / -1) of the vertex plane.
+    let cell = |axis: usize, side: i32| if side > 0 { corner[axis] } else { corner[axis] - 1 };
+
+    let outward = if face & 1 == 0 { 1 } else { -1 };
+    // Direction from the vertex towards the quad interior along w and h.
+    let w_inward = if vertex_id >> 1 == 0 { FLIP_LOOKUP[face] } else { -FLIP_LOOKUP[face] };
+    let h_inward = if vertex_id & 1 == 0 { 1 } else { -1 };
+
+    let solid = |w_side: i32, h_side: i32| {
+        let mut p = [0i32; 3];
+        p[n_dir] = cell(n_dir, outward);
+        p[w_dir] = cell(w_dir, w_side);
+        p[h_dir] = cell(h_dir, h_side);
+        // Quad coordinates are unpadded; the padding ring covers the -1 / CS neighbours.
+        let [x, y, z] = p.map(|c| (c + 1) as usize);
+        voxels[get_zxy_index(x, y, z)] != 0
+    };
+
+    let side1 = solid(-w_inward, h_inward);
+    let side2 = solid(w_inward, -h_inward);
+    let corner_cell = solid(-w_inward, -h_inward);
+
+    if side1 && side2 {
+        0
+    } else {
+        3 - (side1 as usize + side2 as usize + corner_cell as usize)
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct Asset {
+    version: &'static str,
+    generator: &'static str,
+}
+
+#[derive(Clone, Serialize)]
+struct Scene {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    nodes: Vec<usize>,
+}
+
+#[derive(Clone, Serialize)]
+struct Node {
+    name: String,
+    mesh: usize,
+    translation: [f32; 3],
+}
+
+#[derive(Clone, Serialize)]
+struct GltfMesh {
+    name: String,
+    primitives: Vec<GltfPrimitive>,
+}
+
+#[derive(Clone, Serialize)]
+struct GltfPrimitive {
+    attributes: BTreeMap<&'static str, usize>,
+    indices: usize,
+    material: usize,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PbrMetallicRoughness {
+    base_color_factor: [f32; 4],
+    metallic_factor: f32,
+    roughness_factor: f32,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Material {
+    name: String,
+    pbr_metallic_roughness: PbrMetallicRoughness,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Accessor {
+    buffer_view: usize,
+    component_type: u32,
+    count: usize,
+    #[serde(rename = "type")]
+    ty: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min: Option<[f32; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max: Option<[f32; 3]>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BufferView {
+    buffer: usize,
+    byte_offset: usize,
+    byte_length: usize,
+    target: u32,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Buffer {
+    byte_length: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uri: Option<String>,
+}
+
+// glTF forbids empty arrays, so every list is omitted when it has no entries.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Root {
+    asset: Asset,
+    scene: usize,
+    scenes: Vec<Scene>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    nodes: Vec<Node>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    meshes: Vec<GltfMesh>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    materials: Vec<Material>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    accessors: Vec<Accessor>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    buffer_views: Vec<BufferView>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    buffers: Vec<Buffer>,
+}
+
+/// Accumulates chunk geometry into one glTF document with a single binary buffer:
+/// one node per chunk (translated to its world origin) and one primitive per material.
+pub struct GltfBuilder {
+    root: Root,
+    material_index: [Option<usize>; 256],
+    bin: Vec<u8>,
+}
+
+impl Default for GltfBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GltfBuilder {
+    pub fn new() -> Self {
+        Self {
+            root: Root {
+                asset: Asset {
+                    version: "2.0",
+                    generator: "binary_greedy_mesher_demo_rs",
+                },
+                scene: 0,
+                scenes: vec![Scene { nodes: Vec::new() }],
+                nodes: Vec::new(),
+                meshes: Vec::new(),
+                materials: Vec::new(),
+                accessors: Vec::new(),
+                buffer_views: Vec::new(),
+                buffers: Vec::new(),
+            },
+            material_index: [None; 256],
+            bin: Vec::new(),
+        }
+    }
+
+    pub fn add_chunk(&mut self, geometry: &ChunkGeometry) {
+        if geometry.primitives.iter().all(|p| p.indices.is_empty()) {
+            return;
+        }
+
+        let mut primitives = Vec::with_capacity(geometry.primitives.len());
+        for prim in geometry.primitives.iter().filter(|p| !p.indices.is_empty()) {
+            let mut attributes = BTreeMap::new();
+            attributes.insert("POSITION", self.push_vec3(&prim.positions, true));
+            attributes.insert("NORMAL", self.push_vec3(&prim.normals, false));
+            if !prim.colors.is_empty() {
+                attributes.insert("COLOR_0", self.push_vec3(&prim.colors, false));
+            }
+            let indices = self.push_indices(&prim.indices);
+            let material = self.material(prim.material);
+            primitives.push(GltfPrimitive {
+                attributes,
+                indices,
+                material,
+            });
+        }
+
+        let p = geometry.chunk_pos;
+        let name = format!("chunk_{}_{}_{}", p.x, p.y, p.z);
+        self.root.meshes.push(GltfMesh {
+            name: name.clone(),
+            primitives,
+        });
+        self.root.nodes.push(Node {
+            name,
+            mesh: self.root.meshes.len() - 1,
+            translation: (p * CS as i32).as_vec3().to_array(),
+        });
+        self.root.scenes[0].nodes.push(self.root.nodes.len() - 1);
+    }
+
+    fn material(&mut self, id: u8) -> usize {
+        if let Some(index) = self.material_index[id as usize] {
+            return index;
+        }
+        // Unknown IDs fall back to magenta so they stand out in the target engine.
+        let [r, g, b] = material_color(id).unwrap_or([1.0, 0.0, 1.0]);
+        self.root.materials.push(Material {
+            name: format!("material_{id}"),
+            pbr_metallic_roughness: PbrMetallicRoughness {
+                base_color_factor: [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), 1.0],
+                metallic_factor: 0.0,
+                roughness_factor: 1.0,
+            },
+        });
+        let index = self.root.materials.len() - 1;
+        self.material_index[id as usize] = Some(index);
+        index
+    }
+
+    fn push_view(&mut self, bytes: &[u8], target: u32) -> usize {
+        let byte_offset = self.bin.len();
+        self.bin.extend_from_slice(bytes);
+        self.root.buffer_views.push(BufferView {
+            buffer: 0,
+            byte_offset,
+            byte_length: bytes.len(),
+            target,
+        });
+        self.root.buffer_views.len() - 1
+    }
+
+    fn push_vec3(&mut self, data: &[[f32; 3]], with_bounds: bool) -> usize {
+        let bytes: Vec<u8> = data.iter().flatten().flat_map(|v| v.to_le_bytes()).collect();
+        let buffer_view = self.push_view(&bytes, TARGET_ARRAY_BUFFER);
+
+        // POSITION accessors must carry min/max.
+        let (min, max) = if with_bounds {
+            let mut min = [f32::MAX; 3];
+            let mut max = [f32::MIN; 3];
+            for v in data {
+                for i in 0..3 {
+                    min[i] = min[i].min(v[i]);
+                    max[i] = max[i].max(v[i]);
+                }
+            }
+            (Some(min), Some(max))
+        } else {
+            (None, None)
+        };
+
+        self.root.accessors.push(Accessor {
+            buffer_view,
+            component_type: COMPONENT_FLOAT,
+            count: data.len(),
+            ty: "VEC3",
+            min,
+            max,
+        });
+        self.root.accessors.len() - 1
+    }
+
+    fn push_indices(&mut self, indices: &[u32]) -> usize {
+        let bytes: Vec<u8> = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        let buffer_view = self.push_view(&bytes, TARGET_ELEMENT_ARRAY_BUFFER);
+        self.root.accessors.push(Accessor {
+            buffer_view,
+            component_type: COMPONENT_UNSIGNED_INT,
+            count: indices.len(),
+            ty: "SCALAR",
+            min: None,
+            max: None,
+        });
+        self.root.accessors.len() - 1
+    }
+
+    fn to_json(&self, buffer_uri: Option<String>) -> Result<Vec<u8>> {
+        let mut root = self.root.clone();
+        if !self.bin.is_empty() {
+            root.buffers.push(Buffer {
+                byte_length: self.bin.len(),
+                uri: buffer_uri,
+            });
+        }
+        serde_json::to_vec(&root).context("Failed to serialize glTF JSON")
+    }
+
+    /// Writes `path` (.gltf JSON) plus a sibling `.bin` with the same stem.
+    pub fn write_gltf<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let bin_path = path.with_extension("bin");
+        let bin_name = bin_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .context("glTF output path has no file name")?
+            .to_string();
+
+        let json = self.to_json(Some(bin_name))?;
+        fs::write(path, json).with_context(|| format!("Failed to write glTF file: {}", path.display()))?;
+        if !self.bin.is_empty() {
+            fs::write(&bin_path, &self.bin)
+                .with_context(|| format!("Failed to write glTF buffer: {}", bin_path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Binary glTF: 12-byte header, a JSON chunk and (if there is geometry) a BIN chunk.
+    pub fn to_glb(&self) -> Result<Vec<u8>> {
+        let mut json = self.to_json(None)?;
+        while !json.len().is_multiple_of(4) {
+            json.push(b' ');
+        }
+        let mut bin = self.bin.clone();
+        while !bin.len().is_multiple_of(4) {
+            bin.push(0);
+        }
+
+        let mut total = 12 + 8 + json.len();
+        if !bin.is_empty() {
+            total += 8 + bin.len();
+        }
+
+        let mut out = Vec::with_capacity(total);
+        out.extend_from_slice(&GLB_MAGIC.to_le_bytes());
+        out.extend_from_slice(&2u32.to_le_bytes());
+        out.extend_from_slice(&(total as u32).to_le_bytes());
+        out.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        out.extend_from_slice(&GLB_CHUNK_JSON.to_le_bytes());
+        out.extend_from_slice(&json);
+        if !bin.is_empty() {
+            out.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+            out.extend_from_slice(&GLB_CHUNK_BIN.to_le_bytes());
+            out.extend_from_slice(&bin);
+        }
+        Ok(out)
+    }
+
+    pub fn write_glb<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let bytes = self.to_glb()?;
+        fs::write(path.as_ref(), bytes)
+            .with_context(|| format!("Failed to write GLB file: {}", path.as_ref().display()))
+    }
+
+    /// Picks .glb or .gltf + .bin from the file extension.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let is_glb = path
+            .as_ref()
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("glb"));
+        if is_glb {
+            self.write_glb(path)
+        } else {
+            self.write_gltf(path)
+        }
+    }
+}
+
+/// Meshes every chunk of a level file (in parallel) into one glTF document.
+pub fn export_level(level: &LevelFile, options: GltfExportOptions) -> GltfBuilder {
+    let geometry: Vec<ChunkGeometry> = level
+        .chunk_table
+        .par_iter()
+        .map(|entry| {
+            let (x, y, z) = parse_xyz_key(entry.key);
+            let mut voxels = vec![0u8; CS_P3];
+            let mut opaque_mask = vec![0u64; CS_P2];
+            rle::decompress_to_voxels_and_opaque_mask(level.chunk_rle(entry), &mut voxels, &mut opaque_mask);
+            mesh_chunk(IVec3::new(x as i32, y as i32, z as i32), &voxels, options)
+        })
+        .collect();
+
+    let mut builder = GltfBuilder::new();
+    for chunk in &geometry {
+        builder.add_chunk(chunk);
+    }
+    builder
+}
+
+/// Generates and meshes the chunks in `min..=max` (chunk coordinates) into one glTF document.
+pub fn export_generated_region<G: Generator + ?Sized>(
+    generator: &G,
+    min: IVec3,
+    max: IVec3,
+    options: GltfExportOptions,
+) -> GltfBuilder {
+    let mut chunk_positions = Vec::new();
+    for cz in min.z..=max.z {
+        for cy in min.y..=max.y {
+            for cx in min.x..=max.x {
+                chunk_positions.push(IVec3::new(cx, cy, cz));
+            }
+        }
+    }
+
+    let geometry: Vec<ChunkGeometry> = chunk_positions
+        .par_iter()
+        .map(|&chunk_pos| {
+            let (voxels, _) = generate_chunk_voxels(chunk_pos, generator);
+            mesh_chunk(chunk_pos, &voxels, options)
+        })
+        .collect();
+
+    let mut builder = GltfBuilder::new();
+    for chunk in &geometry {
+        builder.add_chunk(chunk);
+    }
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_xyz_key;
+    use crate::mapgen::generators::ChunkVoxels;
+    use serde_json::Value;
+
+    // Chunk (0, 0, 0) holds a 2x2x2 cube of material 1 and a single voxel of material 2,
+    // chunk (1, 0, 0) a single voxel of material 3; `from_chunks` pads the table with air.
+    fn small_level() -> LevelFile {
+        let mut a = vec![0u8; CS_P3];
+        for z in 1..=2 {
+            for y in 1..=2 {
+                for x in 1..=2 {
+                    a[get_zxy_index(x, y, z)] = 1;
+                }
+            }
+        }
+        a[get_zxy_index(10, 10, 10)] = 2;
+        let mut b = vec![0u8; CS_P3];
+        b[get_zxy_index(5, 1, 5)] = 3;
+        LevelFile::from_chunks(&[(get_xyz_key(0, 0, 0), ChunkVoxels::Dense(a)), (get_xyz_key(1, 0, 0), ChunkVoxels::Dense(b))])
+            .unwrap()
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn usize_of(value: &Value) -> usize {
+        value.as_u64().unwrap() as usize
+    }
+
+    // Checks the document against the buffer it points into and returns the POSITION
+    // extent (max - min) of each material's primitive, keyed by material name
+    fn check_document(doc: &Value, bin: &[u8]) -> BTreeMap<String, [f32; 3]> {
+        assert_eq!(doc["asset"]["version"], "2.0");
+        let buffers = doc["buffers"].as_array().unwrap();
+        assert_eq!(buffers.len(), 1);
+        assert_eq!(usize_of(&buffers[0]["byteLength"]), bin.len());
+
+        // Views are packed back to back and fill the buffer
+        let views = doc["bufferViews"].as_array().unwrap();
+        let mut end = 0;
+        for view in views {
+            assert_eq!(usize_of(&view["buffer"]), 0);
+            assert_eq!(usize_of(&view["byteOffset"]), end);
+            assert_eq!(usize_of(&view["byteOffset"]) % 4, 0);
+            end += usize_of(&view["byteLength"]);
+        }
+        assert_eq!(end, bin.len());
+
+        let accessors = doc["accessors"].as_array().unwrap();
+        let floats = |index: usize| -> Vec<f32> {
+            let view = &views[usize_of(&accessors[index]["bufferView"])];
+            let start = usize_of(&view["byteOffset"]);
+            bin[start..start + usize_of(&view["byteLength"])]
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect()
+        };
+        for accessor in accessors {
+            let components = match accessor["type"].as_str().unwrap() {
+                "VEC3" => 3,
+                "SCALAR" => 1,
+                ty => panic!("unexpected accessor type {ty}"),
+            };
+            let view = &views[usize_of(&accessor["bufferView"])];
+            assert_eq!(usize_of(&accessor["count"]) * components * 4, usize_of(&view["byteLength"]));
+        }
+
+        let materials = doc["materials"].as_array().unwrap();
+        let mut extents = BTreeMap::new();
+        for mesh in doc["meshes"].as_array().unwrap() {
+            let primitives = mesh["primitives"].as_array().unwrap();
+            let mut used = Vec::new();
+            for primitive in primitives {
+                let material = usize_of(&primitive["material"]);
+                assert!(!used.contains(&material), "two primitives of material {material} in {}", mesh["name"]);
+                used.push(material);
+
+                let position = usize_of(&primitive["attributes"]["POSITION"]);
+                let indices = usize_of(&primitive["indices"]);
+                let count = usize_of(&accessors[position]["count"]);
+                assert_eq!(usize_of(&accessors[usize_of(&primitive["attributes"]["NORMAL"])]["count"]), count);
+                assert_eq!(accessors[indices]["componentType"], COMPONENT_UNSIGNED_INT);
+                let view = &views[usize_of(&accessors[indices]["bufferView"])];
+                let start = usize_of(&view["byteOffset"]);
+                assert!(
+                    bin[start..start + usize_of(&view["byteLength"])]
+                        .chunks_exact(4)
+                        .all(|b| (u32::from_le_bytes(b.try_into().unwrap()) as usize) < count)
+                );
+
+                // min / max are the exact bounds of the vertex data
+                let data = floats(position);
+                let (mut min, mut max) = ([f32::MAX; 3], [f32::MIN; 3]);
+                for v in data.chunks_exact(3) {
+                    for i in 0..3 {
+                        min[i] = min[i].min(v[i]);
+                        max[i] = max[i].max(v[i]);
+                    }
+                }
+                let as_vec3 = |v: &Value| -> [f32; 3] { std::array::from_fn(|i| v[i].as_f64().unwrap() as f32) };
+                assert_eq!(as_vec3(&accessors[position]["min"]), min);
+                assert_eq!(as_vec3(&accessors[position]["max"]), max);
+                let name = materials[material]["name"].as_str().unwrap().to_string();
+                extents.insert(name, std::array::from_fn(|i| max[i] - min[i]));
+            }
+        }
+        extents
+    }
+
+    fn check_scene(doc: &Value) {
+        // One node per non-empty chunk, at the chunk's world origin
+        let nodes = doc["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(doc["scenes"][0]["nodes"], serde_json::json!([0, 1]));
+        let mut translations: Vec<(String, Value)> =
+            nodes.iter().map(|n| (n["name"].as_str().unwrap().to_string(), n["translation"].clone())).collect();
+        translations.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            translations,
+            vec![
+                ("chunk_0_0_0".to_string(), serde_json::json!([0.0, 0.0, 0.0])),
+                ("chunk_1_0_0".to_string(), serde_json::json!([CS as f32, 0.0, 0.0])),
+            ]
+        );
+        for node in nodes {
+            let mesh = &doc["meshes"][usize_of(&node["mesh"])];
+            let expected = if node["name"] == "chunk_0_0_0" { 2 } else { 1 };
+            assert_eq!(mesh["primitives"].as_array().unwrap().len(), expected);
+        }
+    }
+
+    fn check_extents(extents: BTreeMap<String, [f32; 3]>) {
+        assert_eq!(
+            extents,
+            BTreeMap::from([
+                ("material_1".to_string(), [2.0; 3]),
+                ("material_2".to_string(), [1.0; 3]),
+                ("material_3".to_string(), [1.0; 3]),
+            ])
+        );
+    }
+
+    #[test]
+    fn gltf_and_bin_are_consistent() {
+        let dir = std::env::temp_dir().join(format!("gltf_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("level.gltf");
+        export_level(&small_level(), GltfExportOptions::default()).write(&path).unwrap();
+
+        let doc: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        let bin = fs::read(dir.join("level.bin")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(doc["buffers"][0]["uri"], "level.bin");
+        check_extents(check_document(&doc, &bin));
+        check_scene(&doc);
+    }
+
+    #[test]
+    fn glb_has_aligned_chunks() {
+        let glb = export_level(&small_level(), GltfExportOptions { bake_ao: true }).to_glb().unwrap();
+
+        assert_eq!(u32_at(&glb, 0), GLB_MAGIC);
+        assert_eq!(u32_at(&glb, 4), 2);
+        assert_eq!(u32_at(&glb, 8) as usize, glb.len());
+
+        let json_len = u32_at(&glb, 12) as usize;
+        assert_eq!(u32_at(&glb, 16), GLB_CHUNK_JSON);
+        assert_eq!(json_len % 4, 0);
+        let doc: Value = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+
+        let bin_header = 20 + json_len;
+        let bin_len = u32_at(&glb, bin_header) as usize;
+        assert_eq!(u32_at(&glb, bin_header + 4), GLB_CHUNK_BIN);
+        assert_eq!(bin_len % 4, 0);
+        assert_eq!(bin_header + 8 + bin_len, glb.len());
+
+        // The embedded buffer has no URI and may be followed by chunk padding
+        assert!(doc["buffers"][0].get("uri").is_none());
+        let byte_length = usize_of(&doc["buffers"][0]["byteLength"]);
+        assert!(byte_length <= bin_len && bin_len - byte_length < 4);
+        let bin = &glb[bin_header + 8..bin_header + 8 + byte_length];
+        check_extents(check_document(&doc, bin));
+        check_scene(&doc);
+        assert!(doc["meshes"][0]["primitives"][0]["attributes"].get("COLOR_0").is_some());
+    }
+}
@@ -1,5 +1,9 @@
+use crate::data::rle;
+use crate::mapgen::generators::ChunkVoxels;
+use crate::{get_xyz_key, parse_xyz_key, CS_P3};
 use anyhow::{Context, Result};
 use bytemuck::{Pod, Zeroable};
+use std::collections::{HashMap, HashSet};
 use std::{fs, path::Path};
 
 #[repr(C)]
@@ -22,6 +26,13 @@ impl LevelFile {
         self.size
     }
 
+    /// RLE bytes of one chunk, ready for `rle::decompress_to_voxels_and_opaque_mask`.
+    pub fn chunk_rle(&self, entry: &ChunkTableEntry) -> &[u8] {
+        let start = entry.rle_data_begin as usize;
+        let end = start + entry.rle_data_size as usize;
+        &self.buffer[start..end]
+    }
+
     pub fn load_from_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
         let bytes = fs::read(&path).with_context(|| format!("Failed to read level file: {}", path.as_ref().display()))?;
         anyhow::ensure!(!bytes.is_empty(), "Level file is empty");
@@ -52,4 +63,101 @@ impl LevelFile {
         self.buffer = bytes;
         Ok(())
     }
+
+    /// Builds a level from padded chunks (`get_zxy_index` layout) keyed by `get_xyz_key`.
+    ///
+    /// The format stores exactly `size * size` table entries, so `size` is the smallest square
+    /// that covers every chunk's X/Z and has room for all of them; unused `(x, 0, z)` slots are
+    /// filled with a shared all-air chunk. `ChunkVoxels::Uniform` chunks of the same material
+    /// share one stored copy.
+    pub fn from_chunks(chunks: &[(u32, ChunkVoxels)]) -> Result<Self> {
+        let extent = chunks
+            .iter()
+            .map(|(key, _)| {
+                let (x, _, z) = parse_xyz_key(*key);
+                x.max(z) as usize + 1
+            })
+            .max()
+            .unwrap_or(1);
+        let mut size = extent;
+        while size * size < chunks.len() {
+            size += 1;
+        }
+        anyhow::ensure!(size <= u8::MAX as usize, "Level is too large ({size} chunks across)");
+
+        let table_len = size * size;
+        let mut rle_blobs: Vec<Vec<u8>> = Vec::with_capacity(chunks.len() + 1);
+        let mut keys = Vec::with_capacity(table_len);
+        let mut used = HashSet::new();
+        // Blob index of each material's single-material chunk
+        let mut uniform = HashMap::new();
+        let mut push_uniform = |material: u8, rle_blobs: &mut Vec<Vec<u8>>| {
+            *uniform.entry(material).or_insert_with(|| {
+                rle_blobs.push(rle::compress(&vec![material; CS_P3]));
+                rle_blobs.len() - 1
+            })
+        };
+        for &(key, ref voxels) in chunks {
+            let (x, y, z) = parse_xyz_key(key);
+            anyhow::ensure!(used.insert(key), "Duplicate chunk ({x}, {y}, {z})");
+            match voxels {
+                ChunkVoxels::Uniform(material) => keys.push((key, push_uniform(*material, &mut rle_blobs))),
+                ChunkVoxels::Dense(voxels) => {
+                    anyhow::ensure!(voxels.len() == CS_P3, "Chunk ({x}, {y}, {z}) is not a padded CS_P3 buffer");
+                    keys.push((key, rle_blobs.len()));
+                    rle_blobs.push(rle::compress(voxels));
+                }
+            }
+        }
+
+        if keys.len() < table_len {
+            let empty = push_uniform(0, &mut rle_blobs);
+            'fill: for z in 0..size {
+                for x in 0..size {
+                    if keys.len() == table_len {
+                        break 'fill;
+                    }
+                    let key = get_xyz_key(x as u8, 0, z as u8);
+                    if used.insert(key) {
+                        keys.push((key, empty));
+                    }
+                }
+            }
+        }
+
+        let table_bytes = table_len * std::mem::size_of::<ChunkTableEntry>();
+        let mut offsets = Vec::with_capacity(rle_blobs.len());
+        let mut buffer = Vec::with_capacity(1 + table_bytes + rle_blobs.iter().map(Vec::len).sum::<usize>());
+        buffer.push(size as u8);
+        buffer.resize(1 + table_bytes, 0);
+        for blob in &rle_blobs {
+            offsets.push(buffer.len() as u32);
+            buffer.extend_from_slice(blob);
+        }
+
+        let mut chunk_table = Vec::with_capacity(table_len);
+        for (i, &(key, blob)) in keys.iter().enumerate() {
+            let entry = ChunkTableEntry {
+                key,
+                rle_data_begin: offsets[blob],
+                rle_data_size: rle_blobs[blob].len() as u32,
+            };
+            let base = 1 + i * 12;
+            buffer[base..base + 4].copy_from_slice(&entry.key.to_le_bytes());
+            buffer[base + 4..base + 8].copy_from_slice(&entry.rle_data_begin.to_le_bytes());
+            buffer[base + 8..base + 12].copy_from_slice(&entry.rle_data_size.to_le_bytes());
+            chunk_table.push(entry);
+        }
+
+        Ok(Self {
+            chunk_table,
+            buffer,
+            size: size as u8,
+        })
+    }
+
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path.as_ref(), &self.buffer)
+            .with_context(|| format!("Failed to write level file: {}", path.as_ref().display()))
+    }
 }
@@ -1,2 +1,7 @@
+pub mod gltf;
 pub mod level_file;
+pub mod nbt;
 pub mod rle;
+pub mod schematic;
+pub mod volume;
+pub mod vox;
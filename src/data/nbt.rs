@@ -0,0 +1,167 @@
+// Minecraft NBT (Named Binary Tag) reader: big-endian, optionally gzip-compressed
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use std::collections::HashMap;
+use std::io::Read;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(map) => map.get(name),
+            _ => None,
+        }
+    }
+
+    /// Any integer tag widened to i64.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(v) => Some(v as i64),
+            Tag::Short(v) => Some(v as i64),
+            Tag::Int(v) => Some(v as i64),
+            Tag::Long(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&HashMap<String, Tag>> {
+        match self {
+            Tag::Compound(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    pub fn as_byte_array(&self) -> Option<&[i8]> {
+        match self {
+            Tag::ByteArray(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+/// Reads a root compound, returning its name and payload. Gzip input is detected by magic.
+pub fn read(bytes: &[u8]) -> Result<(String, Tag)> {
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = Vec::new();
+        GzDecoder::new(bytes)
+            .read_to_end(&mut decompressed)
+            .context("Failed to decompress gzip NBT data")?;
+        return read_uncompressed(&decompressed);
+    }
+    read_uncompressed(bytes)
+}
+
+fn read_uncompressed(bytes: &[u8]) -> Result<(String, Tag)> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let ty = reader.u8()?;
+    anyhow::ensure!(ty == 10, "NBT root must be a compound tag, found tag type {ty}");
+    let name = reader.string()?;
+    let tag = reader.payload(ty, 0)?;
+    Ok((name, tag))
+}
+
+// Deeply nested data is either corrupt or hostile; real files stay far below this.
+const MAX_DEPTH: usize = 512;
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        anyhow::ensure!(self.bytes.len() - self.pos >= n, "Unexpected end of NBT data at byte {}", self.pos);
+        let out = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        let len = i32::from_be_bytes(self.array()?);
+        anyhow::ensure!(len >= 0, "Negative NBT length {len} at byte {}", self.pos);
+        Ok(len as usize)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = u16::from_be_bytes(self.array()?) as usize;
+        // NBT uses Java's modified UTF-8; lossy decoding is fine for block state names.
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn payload(&mut self, ty: u8, depth: usize) -> Result<Tag> {
+        anyhow::ensure!(depth < MAX_DEPTH, "NBT nesting is too deep");
+        Ok(match ty {
+            1 => Tag::Byte(self.u8()? as i8),
+            2 => Tag::Short(i16::from_be_bytes(self.array()?)),
+            3 => Tag::Int(i32::from_be_bytes(self.array()?)),
+            4 => Tag::Long(i64::from_be_bytes(self.array()?)),
+            5 => Tag::Float(f32::from_be_bytes(self.array()?)),
+            6 => Tag::Double(f64::from_be_bytes(self.array()?)),
+            7 => {
+                let len = self.len()?;
+                Tag::ByteArray(self.take(len)?.iter().map(|&b| b as i8).collect())
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let item_ty = self.u8()?;
+                let len = self.len()?;
+                if item_ty == 0 {
+                    // Empty lists are stored with the End type.
+                    Tag::List(Vec::new())
+                } else {
+                    let items = (0..len)
+                        .map(|_| self.payload(item_ty, depth + 1))
+                        .collect::<Result<_>>()?;
+                    Tag::List(items)
+                }
+            }
+            10 => {
+                let mut map = HashMap::new();
+                loop {
+                    let child_ty = self.u8()?;
+                    if child_ty == 0 {
+                        break;
+                    }
+                    let name = self.string()?;
+                    let value = self.payload(child_ty, depth + 1)?;
+                    map.insert(name, value);
+                }
+                Tag::Compound(map)
+            }
+            11 => {
+                let len = self.len()?;
+                let data = self.take(len * 4)?;
+                Tag::IntArray(data.chunks_exact(4).map(|c| i32::from_be_bytes(c.try_into().unwrap())).collect())
+            }
+            12 => {
+                let len = self.len()?;
+                let data = self.take(len * 8)?;
+                Tag::LongArray(data.chunks_exact(8).map(|c| i64::from_be_bytes(c.try_into().unwrap())).collect())
+            }
+            _ => bail!("Unknown NBT tag type {ty} at byte {}", self.pos),
+        })
+    }
+}
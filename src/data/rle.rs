@@ -68,3 +68,20 @@ pub fn decompress_to_voxels_and_opaque_mask(rle: &[u8], voxels: &mut [u8], opaqu
         u_i += len;
     }
 }
+
+/// Inverse of `decompress_to_voxels_and_opaque_mask`: `(type, len)` byte pairs, runs capped at 255.
+pub fn compress(voxels: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < voxels.len() {
+        let ty = voxels[i];
+        let mut len = 1;
+        while len < 255 && i + len < voxels.len() && voxels[i + len] == ty {
+            len += 1;
+        }
+        out.push(ty);
+        out.push(len as u8);
+        i += len;
+    }
+    out
+}
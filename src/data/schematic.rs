@@ -0,0 +1,177 @@
+// Sponge schematic (.schem, versions 2 and 3) import
+// Spec: https://github.com/SpongePowered/Schematic-Specification
+use crate::data::nbt::{self, Tag};
+use crate::data::volume::ChunkVolume;
+use anyhow::{bail, Context, Result};
+use glam::IVec3;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// Block state name -> material ID table, loaded from TOML:
+///
+/// ```toml
+/// [blocks]
+/// "minecraft:stone" = 2
+/// "minecraft:grass_block[snowy=true]" = 5
+/// ```
+///
+/// Full block states are matched first, then the bare block name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlockMapping {
+    pub blocks: HashMap<String, u8>,
+}
+
+impl BlockMapping {
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let contents = fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read block mapping: {:?}", path.as_ref()))?;
+        toml::from_str(&contents).with_context(|| format!("Failed to parse block mapping: {:?}", path.as_ref()))
+    }
+
+    /// `Some(0)` for air, `None` when the state is not covered by the table.
+    pub fn material(&self, state: &str) -> Option<u8> {
+        let name = state.split('[').next().unwrap_or(state);
+        if matches!(name, "minecraft:air" | "minecraft:cave_air" | "minecraft:void_air") {
+            return Some(0);
+        }
+        self.blocks.get(state).or_else(|| self.blocks.get(name)).copied()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Schematic {
+    pub version: i64,
+    pub width: u32,
+    pub height: u32,
+    pub length: u32,
+    /// Block state names by palette index.
+    pub palette: Vec<String>,
+    /// Palette indices in `x + z * width + y * width * length` order.
+    pub blocks: Vec<u32>,
+}
+
+fn dimension(root: &Tag, name: &str) -> Result<u32> {
+    let v = root
+        .get(name)
+        .and_then(Tag::as_i64)
+        .with_context(|| format!("Schematic is missing '{name}'"))?;
+    // Stored as a signed short but meant to be unsigned.
+    Ok((v as u16) as u32)
+}
+
+fn decode_varints(data: &[i8], expected: usize) -> Result<Vec<u32>> {
+    // Every entry takes at least one byte, so a bogus size can't reserve more than the data
+    let mut out = Vec::with_capacity(expected.min(data.len()));
+    let mut value = 0u32;
+    let mut shift = 0;
+    for &b in data {
+        let b = b as u8;
+        value |= ((b & 0x7f) as u32) << shift;
+        if b & 0x80 == 0 {
+            out.push(value);
+            value = 0;
+            shift = 0;
+        } else {
+            shift += 7;
+            anyhow::ensure!(shift < 32, "Block data varint is too long");
+        }
+    }
+    anyhow::ensure!(
+        out.len() == expected,
+        "Block data has {} entries, expected {expected}",
+        out.len()
+    );
+    Ok(out)
+}
+
+impl Schematic {
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let bytes = fs::read(path.as_ref())
+            .with_context(|| format!("Failed to read schematic: {}", path.as_ref().display()))?;
+        let (_, root) = nbt::read(&bytes)?;
+        Self::from_nbt(&root).with_context(|| format!("Failed to parse schematic: {}", path.as_ref().display()))
+    }
+
+    /// Version 3 wraps everything in a `Schematic` compound and moves the palette and data into
+    /// `Blocks`; version 2 keeps `Palette` / `BlockData` at the root.
+    pub fn from_nbt(root: &Tag) -> Result<Self> {
+        let root = root.get("Schematic").unwrap_or(root);
+        let version = root.get("Version").and_then(Tag::as_i64).context("Schematic is missing 'Version'")?;
+
+        let (palette_tag, data_tag) = match version {
+            2 => (root.get("Palette"), root.get("BlockData")),
+            3 => {
+                let blocks = root.get("Blocks").context("Schematic v3 is missing 'Blocks'")?;
+                (blocks.get("Palette"), blocks.get("Data"))
+            }
+            _ => bail!("Unsupported Sponge schematic version {version} (expected 2 or 3)"),
+        };
+
+        let width = dimension(root, "Width")?;
+        let height = dimension(root, "Height")?;
+        let length = dimension(root, "Length")?;
+
+        let palette_map = palette_tag
+            .and_then(Tag::as_compound)
+            .context("Schematic is missing its block palette")?;
+        let mut palette = vec![String::new(); palette_map.len()];
+        for (state, index) in palette_map {
+            let index = index
+                .as_i64()
+                .with_context(|| format!("Palette entry '{state}' is not an integer"))?;
+            let slot = usize::try_from(index)
+                .ok()
+                .filter(|&i| i < palette.len())
+                .with_context(|| format!("Palette index {index} for '{state}' is out of range"))?;
+            palette[slot] = state.clone();
+        }
+
+        let data = data_tag
+            .and_then(Tag::as_byte_array)
+            .context("Schematic is missing its block data")?;
+        let volume = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|v| v.checked_mul(length as usize))
+            .with_context(|| format!("Schematic size {width}x{height}x{length} is too large"))?;
+        let blocks = decode_varints(data, volume)?;
+        if let Some(bad) = blocks.iter().find(|&&b| b as usize >= palette.len()) {
+            bail!("Block data references palette index {bad}, palette has {} entries", palette.len());
+        }
+
+        Ok(Self {
+            version,
+            width,
+            height,
+            length,
+            palette,
+            blocks,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct SchematicImport {
+    pub volume: ChunkVolume,
+    /// Block states that had no mapping, with how many blocks used them. These blocks are left as air.
+    pub unmapped: BTreeMap<String, usize>,
+}
+
+/// Places the schematic with its minimum corner at the origin (Minecraft is already Y-up).
+pub fn import_schematic(schematic: &Schematic, mapping: &BlockMapping) -> SchematicImport {
+    let materials: Vec<Option<u8>> = schematic.palette.iter().map(|s| mapping.material(s)).collect();
+    let mut unmapped = BTreeMap::new();
+    let mut volume = ChunkVolume::new();
+
+    let (w, l) = (schematic.width as usize, schematic.length as usize);
+    for (i, &block) in schematic.blocks.iter().enumerate() {
+        let pos = IVec3::new((i % w) as i32, (i / (w * l)) as i32, ((i / w) % l) as i32);
+        match materials[block as usize] {
+            Some(material) => volume.set(pos, material),
+            None => *unmapped.entry(schematic.palette[block as usize].clone()).or_insert(0) += 1,
+        }
+    }
+
+    SchematicImport { volume, unmapped }
+}
@@ -0,0 +1,114 @@
+// Sparse world-space voxel storage that assembles padded chunks for meshing and level files
+use crate::data::level_file::LevelFile;
+use crate::data::rle;
+use crate::mapgen::generators::ChunkVoxels;
+use crate::{get_xyz_key, get_zxy_index, parse_xyz_key, CS, CS_P, CS_P2, CS_P3};
+use anyhow::Result;
+use glam::IVec3;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// Chunks are stored as padded `CS_P3` buffers (`get_zxy_index` layout), but only the
+/// interior is authoritative; the padding ring is rebuilt from neighbours on export.
+#[derive(Debug, Default, Clone)]
+pub struct ChunkVolume {
+    chunks: HashMap<IVec3, Vec<u8>>,
+}
+
+fn split(pos: IVec3) -> (IVec3, usize) {
+    let cs = CS as i32;
+    let chunk = pos.div_euclid(IVec3::splat(cs));
+    let local = pos.rem_euclid(IVec3::splat(cs)) + IVec3::ONE;
+    (chunk, get_zxy_index(local.x as usize, local.y as usize, local.z as usize))
+}
+
+impl ChunkVolume {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads every chunk of a level file; coordinates are the chunk keys times `CS`.
+    pub fn from_level(level: &LevelFile) -> Self {
+        let chunks = level
+            .chunk_table
+            .par_iter()
+            .map(|entry| {
+                let (x, y, z) = parse_xyz_key(entry.key);
+                let mut voxels = vec![0u8; CS_P3];
+                let mut opaque_mask = vec![0u64; CS_P2];
+                rle::decompress_to_voxels_and_opaque_mask(level.chunk_rle(entry), &mut voxels, &mut opaque_mask);
+                (IVec3::new(x as i32, y as i32, z as i32), voxels)
+            })
+            .collect();
+        Self { chunks }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn chunk_positions(&self) -> impl Iterator<Item = IVec3> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    pub fn get(&self, pos: IVec3) -> u8 {
+        let (chunk, index) = split(pos);
+        self.chunks.get(&chunk).map_or(0, |voxels| voxels[index])
+    }
+
+    /// Setting air never allocates a chunk.
+    pub fn set(&mut self, pos: IVec3, material: u8) {
+        let (chunk, index) = split(pos);
+        if material == 0 {
+            if let Some(voxels) = self.chunks.get_mut(&chunk) {
+                voxels[index] = 0;
+            }
+        } else {
+            self.chunks.entry(chunk).or_insert_with(|| vec![0u8; CS_P3])[index] = material;
+        }
+    }
+
+    /// Padded chunks ready for `mesh`, with the padding ring copied from neighbouring chunks.
+    /// Sorted by chunk position so output is deterministic.
+    pub fn into_padded_chunks(self) -> Vec<(IVec3, Vec<u8>)> {
+        let mut out: Vec<(IVec3, Vec<u8>)> = self
+            .chunks
+            .par_iter()
+            .map(|(&chunk, interior)| {
+                let mut voxels = interior.clone();
+                let origin = chunk * CS as i32 - IVec3::ONE;
+                for y in 0..CS_P {
+                    for x in 0..CS_P {
+                        let edge_yx = y == 0 || y == CS_P - 1 || x == 0 || x == CS_P - 1;
+                        for z in 0..CS_P {
+                            if !edge_yx && z != 0 && z != CS_P - 1 {
+                                continue;
+                            }
+                            let world = origin + IVec3::new(x as i32, y as i32, z as i32);
+                            voxels[get_zxy_index(x, y, z)] = self.get(world);
+                        }
+                    }
+                }
+                (chunk, voxels)
+            })
+            .collect();
+        out.sort_by_key(|(c, _)| (c.y, c.z, c.x));
+        out
+    }
+
+    /// Chunk coordinates must fit the level format's `u8` keys.
+    pub fn into_level_file(self) -> Result<LevelFile> {
+        let mut chunks = Vec::with_capacity(self.chunks.len());
+        for (c, voxels) in self.into_padded_chunks() {
+            anyhow::ensure!(
+                c.cmpge(IVec3::ZERO).all() && c.cmple(IVec3::splat(u8::MAX as i32)).all(),
+                "Chunk ({}, {}, {}) is outside the level file's 0..=255 chunk range",
+                c.x,
+                c.y,
+                c.z
+            );
+            chunks.push((get_xyz_key(c.x as u8, c.y as u8, c.z as u8), ChunkVoxels::from_dense(voxels)));
+        }
+        LevelFile::from_chunks(&chunks)
+    }
+}
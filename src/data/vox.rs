@@ -0,0 +1,643 @@
+// MagicaVoxel .vox import and export
+// Spec: https://github.com/ephtracy/voxel-model/blob/master/MagicaVoxel-file-format-vox.txt
+use crate::data::volume::ChunkVolume;
+use crate::rendering::palette::{material_color, COLOR_LOOKUP};
+use anyhow::{bail, Context, Result};
+use glam::IVec3;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// One `SIZE` + `XYZI` pair. Coordinates are MagicaVoxel's (Z up), `[x, y, z, color_index]`.
+#[derive(Debug, Clone)]
+pub struct VoxModel {
+    pub size: [u32; 3],
+    pub voxels: Vec<[u8; 4]>,
+}
+
+/// Row-major signed permutation matrix.
+pub type VoxRotation = [[i32; 3]; 3];
+
+pub const VOX_IDENTITY: VoxRotation = [[1, 0, 0], [0, 1, 0], [0, 0, 1]];
+
+/// A model placed by the scene graph (accumulated `nTRN` transforms, MagicaVoxel space).
+/// World position of a model voxel is `rotation * (v - pivot) + translation`.
+#[derive(Debug, Clone, Copy)]
+pub struct VoxInstance {
+    pub model: usize,
+    pub rotation: VoxRotation,
+    pub translation: IVec3,
+    pub pivot: IVec3,
+}
+
+pub fn rotate(m: &VoxRotation, v: IVec3) -> IVec3 {
+    let row = |r: [i32; 3]| r[0] * v.x + r[1] * v.y + r[2] * v.z;
+    IVec3::new(row(m[0]), row(m[1]), row(m[2]))
+}
+
+fn mul(a: &VoxRotation, b: &VoxRotation) -> VoxRotation {
+    std::array::from_fn(|i| std::array::from_fn(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+#[derive(Debug, Clone)]
+pub struct VoxScene {
+    pub models: Vec<VoxModel>,
+    /// RGBA by color index; index 0 is unused (empty voxel).
+    pub palette: [[u8; 4]; 256],
+    pub instances: Vec<VoxInstance>,
+}
+
+enum SceneNode {
+    Transform { child: i32, rotation: VoxRotation, translation: IVec3 },
+    Group { children: Vec<i32> },
+    Shape { models: Vec<i32> },
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        anyhow::ensure!(self.remaining() >= n, "Unexpected end of .vox data at byte {}", self.pos);
+        let out = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(out)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn dict(&mut self) -> Result<HashMap<String, String>> {
+        let count = self.u32()?;
+        let mut dict = HashMap::new();
+        for _ in 0..count {
+            let key = self.string()?;
+            let value = self.string()?;
+            dict.insert(key, value);
+        }
+        Ok(dict)
+    }
+}
+
+/// Palette used when a file has no `RGBA` chunk: a 6x6x6 color cube without black,
+/// followed by red, green, blue and gray ramps.
+pub fn default_palette() -> [[u8; 4]; 256] {
+    let mut palette = [[0u8; 4]; 256];
+    let cube = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    let ramp = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let mut i = 1;
+    for r in cube {
+        for g in cube {
+            for b in cube {
+                if r == 0 && g == 0 && b == 0 {
+                    continue;
+                }
+                palette[i] = [r, g, b, 0xff];
+                i += 1;
+            }
+        }
+    }
+    for channel in 0..4 {
+        for v in ramp {
+            palette[i] = match channel {
+                0 => [v, 0, 0, 0xff],
+                1 => [0, v, 0, 0xff],
+                2 => [0, 0, v, 0xff],
+                _ => [v, v, v, 0xff],
+            };
+            i += 1;
+        }
+    }
+    palette
+}
+
+/// Palette written by `export_region`: color index `n` is material `n`. IDs without a
+/// `colorLookup` entry get a color of their own (red channel = ID) that the default palette
+/// never uses, so `palette_to_materials` can tell them apart.
+pub fn level_palette() -> [[u8; 4]; 256] {
+    let mut palette = [[0u8; 4]; 256];
+    for (material, entry) in palette.iter_mut().enumerate().skip(1) {
+        let id = material as u8;
+        *entry = match material_color(id) {
+            Some([r, g, b]) => [r, g, b, 1.0].map(|c| (c * 255.0).round() as u8),
+            None => [id, id.wrapping_mul(67), id.wrapping_mul(151), 0xff],
+        };
+    }
+    palette
+}
+
+/// `_r` packs a signed permutation matrix: bits 0-1 / 2-3 are the column of the non-zero entry
+/// in rows 0 / 1, bits 4-6 the signs of rows 0-2.
+fn decode_rotation(r: u8) -> Result<VoxRotation> {
+    let c0 = (r & 3) as usize;
+    let c1 = ((r >> 2) & 3) as usize;
+    anyhow::ensure!(c0 < 3 && c1 < 3 && c0 != c1, "Invalid .vox rotation {r}");
+    let c2 = 3 - c0 - c1;
+
+    let mut rows = [[0i32; 3]; 3];
+    for (row, col) in [c0, c1, c2].into_iter().enumerate() {
+        rows[row][col] = if (r >> (4 + row)) & 1 == 1 { -1 } else { 1 };
+    }
+    Ok(rows)
+}
+
+fn encode_rotation(m: &VoxRotation) -> u8 {
+    let mut r = 0u8;
+    for (row, values) in m.iter().enumerate() {
+        let col = values.iter().position(|&v| v != 0).unwrap_or(row);
+        if row < 2 {
+            r |= (col as u8) << (row * 2);
+        }
+        if values[col] < 0 {
+            r |= 1 << (4 + row);
+        }
+    }
+    r
+}
+
+fn parse_translation(t: &str) -> Result<IVec3> {
+    let parts: Vec<i32> = t
+        .split_whitespace()
+        .map(|p| p.parse::<i32>())
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("Invalid .vox translation '{t}'"))?;
+    anyhow::ensure!(parts.len() == 3, "Invalid .vox translation '{t}'");
+    Ok(IVec3::new(parts[0], parts[1], parts[2]))
+}
+
+impl VoxScene {
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let bytes = fs::read(path.as_ref())
+            .with_context(|| format!("Failed to read .vox file: {}", path.as_ref().display()))?;
+        Self::parse(&bytes).with_context(|| format!("Failed to parse .vox file: {}", path.as_ref().display()))
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(bytes);
+        anyhow::ensure!(reader.take(4)? == b"VOX ", "Missing 'VOX ' magic");
+        let _version = reader.u32()?;
+
+        anyhow::ensure!(reader.take(4)? == b"MAIN", "Missing MAIN chunk");
+        let main_content = reader.u32()? as usize;
+        let main_children = reader.u32()? as usize;
+        reader.take(main_content)?;
+        let mut reader = Reader::new(reader.take(main_children)?);
+
+        let mut models = Vec::new();
+        let mut pending_size: Option<[u32; 3]> = None;
+        let mut palette = None;
+        let mut nodes: HashMap<i32, SceneNode> = HashMap::new();
+
+        while reader.remaining() > 0 {
+            let id: [u8; 4] = reader.take(4)?.try_into().unwrap();
+            let content_len = reader.u32()? as usize;
+            let children_len = reader.u32()? as usize;
+            let mut content = Reader::new(reader.take(content_len)?);
+            reader.take(children_len)?;
+
+            match &id {
+                b"SIZE" => {
+                    pending_size = Some([content.u32()?, content.u32()?, content.u32()?]);
+                }
+                b"XYZI" => {
+                    let size = pending_size.take().context("XYZI chunk without a preceding SIZE")?;
+                    let count = content.u32()? as usize;
+                    let data = content.take(count * 4)?;
+                    let voxels = data.chunks_exact(4).map(|v| [v[0], v[1], v[2], v[3]]).collect();
+                    models.push(VoxModel { size, voxels });
+                }
+                b"RGBA" => {
+                    let mut p = [[0u8; 4]; 256];
+                    for entry in p.iter_mut().skip(1) {
+                        entry.copy_from_slice(content.take(4)?);
+                    }
+                    palette = Some(p);
+                }
+                b"nTRN" => {
+                    let node_id = content.i32()?;
+                    content.dict()?;
+                    let child = content.i32()?;
+                    let _reserved = content.i32()?;
+                    let _layer = content.i32()?;
+                    let frames = content.u32()?;
+                    let mut rotation = VOX_IDENTITY;
+                    let mut translation = IVec3::ZERO;
+                    // Only the first animation frame is used.
+                    for frame in 0..frames {
+                        let attrs = content.dict()?;
+                        if frame > 0 {
+                            continue;
+                        }
+                        if let Some(r) = attrs.get("_r") {
+                            let r: u8 = r.parse().with_context(|| format!("Invalid .vox rotation '{r}'"))?;
+                            rotation = decode_rotation(r)?;
+                        }
+                        if let Some(t) = attrs.get("_t") {
+                            translation = parse_translation(t)?;
+                        }
+                    }
+                    nodes.insert(node_id, SceneNode::Transform { child, rotation, translation });
+                }
+                b"nGRP" => {
+                    let node_id = content.i32()?;
+                    content.dict()?;
+                    let count = content.u32()?;
+                    let children = (0..count).map(|_| content.i32()).collect::<Result<_>>()?;
+                    nodes.insert(node_id, SceneNode::Group { children });
+                }
+                b"nSHP" => {
+                    let node_id = content.i32()?;
+                    content.dict()?;
+                    let count = content.u32()?;
+                    let mut shape_models = Vec::with_capacity(count as usize);
+                    for _ in 0..count {
+                        shape_models.push(content.i32()?);
+                        content.dict()?;
+                    }
+                    nodes.insert(node_id, SceneNode::Shape { models: shape_models });
+                }
+                // PACK, MATL, LAYR, rOBJ, rCAM, NOTE, IMAP, ... carry nothing we need.
+                _ => {}
+            }
+        }
+
+        let mut instances = Vec::new();
+        if nodes.contains_key(&0) {
+            collect_instances(&nodes, &models, 0, &VOX_IDENTITY, IVec3::ZERO, 0, &mut instances)?;
+        } else {
+            // Pre-scene-graph files: every model sits at the origin, uncentered.
+            for model in 0..models.len() {
+                instances.push(VoxInstance {
+                    model,
+                    rotation: VOX_IDENTITY,
+                    translation: IVec3::ZERO,
+                    pivot: IVec3::ZERO,
+                });
+            }
+        }
+        Ok(Self {
+            models,
+            palette: palette.unwrap_or_else(default_palette),
+            instances,
+        })
+    }
+
+    /// World-space voxels of every instance in MagicaVoxel space (Z up), as `(position, color_index)`.
+    pub fn world_voxels(&self) -> impl Iterator<Item = (IVec3, u8)> + '_ {
+        self.instances.iter().flat_map(move |instance| {
+            self.models[instance.model].voxels.iter().map(move |&[x, y, z, c]| {
+                let local = IVec3::new(x as i32, y as i32, z as i32) - instance.pivot;
+                (rotate(&instance.rotation, local) + instance.translation, c)
+            })
+        })
+    }
+}
+
+// Scene-graph instances are centered on their translation, like MagicaVoxel does.
+fn collect_instances(
+    nodes: &HashMap<i32, SceneNode>,
+    models: &[VoxModel],
+    id: i32,
+    rotation: &VoxRotation,
+    translation: IVec3,
+    depth: usize,
+    out: &mut Vec<VoxInstance>,
+) -> Result<()> {
+    anyhow::ensure!(depth < 256, "Scene graph is too deep (cycle?)");
+    let Some(node) = nodes.get(&id) else {
+        bail!("Scene graph references missing node {id}");
+    };
+    match node {
+        SceneNode::Transform { child, rotation: r, translation: t } => {
+            let world_rotation = mul(rotation, r);
+            let world_translation = rotate(rotation, *t) + translation;
+            collect_instances(nodes, models, *child, &world_rotation, world_translation, depth + 1, out)?;
+        }
+        SceneNode::Group { children } => {
+            for child in children {
+                collect_instances(nodes, models, *child, rotation, translation, depth + 1, out)?;
+            }
+        }
+        SceneNode::Shape { models: shape_models } => {
+            for &model in shape_models {
+                let Some(m) = usize::try_from(model).ok().and_then(|i| models.get(i)) else {
+                    bail!("Scene references missing model {model}");
+                };
+                out.push(VoxInstance {
+                    model: model as usize,
+                    rotation: *rotation,
+                    translation,
+                    pivot: IVec3::new(m.size[0] as i32, m.size[1] as i32, m.size[2] as i32) / 2,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Maps each palette entry to the material whose `level_palette` color it is, and any other
+/// color to the material whose `colorLookup` color is nearest. Exports therefore re-import
+/// one-to-one even after some palette entries were edited or moved.
+/// The result can be edited before `import_vox` to pin specific color indices.
+pub fn palette_to_materials(palette: &[[u8; 4]; 256]) -> [u8; 256] {
+    let level = level_palette();
+    let mut materials = [0u8; 256];
+    for (index, rgba) in palette.iter().enumerate().skip(1) {
+        if let Some(material) = level.iter().skip(1).position(|c| c == rgba) {
+            materials[index] = material as u8 + 1;
+            continue;
+        }
+        let mut best = (f32::MAX, 0u8);
+        for (i, color) in COLOR_LOOKUP.iter().enumerate() {
+            let d: f32 = (0..3)
+                .map(|c| {
+                    let diff = rgba[c] as f32 / 255.0 - color[c];
+                    diff * diff
+                })
+                .sum();
+            if d < best.0 {
+                best = (d, i as u8 + 1);
+            }
+        }
+        materials[index] = best.1;
+    }
+    materials
+}
+
+/// MagicaVoxel (x, y, z) with Z up to our Y-up frame, keeping handedness.
+pub fn vox_to_world(p: IVec3) -> IVec3 {
+    IVec3::new(p.x, p.z, -p.y)
+}
+
+/// Converts a scene into chunks. `materials` maps color index to material ID (0 drops the voxel).
+/// The result is shifted so its bounding box starts at the origin, which keeps chunk keys valid
+/// for `ChunkVolume::into_level_file`.
+pub fn import_vox(scene: &VoxScene, materials: &[u8; 256]) -> ChunkVolume {
+    let voxels: Vec<(IVec3, u8)> = scene
+        .world_voxels()
+        .filter_map(|(p, c)| {
+            let material = materials[c as usize];
+            (material != 0).then(|| (vox_to_world(p), material))
+        })
+        .collect();
+
+    let min = voxels.iter().fold(IVec3::MAX, |m, (p, _)| m.min(*p));
+    let mut volume = ChunkVolume::new();
+    for (p, material) in voxels {
+        volume.set(p - min, material);
+    }
+    volume
+}
+
+/// Our Y-up frame back to MagicaVoxel's Z-up one (inverse of `vox_to_world`).
+pub fn world_to_vox(p: IVec3) -> IVec3 {
+    IVec3::new(p.x, -p.z, p.y)
+}
+
+/// MagicaVoxel caps models at 256 voxels per axis.
+pub const VOX_MAX_MODEL_SIZE: i32 = 256;
+
+/// Copies the box `min..max` (world voxels, `max` exclusive) into a scene using `level_palette`.
+/// Boxes larger than 256 on any axis are split into a grid of models, each placed by its own
+/// `nTRN` so the pieces line up in MagicaVoxel. Empty pieces are skipped.
+pub fn export_region(volume: &ChunkVolume, min: IVec3, max: IVec3) -> VoxScene {
+    let mut models = Vec::new();
+    let mut instances = Vec::new();
+
+    // Work in MagicaVoxel axes relative to the box, so every coordinate is non-negative.
+    let vox_min = world_to_vox(min).min(world_to_vox(max - IVec3::ONE));
+    let vox_max = world_to_vox(min).max(world_to_vox(max - IVec3::ONE)) + IVec3::ONE;
+    let extent = (vox_max - vox_min).max(IVec3::ZERO);
+    let pieces = (extent + IVec3::splat(VOX_MAX_MODEL_SIZE - 1)) / VOX_MAX_MODEL_SIZE;
+
+    for pz in 0..pieces.z {
+        for py in 0..pieces.y {
+            for px in 0..pieces.x {
+                let piece_min = IVec3::new(px, py, pz) * VOX_MAX_MODEL_SIZE;
+                let size = (extent - piece_min).min(IVec3::splat(VOX_MAX_MODEL_SIZE));
+
+                let mut voxels = Vec::new();
+                for z in 0..size.z {
+                    for y in 0..size.y {
+                        for x in 0..size.x {
+                            let local = IVec3::new(x, y, z);
+                            let material = volume.get(vox_to_world(vox_min + piece_min + local));
+                            if material != 0 {
+                                voxels.push([x as u8, y as u8, z as u8, material]);
+                            }
+                        }
+                    }
+                }
+                if voxels.is_empty() {
+                    continue;
+                }
+
+                let pivot = size / 2;
+                instances.push(VoxInstance {
+                    model: models.len(),
+                    rotation: VOX_IDENTITY,
+                    translation: piece_min + pivot,
+                    pivot,
+                });
+                models.push(VoxModel {
+                    size: [size.x as u32, size.y as u32, size.z as u32],
+                    voxels,
+                });
+            }
+        }
+    }
+
+    VoxScene {
+        models,
+        palette: level_palette(),
+        instances,
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], content: &[u8], children: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    out.extend_from_slice(&(children.len() as u32).to_le_bytes());
+    out.extend_from_slice(content);
+    out.extend_from_slice(children);
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_dict(out: &mut Vec<u8>, entries: &[(&str, String)]) {
+    out.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    for (key, value) in entries {
+        write_string(out, key);
+        write_string(out, value);
+    }
+}
+
+fn write_i32s(out: &mut Vec<u8>, values: &[i32]) {
+    for v in values {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+}
+
+impl VoxScene {
+    /// Serializes as a version 150 file with a flat scene graph:
+    /// root `nTRN` -> `nGRP` -> one `nTRN` + `nSHP` per instance.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut children = Vec::new();
+
+        for model in &self.models {
+            let mut content = Vec::new();
+            for v in model.size {
+                content.extend_from_slice(&v.to_le_bytes());
+            }
+            write_chunk(&mut children, b"SIZE", &content, &[]);
+
+            let mut content = Vec::with_capacity(4 + model.voxels.len() * 4);
+            content.extend_from_slice(&(model.voxels.len() as u32).to_le_bytes());
+            for v in &model.voxels {
+                content.extend_from_slice(v);
+            }
+            write_chunk(&mut children, b"XYZI", &content, &[]);
+        }
+
+        // Node ids: 0 root transform, 1 group, then transform/shape pairs.
+        let mut content = Vec::new();
+        write_i32s(&mut content, &[0]);
+        write_dict(&mut content, &[]);
+        write_i32s(&mut content, &[1, -1, -1, 1]);
+        write_dict(&mut content, &[]);
+        write_chunk(&mut children, b"nTRN", &content, &[]);
+
+        let mut content = Vec::new();
+        write_i32s(&mut content, &[1]);
+        write_dict(&mut content, &[]);
+        content.extend_from_slice(&(self.instances.len() as u32).to_le_bytes());
+        for i in 0..self.instances.len() as i32 {
+            write_i32s(&mut content, &[2 + i * 2]);
+        }
+        write_chunk(&mut children, b"nGRP", &content, &[]);
+
+        for (i, instance) in self.instances.iter().enumerate() {
+            let transform_id = 2 + i as i32 * 2;
+            let t = instance.translation;
+            let mut frame = vec![("_t", format!("{} {} {}", t.x, t.y, t.z))];
+            if instance.rotation != VOX_IDENTITY {
+                frame.push(("_r", encode_rotation(&instance.rotation).to_string()));
+            }
+
+            let mut content = Vec::new();
+            write_i32s(&mut content, &[transform_id]);
+            write_dict(&mut content, &[]);
+            write_i32s(&mut content, &[transform_id + 1, -1, 0, 1]);
+            write_dict(&mut content, &frame);
+            write_chunk(&mut children, b"nTRN", &content, &[]);
+
+            let mut content = Vec::new();
+            write_i32s(&mut content, &[transform_id + 1]);
+            write_dict(&mut content, &[]);
+            write_i32s(&mut content, &[1, instance.model as i32]);
+            write_dict(&mut content, &[]);
+            write_chunk(&mut children, b"nSHP", &content, &[]);
+        }
+
+        let mut content = Vec::new();
+        write_i32s(&mut content, &[0]);
+        write_dict(&mut content, &[]);
+        write_i32s(&mut content, &[-1]);
+        write_chunk(&mut children, b"LAYR", &content, &[]);
+
+        // RGBA entry i holds color index i + 1.
+        let mut content = Vec::with_capacity(256 * 4);
+        for i in 0..256 {
+            content.extend_from_slice(&self.palette[(i + 1) % 256]);
+        }
+        write_chunk(&mut children, b"RGBA", &content, &[]);
+
+        let mut out = Vec::with_capacity(8 + 12 + children.len());
+        out.extend_from_slice(b"VOX ");
+        out.extend_from_slice(&150u32.to_le_bytes());
+        write_chunk(&mut out, b"MAIN", &[], &children);
+        out
+    }
+
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path.as_ref(), self.to_bytes())
+            .with_context(|| format!("Failed to write .vox file: {}", path.as_ref().display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exported_materials_reimport_unchanged() {
+        let mut volume = ChunkVolume::new();
+        for material in 1..=255u8 {
+            volume.set(IVec3::new(material as i32, 0, 0), material);
+        }
+        let bytes = export_region(&volume, IVec3::ZERO, IVec3::new(256, 1, 1)).to_bytes();
+        let scene = VoxScene::parse(&bytes).unwrap();
+        let materials = palette_to_materials(&scene.palette);
+        let mut voxels: Vec<u8> = scene.world_voxels().map(|(_, c)| materials[c as usize]).collect();
+        voxels.sort_unstable();
+        assert_eq!(voxels, (1..=255).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn edited_palettes_keep_the_other_materials() {
+        let mut volume = ChunkVolume::new();
+        for material in 1..=255u8 {
+            volume.set(IVec3::new(material as i32, 0, 0), material);
+        }
+        let mut scene = export_region(&volume, IVec3::ZERO, IVec3::new(256, 1, 1));
+        scene.palette[3] = [0xff, 0xff, 0xff, 0xff];
+        scene.palette[200] = [0, 0, 0, 0xff];
+        scene.palette.swap(20, 21);
+        let scene = VoxScene::parse(&scene.to_bytes()).unwrap();
+        let materials = palette_to_materials(&scene.palette);
+
+        let mut seen = std::collections::HashSet::new();
+        for (_, exported) in scene.world_voxels() {
+            let imported = materials[exported as usize];
+            match exported {
+                3 | 200 => assert!((1..=COLOR_LOOKUP.len() as u8).contains(&imported)),
+                20 | 21 => assert_eq!(imported, 41 - exported),
+                _ => assert_eq!(imported, exported),
+            }
+            if exported > COLOR_LOOKUP.len() as u8 && exported != 200 {
+                assert!(seen.insert(imported), "material {imported} imported twice");
+            }
+        }
+        assert_eq!(seen.len(), 255 - COLOR_LOOKUP.len() - 1);
+    }
+
+    #[test]
+    fn default_palette_files_only_use_colored_materials() {
+        let materials = palette_to_materials(&default_palette());
+        assert!(materials[1..].iter().all(|&m| (1..=COLOR_LOOKUP.len() as u8).contains(&m)));
+    }
+}
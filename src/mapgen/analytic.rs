@@ -0,0 +1,135 @@
+// Closed-form terrain for testing the pipeline: flat ground and debug patterns
+use super::biome::BiomeMap;
+use super::config::MapGenConfig;
+use super::generators::Generator;
+use super::interval::Interval;
+use anyhow::Result;
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+/// `[flat]` section for `generator = "flat"`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlatConfig {
+    /// Voxels below this height are solid.
+    pub height: f32,
+}
+
+/// Level ground at `[flat] height`.
+pub struct FlatGenerator {
+    config: MapGenConfig,
+    height: f32,
+    biomes: Option<BiomeMap>,
+}
+
+impl FlatGenerator {
+    pub fn new(config: MapGenConfig) -> Result<Self> {
+        let height = config.flat.clone().unwrap_or_default().height;
+        let biomes = BiomeMap::new(&config);
+        Ok(Self { config, height, biomes })
+    }
+}
+
+impl Generator for FlatGenerator {
+    fn config(&self) -> &MapGenConfig {
+        &self.config
+    }
+
+    fn biomes(&self) -> Option<&BiomeMap> {
+        self.biomes.as_ref()
+    }
+
+    // Offset by the threshold so the ground doesn't move with it
+    fn generate_density(&self, _wx: f32, wy: f32, _wz: f32) -> f32 {
+        self.height - wy + self.config.density_threshold
+    }
+
+    fn generate_density_and_gradient(&self, wx: f32, wy: f32, wz: f32) -> (f32, Vec3) {
+        (self.generate_density(wx, wy, wz), Vec3::NEG_Y)
+    }
+
+    fn density_bounds(&self, lo: Vec3, hi: Vec3) -> Option<Interval> {
+        Some(-Interval::new(lo.y, hi.y) + (self.height + self.config.density_threshold))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DebugPattern {
+    /// Alternating solid and empty cubes `size` voxels across.
+    #[default]
+    Checkerboard,
+    /// A sphere `size` voxels across in every cell of a `2 * size` grid.
+    Spheres,
+    /// Ground rising and falling by `size` voxels with a `8 * size` wavelength.
+    Waves,
+}
+
+/// `[debug]` section for `generator = "debug"`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DebugConfig {
+    pub pattern: DebugPattern,
+    pub size: f32,
+}
+
+impl Default for DebugConfig {
+    fn default() -> Self {
+        Self {
+            pattern: DebugPattern::Checkerboard,
+            size: 8.0,
+        }
+    }
+}
+
+/// Regular test shapes for checking meshing, chunk borders and materials.
+pub struct DebugGenerator {
+    config: MapGenConfig,
+    debug: DebugConfig,
+}
+
+impl DebugGenerator {
+    pub fn new(config: MapGenConfig) -> Result<Self> {
+        let debug = config.debug.clone().unwrap_or_default();
+        anyhow::ensure!(debug.size > 0.0, "[debug] size must be positive, got {}", debug.size);
+        Ok(Self { config, debug })
+    }
+}
+
+impl Generator for DebugGenerator {
+    fn config(&self) -> &MapGenConfig {
+        &self.config
+    }
+
+    // Signed distance-like values, offset by the threshold so shapes don't move with it
+    fn generate_density(&self, wx: f32, wy: f32, wz: f32) -> f32 {
+        let size = self.debug.size;
+        let p = Vec3::new(wx, wy, wz);
+        let value = match self.debug.pattern {
+            DebugPattern::Checkerboard => {
+                let cell = (p / size).floor();
+                if (cell.x + cell.y + cell.z).rem_euclid(2.0) == 0.0 { 1.0 } else { -1.0 }
+            }
+            DebugPattern::Spheres => {
+                let cell = 2.0 * size;
+                let center = ((p / cell).floor() + Vec3::splat(0.5)) * cell;
+                size * 0.5 - p.distance(center)
+            }
+            DebugPattern::Waves => {
+                let k = std::f32::consts::TAU / (8.0 * size);
+                size * (wx * k).sin() * (wz * k).cos() - wy
+            }
+        };
+        value + self.config.density_threshold
+    }
+
+    fn density_bounds(&self, lo: Vec3, hi: Vec3) -> Option<Interval> {
+        match self.debug.pattern {
+            DebugPattern::Waves => {
+                let size = self.debug.size;
+                Some(Interval::new(-size, size) - Interval::new(lo.y, hi.y) + self.config.density_threshold)
+            }
+            DebugPattern::Checkerboard | DebugPattern::Spheres => None,
+        }
+    }
+}
@@ -0,0 +1,38 @@
+// Baking generated terrain into level files
+use super::generators::{generate_chunk, ChunkVoxels, Generator};
+use crate::data::level_file::LevelFile;
+use crate::get_xyz_key;
+use anyhow::Result;
+use glam::IVec3;
+use rayon::prelude::*;
+
+/// Generates the chunks in `min..=max` (chunk coordinates) and stores them in a level file,
+/// keyed relative to `min` so the region starts at chunk `(0, 0, 0)`.
+pub fn bake_level<G: Generator + ?Sized>(generator: &G, min: IVec3, max: IVec3) -> Result<LevelFile> {
+    let extent = max - min + IVec3::ONE;
+    anyhow::ensure!(extent.cmpgt(IVec3::ZERO).all(), "Empty bake region {min}..={max}");
+    anyhow::ensure!(
+        extent.cmple(IVec3::splat(256)).all(),
+        "Bake region {min}..={max} exceeds the level format's 256 chunks per axis"
+    );
+
+    let mut chunk_positions = Vec::new();
+    for cz in min.z..=max.z {
+        for cy in min.y..=max.y {
+            for cx in min.x..=max.x {
+                chunk_positions.push(IVec3::new(cx, cy, cz));
+            }
+        }
+    }
+
+    let chunks: Vec<(u32, ChunkVoxels)> = chunk_positions
+        .par_iter()
+        .map(|&chunk_pos| {
+            let (voxels, _) = generate_chunk(chunk_pos, generator);
+            let key = chunk_pos - min;
+            (get_xyz_key(key.x as u8, key.y as u8, key.z as u8), voxels)
+        })
+        .collect();
+
+    LevelFile::from_chunks(&chunks)
+}
@@ -0,0 +1,242 @@
+// Temperature / humidity climate maps and the biomes they select
+use super::config::{MapGenConfig, NoiseLayer};
+use super::interval::Interval;
+use super::noise::{fbm3d, NoiseBasis};
+use serde::{Deserialize, Serialize};
+
+/// Noise for the two climate axes, sampled in the XZ plane and mapped to 0..1.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClimateConfig {
+    pub temperature: NoiseLayer,
+    pub humidity: NoiseLayer,
+    /// Width of the border zone in climate space; 0 gives hard biome edges.
+    #[serde(default = "default_blend_width")]
+    pub blend_width: f32,
+}
+
+fn default_blend_width() -> f32 {
+    0.1
+}
+
+impl Default for ClimateConfig {
+    fn default() -> Self {
+        let layer = |scale| NoiseLayer {
+            scale,
+            octaves: 3,
+            lacunarity: 2.0,
+            gain: 0.5,
+            basis: NoiseBasis::Perlin,
+            warp: None,
+        };
+        Self {
+            temperature: layer(320.0),
+            humidity: layer(260.0),
+            blend_width: default_blend_width(),
+        }
+    }
+}
+
+/// One `[[biomes]]` entry. A column belongs to the biome whose climate point is closest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BiomeConfig {
+    pub name: String,
+    /// Climate point, both in 0..1.
+    pub temperature: f32,
+    pub humidity: f32,
+    /// Top solid voxel of a column.
+    pub surface: u8,
+    /// The `subsurface_depth` voxels under the surface.
+    pub subsurface: u8,
+    #[serde(default = "default_subsurface_depth")]
+    pub subsurface_depth: u32,
+    /// Everything deeper.
+    #[serde(default = "default_stone")]
+    pub stone: u8,
+    #[serde(default)]
+    pub modifiers: BiomeModifiers,
+}
+
+fn default_subsurface_depth() -> u32 {
+    3
+}
+
+fn default_stone() -> u8 {
+    1
+}
+
+/// Density shaping blended across biome borders. The weight factors multiply
+/// the matching `[weights]` entries (or the whole `[[layers]]` sum), `offset` is
+/// added to the density.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BiomeModifiers {
+    pub continentalness: f32,
+    pub peaks_valleys: f32,
+    pub erosion: f32,
+    pub layers: f32,
+    pub offset: f32,
+}
+
+impl Default for BiomeModifiers {
+    fn default() -> Self {
+        Self {
+            continentalness: 1.0,
+            peaks_valleys: 1.0,
+            erosion: 1.0,
+            layers: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl BiomeModifiers {
+    fn scaled(self, w: f32) -> Self {
+        Self {
+            continentalness: self.continentalness * w,
+            peaks_valleys: self.peaks_valleys * w,
+            erosion: self.erosion * w,
+            layers: self.layers * w,
+            offset: self.offset * w,
+        }
+    }
+
+    fn add(self, o: Self) -> Self {
+        Self {
+            continentalness: self.continentalness + o.continentalness,
+            peaks_valleys: self.peaks_valleys + o.peaks_valleys,
+            erosion: self.erosion + o.erosion,
+            layers: self.layers + o.layers,
+            offset: self.offset + o.offset,
+        }
+    }
+}
+
+impl BiomeConfig {
+    /// Material for a solid voxel with `depth` solid voxels directly above it.
+    pub fn material(&self, depth: u32) -> u8 {
+        if depth == 0 {
+            self.surface
+        } else if depth <= self.subsurface_depth {
+            self.subsurface
+        } else {
+            self.stone
+        }
+    }
+}
+
+pub struct BiomeMap {
+    seed: u32,
+    climate: ClimateConfig,
+    biomes: Vec<BiomeConfig>,
+}
+
+impl BiomeMap {
+    /// `None` when the config defines no biomes.
+    pub fn new(config: &MapGenConfig) -> Option<Self> {
+        if config.biomes.is_empty() {
+            return None;
+        }
+        Some(Self {
+            seed: config.seed,
+            climate: config.climate.clone(),
+            biomes: config.biomes.clone(),
+        })
+    }
+
+    pub fn biomes(&self) -> &[BiomeConfig] {
+        &self.biomes
+    }
+
+    /// Temperature and humidity at a column, each in 0..1.
+    pub fn climate(&self, wx: f32, wz: f32) -> (f32, f32) {
+        let sample = |layer: &NoiseLayer, seed: u32| {
+            let (x, _, z) = layer.coords(wx, 0.0, wz, seed);
+            let n = fbm3d(layer.basis, x, 0.0, z, seed, layer.octaves, layer.lacunarity, layer.gain);
+            (n * 0.5 + 0.5).clamp(0.0, 1.0)
+        };
+        (
+            sample(&self.climate.temperature, self.seed.wrapping_add(71)),
+            sample(&self.climate.humidity, self.seed.wrapping_add(83)),
+        )
+    }
+
+    fn distances(&self, t: f32, h: f32) -> impl Iterator<Item = f32> + '_ {
+        self.biomes.iter().map(move |b| {
+            let (dt, dh) = (b.temperature - t, b.humidity - h);
+            (dt * dt + dh * dh).sqrt()
+        })
+    }
+
+    /// Index into `biomes()` of the biome at a world column.
+    pub fn biome_index_at(&self, wx: f32, wz: f32) -> usize {
+        let (t, h) = self.climate(wx, wz);
+        self.distances(t, h)
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(0, |(i, _)| i)
+    }
+
+    pub fn biome_at(&self, wx: f32, wz: f32) -> &BiomeConfig {
+        &self.biomes[self.biome_index_at(wx, wz)]
+    }
+
+    /// Per-biome weights summing to 1. Biomes within `blend_width` of the closest one
+    /// fade in linearly with their climate distance, so modifiers change continuously.
+    pub fn blend_weights(&self, wx: f32, wz: f32) -> Vec<f32> {
+        let (t, h) = self.climate(wx, wz);
+        self.weights(t, h).collect()
+    }
+
+    // `blend_weights` at a climate point, without allocating
+    fn weights(&self, t: f32, h: f32) -> impl Iterator<Item = f32> + '_ {
+        let nearest = self.distances(t, h).fold(f32::MAX, f32::min);
+        let blend = self.climate.blend_width;
+        let weight = move |d: f32| {
+            if blend > 0.0 {
+                (1.0 - (d - nearest) / blend).max(0.0)
+            } else if d == nearest {
+                1.0
+            } else {
+                0.0
+            }
+        };
+        let total: f32 = self.distances(t, h).map(weight).sum();
+        self.distances(t, h).map(move |d| weight(d) / total)
+    }
+
+    /// Density modifiers blended across nearby biomes.
+    pub fn modifiers(&self, wx: f32, wz: f32) -> BiomeModifiers {
+        let (t, h) = self.climate(wx, wz);
+        let zero = BiomeModifiers::default().scaled(0.0);
+        self.weights(t, h)
+            .zip(&self.biomes)
+            .filter(|(w, _)| *w > 0.0)
+            .fold(zero, |acc, (w, b)| acc.add(b.modifiers.scaled(w)))
+    }
+
+    /// Range of one modifier field anywhere; blending never leaves the biomes' own values.
+    pub fn modifier_bounds(&self, field: fn(&BiomeModifiers) -> f32) -> Interval {
+        self.biomes
+            .iter()
+            .map(|b| Interval::point(field(&b.modifiers)))
+            .reduce(Interval::hull)
+            .unwrap_or(Interval::point(field(&BiomeModifiers::default())))
+    }
+
+    /// `modifiers` with their rates of change along X and Z, by central differences.
+    pub fn modifiers_slope(&self, wx: f32, wz: f32) -> (BiomeModifiers, BiomeModifiers, BiomeModifiers) {
+        const H: f32 = 0.05;
+        let slope = |a: BiomeModifiers, b: BiomeModifiers| a.add(b.scaled(-1.0)).scaled(0.5 / H);
+        (
+            self.modifiers(wx, wz),
+            slope(self.modifiers(wx + H, wz), self.modifiers(wx - H, wz)),
+            slope(self.modifiers(wx, wz + H), self.modifiers(wx, wz - H)),
+        )
+    }
+
+    /// Deepest material band of any biome; columns are sampled this far above a chunk
+    /// so surface materials match across chunk borders.
+    pub fn max_depth(&self) -> u32 {
+        self.biomes.iter().map(|b| b.subsurface_depth + 1).max().unwrap_or(0)
+    }
+}
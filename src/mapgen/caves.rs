@@ -0,0 +1,200 @@
+// Cave carving applied to solid voxels after density evaluation
+use super::noise::{fbm3d, lerp, ridged_fbm3d, NoiseBasis};
+use serde::{Deserialize, Serialize};
+
+/// `[caves]` section. Depth is measured below `surface_y`; noise scales move from
+/// their shallow to their `deep_` values over `depth_range` voxels.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaveConfig {
+    #[serde(default)]
+    pub surface_y: f32,
+    #[serde(default = "default_depth_range")]
+    pub depth_range: f32,
+    /// Solid voxels kept under the terrain surface; 0 lets caves open onto it.
+    #[serde(default = "default_surface_margin")]
+    pub surface_margin: u32,
+    #[serde(default)]
+    pub seed_offset: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cheese: Option<CheeseCaves>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spaghetti: Option<SpaghettiCaves>,
+}
+
+fn default_depth_range() -> f32 {
+    128.0
+}
+
+fn default_surface_margin() -> u32 {
+    4
+}
+
+/// Large caverns where fbm noise rises above `threshold`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheeseCaves {
+    pub scale: f32,
+    pub deep_scale: f32,
+    #[serde(default = "default_cheese_octaves")]
+    pub octaves: u32,
+    pub threshold: f32,
+    #[serde(default)]
+    pub basis: NoiseBasis,
+    /// Basis at `deep_scale`, set when `[wrap]` tiles the two scales with different periods.
+    #[serde(skip)]
+    pub deep_basis: Option<NoiseBasis>,
+}
+
+fn default_cheese_octaves() -> u32 {
+    2
+}
+
+/// Tunnels along the intersection of the ridges of two noise fields; `width` is
+/// how close to the ridge crest (0..1) both fields must be.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpaghettiCaves {
+    pub scale: f32,
+    pub deep_scale: f32,
+    pub width: f32,
+    #[serde(default)]
+    pub basis: NoiseBasis,
+    /// Basis at `deep_scale`, set when `[wrap]` tiles the two scales with different periods.
+    #[serde(skip)]
+    pub deep_basis: Option<NoiseBasis>,
+}
+
+pub struct CaveCarver {
+    seed: u32,
+    config: CaveConfig,
+}
+
+impl CaveCarver {
+    pub fn new(seed: u32, config: CaveConfig) -> Self {
+        Self {
+            seed: seed.wrapping_add(config.seed_offset),
+            config,
+        }
+    }
+
+    /// Voxels of terrain that must stay solid above any cave.
+    pub fn surface_margin(&self) -> u32 {
+        self.config.surface_margin
+    }
+
+    /// Whether the solid voxel at a world position is hollowed out. `depth` is the number of
+    /// solid voxels directly above it.
+    pub fn carves(&self, wx: f32, wy: f32, wz: f32, depth: u32) -> bool {
+        let cfg = &self.config;
+        if depth < cfg.surface_margin {
+            return false;
+        }
+        let t = ((cfg.surface_y - wy) / cfg.depth_range).clamp(0.0, 1.0);
+
+        if let Some(cheese) = &cfg.cheese {
+            let n = at_depth(cheese.scale, cheese.deep_scale, cheese.basis, cheese.deep_basis, t, |basis, scale| {
+                fbm3d(
+                    basis,
+                    wx / scale,
+                    wy / scale,
+                    wz / scale,
+                    self.seed.wrapping_add(401),
+                    cheese.octaves,
+                    2.0,
+                    0.5,
+                )
+            });
+            if n > cheese.threshold {
+                return true;
+            }
+        }
+
+        if let Some(spaghetti) = &cfg.spaghetti {
+            let ridge = |seed: u32| {
+                at_depth(spaghetti.scale, spaghetti.deep_scale, spaghetti.basis, spaghetti.deep_basis, t, |basis, scale| {
+                    ridged_fbm3d(basis, wx / scale, wy / scale, wz / scale, self.seed.wrapping_add(seed), 1, 2.0, 0.5)
+                })
+            };
+            let crest = 1.0 - spaghetti.width;
+            if ridge(503) > crest && ridge(607) > crest {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+/// `noise(basis, scale)` at depth fraction `t`. The scale itself is interpolated, unless a
+/// `deep_basis` says the two scales tile with different periods; an interpolated scale would
+/// then repeat at neither, so the noise values at both scales are blended instead.
+fn at_depth(
+    scale: f32,
+    deep_scale: f32,
+    basis: NoiseBasis,
+    deep_basis: Option<NoiseBasis>,
+    t: f32,
+    noise: impl Fn(NoiseBasis, f32) -> f32,
+) -> f32 {
+    match deep_basis {
+        None => noise(basis, lerp(scale, deep_scale, t)),
+        Some(_) if t <= 0.0 => noise(basis, scale),
+        Some(deep_basis) if t >= 1.0 => noise(deep_basis, deep_scale),
+        Some(deep_basis) => lerp(noise(basis, scale), noise(deep_basis, deep_scale), t),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn carver(toml: &str) -> CaveCarver {
+        CaveCarver::new(9, toml::from_str(toml).unwrap())
+    }
+
+    fn points() -> impl Iterator<Item = (f32, f32, f32)> {
+        (0..4000).map(|i| ((i % 40) as f32 * 3.0 - 60.0, (i / 40 % 10) as f32 * 7.0 - 80.0, (i / 400) as f32 * 5.0))
+    }
+
+    #[test]
+    fn surface_margin_keeps_shallow_voxels_solid() {
+        let caves = carver("surface_margin = 3\n[cheese]\nscale = 20.0\ndeep_scale = 20.0\nthreshold = -10.0\n");
+        assert!(!caves.carves(0.0, -5.0, 0.0, 2));
+        assert!(caves.carves(0.0, -5.0, 0.0, 3));
+    }
+
+    #[test]
+    fn cheese_scale_moves_to_deep_scale_over_depth_range() {
+        let caves = carver(
+            r#"
+            surface_y = 10.0
+            depth_range = 40.0
+            seed_offset = 5
+            [cheese]
+            scale = 16.0
+            deep_scale = 48.0
+            threshold = 0.1
+            "#,
+        );
+        let expected = |x: f32, y: f32, z: f32, scale: f32| {
+            fbm3d(NoiseBasis::Perlin, x / scale, y / scale, z / scale, 9 + 5 + 401, 2, 2.0, 0.5) > 0.1
+        };
+        for (x, _, z) in points() {
+            // At the surface, halfway down and past the bottom of the range
+            assert_eq!(caves.carves(x, 10.0, z, 8), expected(x, 10.0, z, 16.0), "({x}, 10, {z})");
+            assert_eq!(caves.carves(x, -10.0, z, 8), expected(x, -10.0, z, 32.0), "({x}, -10, {z})");
+            assert_eq!(caves.carves(x, -70.0, z, 8), expected(x, -70.0, z, 48.0), "({x}, -70, {z})");
+        }
+    }
+
+    #[test]
+    fn wider_spaghetti_tunnels_contain_narrower_ones() {
+        let tunnels = |width: f32| {
+            let caves = carver(&format!("[spaghetti]\nscale = 24.0\ndeep_scale = 24.0\nwidth = {width}\n"));
+            points().map(|(x, y, z)| caves.carves(x, y, z, 8)).collect::<Vec<bool>>()
+        };
+        let (narrow, wide) = (tunnels(0.05), tunnels(0.2));
+        assert!(narrow.iter().zip(&wide).all(|(&n, &w)| !n || w));
+        let carved = wide.iter().filter(|&&w| w).count();
+        assert!(carved > narrow.iter().filter(|&&n| n).count() && carved < wide.len(), "{carved} carved");
+        assert!(tunnels(0.0).iter().all(|&c| !c));
+    }
+}
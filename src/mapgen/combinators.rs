@@ -0,0 +1,410 @@
+// Generators assembled from other generators
+use super::biome::BiomeMap;
+use super::config::MapGenConfig;
+use super::generators::Generator;
+use super::interval::Interval;
+use super::noise::DomainWarp;
+use anyhow::Result;
+use glam::Vec3;
+
+/// How `CombinedGenerator` merges its parts' densities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combine {
+    Sum,
+    /// Solid only where every part is: CSG intersection.
+    Min,
+    /// Solid where any part is: CSG union.
+    Max,
+}
+
+impl Combine {
+    fn apply(self, a: f32, b: f32) -> f32 {
+        match self {
+            Combine::Sum => a + b,
+            Combine::Min => a.min(b),
+            Combine::Max => a.max(b),
+        }
+    }
+}
+
+/// Sum, minimum or maximum of several generators' densities. The threshold, materials and
+/// everything else in `config()` come from the first part, as do the biomes.
+pub struct CombinedGenerator {
+    op: Combine,
+    parts: Vec<Box<dyn Generator>>,
+}
+
+impl CombinedGenerator {
+    pub fn new(op: Combine, parts: Vec<Box<dyn Generator>>) -> Result<Self> {
+        anyhow::ensure!(!parts.is_empty(), "{op:?} of no generators");
+        Ok(Self { op, parts })
+    }
+}
+
+impl Generator for CombinedGenerator {
+    fn config(&self) -> &MapGenConfig {
+        self.parts[0].config()
+    }
+
+    fn biomes(&self) -> Option<&BiomeMap> {
+        self.parts[0].biomes()
+    }
+
+    fn generate_density(&self, wx: f32, wy: f32, wz: f32) -> f32 {
+        let (first, rest) = self.parts.split_first().unwrap();
+        rest.iter()
+            .fold(first.generate_density(wx, wy, wz), |d, part| self.op.apply(d, part.generate_density(wx, wy, wz)))
+    }
+
+    fn generate_column(&self, wx: f32, wz: f32, ys: &[f32], out: &mut [f32]) {
+        let (first, rest) = self.parts.split_first().unwrap();
+        first.generate_column(wx, wz, ys, out);
+        let mut scratch = vec![0.0; out.len()];
+        for part in rest {
+            part.generate_column(wx, wz, ys, &mut scratch);
+            for (d, &p) in out.iter_mut().zip(&scratch) {
+                *d = self.op.apply(*d, p);
+            }
+        }
+    }
+
+    fn generate_density_and_gradient(&self, wx: f32, wy: f32, wz: f32) -> (f32, Vec3) {
+        let (first, rest) = self.parts.split_first().unwrap();
+        rest.iter().fold(first.generate_density_and_gradient(wx, wy, wz), |(d, g), part| {
+            let (pd, pg) = part.generate_density_and_gradient(wx, wy, wz);
+            match self.op {
+                Combine::Sum => (d + pd, g + pg),
+                // The part that wins has the gradient; at a tie either side's is used
+                Combine::Min if pd < d => (pd, pg),
+                Combine::Max if pd > d => (pd, pg),
+                Combine::Min | Combine::Max => (d, g),
+            }
+        })
+    }
+
+    fn density_bounds(&self, lo: Vec3, hi: Vec3) -> Option<Interval> {
+        let mut bounds = self.parts.iter().map(|part| part.density_bounds(lo, hi));
+        let first = bounds.next()??;
+        bounds.try_fold(first, |acc, part| {
+            let part = part?;
+            Some(match self.op {
+                Combine::Sum => acc + part,
+                Combine::Min => Interval::new(acc.lo.min(part.lo), acc.hi.min(part.hi)),
+                Combine::Max => Interval::new(acc.lo.max(part.lo), acc.hi.max(part.hi)),
+            })
+        })
+    }
+}
+
+/// Fades from `a` to `b` as the density of `mask` goes from 0 to 1; the mask is clamped,
+/// so it only has to cross that range where the transition should be. Config and biomes
+/// come from `a`.
+pub struct BlendGenerator {
+    a: Box<dyn Generator>,
+    b: Box<dyn Generator>,
+    mask: Box<dyn Generator>,
+}
+
+impl BlendGenerator {
+    pub fn new(a: Box<dyn Generator>, b: Box<dyn Generator>, mask: Box<dyn Generator>) -> Self {
+        Self { a, b, mask }
+    }
+}
+
+impl Generator for BlendGenerator {
+    fn config(&self) -> &MapGenConfig {
+        self.a.config()
+    }
+
+    fn biomes(&self) -> Option<&BiomeMap> {
+        self.a.biomes()
+    }
+
+    fn generate_density(&self, wx: f32, wy: f32, wz: f32) -> f32 {
+        let t = self.mask.generate_density(wx, wy, wz).clamp(0.0, 1.0);
+        let a = self.a.generate_density(wx, wy, wz);
+        a + (self.b.generate_density(wx, wy, wz) - a) * t
+    }
+
+    fn generate_column(&self, wx: f32, wz: f32, ys: &[f32], out: &mut [f32]) {
+        let (mut t, mut b) = (vec![0.0; out.len()], vec![0.0; out.len()]);
+        self.mask.generate_column(wx, wz, ys, &mut t);
+        self.a.generate_column(wx, wz, ys, out);
+        self.b.generate_column(wx, wz, ys, &mut b);
+        for ((a, b), t) in out.iter_mut().zip(b).zip(t) {
+            *a += (b - *a) * t.clamp(0.0, 1.0);
+        }
+    }
+
+    fn generate_density_and_gradient(&self, wx: f32, wy: f32, wz: f32) -> (f32, Vec3) {
+        let (m, gm) = self.mask.generate_density_and_gradient(wx, wy, wz);
+        let (a, ga) = self.a.generate_density_and_gradient(wx, wy, wz);
+        let (b, gb) = self.b.generate_density_and_gradient(wx, wy, wz);
+        let t = m.clamp(0.0, 1.0);
+        // The clamp holds the weight still outside 0..1
+        let gt = if (0.0..=1.0).contains(&m) { gm } else { Vec3::ZERO };
+        (a + (b - a) * t, ga + (gb - ga) * t + gt * (b - a))
+    }
+
+    fn density_bounds(&self, lo: Vec3, hi: Vec3) -> Option<Interval> {
+        // A blend of two values lies between them, whatever the mask
+        Some(self.a.density_bounds(lo, hi)?.hull(self.b.density_bounds(lo, hi)?))
+    }
+}
+
+/// Density of `inner` held within `lo..=hi`.
+pub struct ClampGenerator {
+    inner: Box<dyn Generator>,
+    lo: f32,
+    hi: f32,
+}
+
+impl ClampGenerator {
+    pub fn new(inner: Box<dyn Generator>, lo: f32, hi: f32) -> Result<Self> {
+        anyhow::ensure!(lo <= hi, "Clamp range {lo}..={hi} is empty");
+        Ok(Self { inner, lo, hi })
+    }
+}
+
+impl Generator for ClampGenerator {
+    fn config(&self) -> &MapGenConfig {
+        self.inner.config()
+    }
+
+    fn biomes(&self) -> Option<&BiomeMap> {
+        self.inner.biomes()
+    }
+
+    fn generate_density(&self, wx: f32, wy: f32, wz: f32) -> f32 {
+        self.inner.generate_density(wx, wy, wz).clamp(self.lo, self.hi)
+    }
+
+    fn generate_column(&self, wx: f32, wz: f32, ys: &[f32], out: &mut [f32]) {
+        self.inner.generate_column(wx, wz, ys, out);
+        for d in out {
+            *d = d.clamp(self.lo, self.hi);
+        }
+    }
+
+    fn generate_density_and_gradient(&self, wx: f32, wy: f32, wz: f32) -> (f32, Vec3) {
+        let (d, g) = self.inner.generate_density_and_gradient(wx, wy, wz);
+        let g = if (self.lo..=self.hi).contains(&d) { g } else { Vec3::ZERO };
+        (d.clamp(self.lo, self.hi), g)
+    }
+
+    fn density_bounds(&self, lo: Vec3, hi: Vec3) -> Option<Interval> {
+        Some(
+            self.inner
+                .density_bounds(lo, hi)
+                .map_or(Interval::new(self.lo, self.hi), |b| b.clamp(self.lo, self.hi)),
+        )
+    }
+}
+
+/// `inner` stretched by `scale` per axis about the origin, then moved by `translate`: the
+/// density at `p` is the inner density at `(p - translate) / scale`.
+pub struct TransformGenerator {
+    inner: Box<dyn Generator>,
+    translate: Vec3,
+    scale: Vec3,
+}
+
+impl TransformGenerator {
+    pub fn new(inner: Box<dyn Generator>, translate: Vec3, scale: Vec3) -> Result<Self> {
+        anyhow::ensure!(scale.cmpne(Vec3::ZERO).all(), "Transform scale {scale} has a zero axis");
+        Ok(Self { inner, translate, scale })
+    }
+
+    fn to_inner(&self, p: Vec3) -> Vec3 {
+        (p - self.translate) / self.scale
+    }
+}
+
+impl Generator for TransformGenerator {
+    fn config(&self) -> &MapGenConfig {
+        self.inner.config()
+    }
+
+    fn biomes(&self) -> Option<&BiomeMap> {
+        self.inner.biomes()
+    }
+
+    fn generate_density(&self, wx: f32, wy: f32, wz: f32) -> f32 {
+        let p = self.to_inner(Vec3::new(wx, wy, wz));
+        self.inner.generate_density(p.x, p.y, p.z)
+    }
+
+    fn generate_column(&self, wx: f32, wz: f32, ys: &[f32], out: &mut [f32]) {
+        let p = self.to_inner(Vec3::new(wx, 0.0, wz));
+        let ys: Vec<f32> = ys.iter().map(|&wy| (wy - self.translate.y) / self.scale.y).collect();
+        self.inner.generate_column(p.x, p.z, &ys, out);
+    }
+
+    fn generate_density_and_gradient(&self, wx: f32, wy: f32, wz: f32) -> (f32, Vec3) {
+        let p = self.to_inner(Vec3::new(wx, wy, wz));
+        let (d, g) = self.inner.generate_density_and_gradient(p.x, p.y, p.z);
+        (d, g / self.scale)
+    }
+
+    fn density_bounds(&self, lo: Vec3, hi: Vec3) -> Option<Interval> {
+        // Negative scales mirror the box
+        let (a, b) = (self.to_inner(lo), self.to_inner(hi));
+        self.inner.density_bounds(a.min(b), a.max(b))
+    }
+}
+
+/// `inner` sampled at domain-warped positions (`DomainWarp::warp3d`).
+pub struct WarpGenerator {
+    inner: Box<dyn Generator>,
+    warp: DomainWarp,
+    seed: u32,
+}
+
+impl WarpGenerator {
+    pub fn new(inner: Box<dyn Generator>, warp: DomainWarp, seed: u32) -> Self {
+        Self { inner, warp, seed }
+    }
+}
+
+impl Generator for WarpGenerator {
+    fn config(&self) -> &MapGenConfig {
+        self.inner.config()
+    }
+
+    fn biomes(&self) -> Option<&BiomeMap> {
+        self.inner.biomes()
+    }
+
+    fn generate_density(&self, wx: f32, wy: f32, wz: f32) -> f32 {
+        let (x, y, z) = self.warp.warp3d(wx, wy, wz, self.seed);
+        self.inner.generate_density(x, y, z)
+    }
+
+    fn density_bounds(&self, lo: Vec3, hi: Vec3) -> Option<Interval> {
+        let reach = Vec3::splat(self.warp.max_displacement() * self.warp.iterations.min(1) as f32);
+        self.inner.density_bounds(lo - reach, hi + reach)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::analytic::{FlatConfig, FlatGenerator};
+    use crate::mapgen::generators::generate_chunk_voxels;
+    use crate::{get_zxy_index, CS_P};
+    use glam::IVec3;
+
+    // Ground at `height` with a zero threshold, so the density is `height - wy`
+    fn flat(height: f32) -> Box<dyn Generator> {
+        let config = MapGenConfig {
+            generator: Some("flat".to_string()),
+            flat: Some(FlatConfig { height }),
+            density_threshold: 0.0,
+            ..MapGenConfig::default()
+        };
+        Box::new(FlatGenerator::new(config).unwrap())
+    }
+
+    fn column(generator: &dyn Generator, ys: &[f32]) -> Vec<f32> {
+        let mut out = vec![0.0; ys.len()];
+        generator.generate_column(3.0, -7.0, ys, &mut out);
+        out
+    }
+
+    // Bounds over heights `lo..=hi` of a box 10 voxels across
+    fn bounds(generator: &dyn Generator, lo: f32, hi: f32) -> Interval {
+        generator.density_bounds(Vec3::new(0.0, lo, 0.0), Vec3::new(10.0, hi, 10.0)).unwrap()
+    }
+
+    #[test]
+    fn combined_densities() {
+        let sum = CombinedGenerator::new(Combine::Sum, vec![flat(10.0), flat(20.0)]).unwrap();
+        assert_eq!(sum.generate_density(3.0, 5.0, -7.0), 20.0);
+        assert_eq!(column(&sum, &[0.0, 5.0, 30.0]), [30.0, 20.0, -30.0]);
+        assert_eq!(bounds(&sum, 0.0, 4.0), Interval::new(22.0, 30.0));
+
+        let min = CombinedGenerator::new(Combine::Min, vec![flat(10.0), flat(20.0)]).unwrap();
+        assert_eq!(min.generate_density(3.0, 5.0, -7.0), 5.0);
+        assert_eq!(column(&min, &[0.0, 15.0]), [10.0, -5.0]);
+        assert_eq!(bounds(&min, 0.0, 4.0), Interval::new(6.0, 10.0));
+
+        let max = CombinedGenerator::new(Combine::Max, vec![flat(10.0), flat(20.0)]).unwrap();
+        assert_eq!(max.generate_density(3.0, 5.0, -7.0), 15.0);
+        assert_eq!(column(&max, &[0.0, 15.0]), [20.0, 5.0]);
+        assert_eq!(bounds(&max, 0.0, 4.0), Interval::new(16.0, 20.0));
+
+        assert!(CombinedGenerator::new(Combine::Sum, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn blend_follows_the_clamped_mask() {
+        // The mask is 0.5 - wy: all `a` above y = 0.5, all `b` below y = -0.5
+        let blend = BlendGenerator::new(flat(10.0), flat(30.0), flat(0.5));
+        assert_eq!(blend.generate_density(3.0, 5.0, -7.0), 5.0);
+        assert_eq!(blend.generate_density(3.0, 0.0, -7.0), 20.0);
+        assert_eq!(blend.generate_density(3.0, -5.0, -7.0), 35.0);
+        assert_eq!(column(&blend, &[5.0, 0.0, -5.0]), [5.0, 20.0, 35.0]);
+        assert_eq!(bounds(&blend, 0.0, 4.0), Interval::new(6.0, 30.0));
+    }
+
+    #[test]
+    fn clamp_holds_the_range() {
+        let clamp = ClampGenerator::new(flat(10.0), -2.0, 3.0).unwrap();
+        assert_eq!(column(&clamp, &[5.0, 9.0, 11.0, 20.0]), [3.0, 1.0, -1.0, -2.0]);
+        assert_eq!(clamp.generate_density(3.0, 9.0, -7.0), 1.0);
+        assert_eq!(bounds(&clamp, 0.0, 4.0), Interval::new(3.0, 3.0));
+        assert_eq!(bounds(&clamp, 8.0, 12.0), Interval::new(-2.0, 2.0));
+        assert!(ClampGenerator::new(flat(10.0), 1.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn transform_maps_into_the_inner_frame() {
+        // Inner height 10 at inner y = (wy - 5) / 2, so the ground sits at wy = 25
+        let stretched = TransformGenerator::new(flat(10.0), Vec3::new(0.0, 5.0, 0.0), Vec3::new(1.0, 2.0, 1.0)).unwrap();
+        assert_eq!(stretched.generate_density(3.0, 25.0, -7.0), 0.0);
+        assert_eq!(column(&stretched, &[5.0, 25.0, 45.0]), [10.0, 0.0, -10.0]);
+        assert_eq!(bounds(&stretched, 5.0, 25.0), Interval::new(0.0, 10.0));
+        assert_eq!(stretched.generate_density_and_gradient(3.0, 25.0, -7.0).1, Vec3::new(0.0, -0.5, 0.0));
+
+        // A mirrored Y axis turns the ground upside down: solid above wy = -10
+        let mirrored = TransformGenerator::new(flat(10.0), Vec3::ZERO, Vec3::new(1.0, -1.0, 1.0)).unwrap();
+        assert_eq!(column(&mirrored, &[-20.0, 0.0]), [-10.0, 10.0]);
+        assert_eq!(bounds(&mirrored, 0.0, 4.0), Interval::new(10.0, 14.0));
+        assert!(TransformGenerator::new(flat(10.0), Vec3::ZERO, Vec3::new(1.0, 0.0, 1.0)).is_err());
+    }
+
+    #[test]
+    fn warp_samples_the_displaced_point() {
+        let warp: DomainWarp = toml::from_str("amplitude = 6.0\nscale = 16.0\niterations = 2").unwrap();
+        let warped = WarpGenerator::new(flat(10.0), warp.clone(), 42);
+        let reach = warp.max_displacement();
+        let range = bounds(&warped, 0.0, 4.0);
+        assert_eq!(range, Interval::new(6.0 - reach, 10.0 + reach));
+        for i in 0..200 {
+            let (x, y, z) = (i as f32 * 1.7, (i % 5) as f32, i as f32 * -2.3);
+            let density = warped.generate_density(x, y, z);
+            assert_eq!(density, 10.0 - warp.warp3d(x, y, z, 42).1);
+            assert!(range.lo <= density && density <= range.hi, "{density} at ({x}, {y}, {z})");
+        }
+    }
+
+    #[test]
+    fn composed_generators_fill_chunks() {
+        // 30 - 2 wy held within -1..=1: solid up to wy = 14
+        let sum = CombinedGenerator::new(Combine::Sum, vec![flat(10.0), flat(20.0)]).unwrap();
+        let composed = ClampGenerator::new(Box::new(sum), -1.0, 1.0).unwrap();
+        let (voxels, solid_count) = generate_chunk_voxels(IVec3::ZERO, &composed);
+        assert_eq!(solid_count, CS_P * CS_P * 16);
+        for z in 0..CS_P {
+            for y in 0..CS_P {
+                for x in 0..CS_P {
+                    let wy = y as i32 - 1;
+                    assert_eq!(voxels[get_zxy_index(x, y, z)], (wy < 15) as u8, "voxel ({x}, {wy}, {z})");
+                }
+            }
+        }
+        let (above, solid_count) = generate_chunk_voxels(IVec3::Y, &composed);
+        assert_eq!(solid_count, 0);
+        assert!(above.iter().all(|&v| v == 0));
+    }
+}
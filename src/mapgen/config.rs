@@ -1,7 +1,21 @@
+use super::analytic::{DebugConfig, FlatConfig};
+use super::biome::{BiomeConfig, ClimateConfig};
+use super::caves::CaveConfig;
+use super::erosion::ErosionSimConfig;
+use super::graph::GraphConfig;
+use super::ores::OreConfig;
+use super::registry::implied_generator;
+use super::sampling::SamplingConfig;
+use super::spline::TerrainShaping;
+use super::structures::StructureConfig;
+use super::surface::SurfaceRule;
+use super::water::WaterConfig;
+use super::wrap::WrapConfig;
+use super::noise::{CellularReturn, DistanceMetric, DomainWarp, NoiseBasis};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
-use anyhow::{Context, Result};
+use anyhow::{ensure, Context, Result};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NoiseLayer {
@@ -9,22 +23,125 @@ pub struct NoiseLayer {
     pub octaves: u32,
     pub lacunarity: f32,
     pub gain: f32,
+    /// Octave noise, `perlin` (default) or `simplex`.
+    #[serde(default)]
+    pub basis: NoiseBasis,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warp: Option<DomainWarp>,
 }
 
+impl NoiseLayer {
+    /// Noise-space coordinates for a world position: warped (if configured), then divided by `scale`.
+    pub fn coords(&self, wx: f32, wy: f32, wz: f32, seed: u32) -> (f32, f32, f32) {
+        let (wx, wy, wz) = match &self.warp {
+            Some(warp) => warp.warp3d(wx, wy, wz, seed),
+            None => (wx, wy, wz),
+        };
+        (wx / self.scale, wy / self.scale, wz / self.scale)
+    }
+}
+
+// Sections only some generators read fall back to `MapGenConfig::default()`, so layer-only
+// files such as `layers_example.toml` parse; `load_from_file` still reports a missing section
+// the selected generator needs.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MapGenConfig {
+    #[serde(default)]
     pub name: String,
+    #[serde(default)]
     pub description: String,
     pub seed: u32,
+
+    /// Name of the generator in `GeneratorRegistry`, e.g. `multi_noise`, `layered`,
+    /// `heightmap`, `graph`, `flat` or `debug`. Inferred from the sections when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generator: Option<String>,
     
+    #[serde(default = "default_continentalness")]
     pub continentalness: NoiseLayer,
+    #[serde(default = "default_erosion")]
     pub erosion: NoiseLayer,
+    #[serde(default = "default_peaks_valleys")]
     pub peaks_valleys: NoiseLayer,
     
+    #[serde(default = "default_weights")]
     pub weights: Weights,
+    #[serde(default = "default_vertical")]
     pub vertical: VerticalConfig,
+    /// Legacy height bands, used as `surface_rules` when neither rules nor biomes are given.
+    #[serde(default = "default_material_thresholds")]
     pub material_thresholds: MaterialThresholds,
+    #[serde(default = "default_density_threshold")]
     pub density_threshold: f32,
+
+    /// Ordered material rules on depth below the surface, height, slope and biome.
+    /// Voxels no rule matches take their biome's materials.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub surface_rules: Vec<SurfaceRule>,
+
+    /// When present, terrain comes from a grayscale image instead of the noise layers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heightmap: Option<HeightmapConfig>,
+
+    /// When non-empty, density is the weighted sum of these layers instead of the
+    /// fixed continentalness / erosion / peaks_valleys stack.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub layers: Vec<LayerConfig>,
+
+    /// When present, density is the `output` node of this graph of named nodes, and the
+    /// noise layers above are unused.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub graph: Option<GraphConfig>,
+
+    /// Ground height for `generator = "flat"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flat: Option<FlatConfig>,
+
+    /// Test pattern for `generator = "debug"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug: Option<DebugConfig>,
+
+    /// Climate noise that picks between `biomes`; unused without biomes.
+    #[serde(default)]
+    pub climate: ClimateConfig,
+
+    /// When non-empty, surface materials (where no `surface_rules` entry matches) and
+    /// density modifiers come from the biome of each column.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub biomes: Vec<BiomeConfig>,
+
+    /// Cheese / spaghetti cave carving applied after density evaluation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caves: Option<CaveConfig>,
+
+    /// Ore deposits placed into host materials after carving.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ores: Vec<OreConfig>,
+
+    /// Trees, boulders and templates placed on the surface; they may cross chunk borders.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub structures: Vec<StructureConfig>,
+
+    /// Sea level water fill with optional beaches and ice.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub water: Option<WaterConfig>,
+
+    /// Spline curves from the noise channels to surface offset, factor and jaggedness.
+    /// Replaces the fixed multi-noise remapping and `[weights]` when present.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shaping: Option<TerrainShaping>,
+
+    /// Hydraulic and thermal erosion applied to the baked region's surface (bake only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub erosion_sim: Option<ErosionSimConfig>,
+
+    /// Coarse density lattice and column-level caching for faster chunk generation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<SamplingConfig>,
+
+    /// World width in chunks for worlds that wrap around on X and Z.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wrap: Option<WrapConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,6 +164,146 @@ pub struct MaterialThresholds {
     pub grass_max: i32,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeightmapConfig {
+    /// PGM or PNG image, relative to the working directory.
+    pub path: String,
+    /// World voxels per image pixel.
+    #[serde(default = "default_horizontal_scale")]
+    pub horizontal_scale: f32,
+    /// Height difference between a black and a white pixel.
+    pub vertical_scale: f32,
+    /// World Y of pixels whose value equals `sea_value`.
+    #[serde(default)]
+    pub sea_level: f32,
+    /// Normalized pixel value (0..1) that sits at `sea_level`.
+    #[serde(default)]
+    pub sea_value: f32,
+}
+
+fn default_horizontal_scale() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerKind {
+    Fbm,
+    RidgedFbm,
+    /// Single-octave cellular noise; `octaves`, `lacunarity`, `gain` and `basis` are ignored.
+    Worley,
+}
+
+/// One entry of a `[[layers]]` stack. Unknown keys (e.g. `display_cmap` used by the
+/// Python visualizer) are ignored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerConfig {
+    #[serde(default)]
+    pub name: String,
+    pub kind: LayerKind,
+    #[serde(default)]
+    pub basis: NoiseBasis,
+    pub scale: f32,
+    #[serde(default = "default_octaves")]
+    pub octaves: u32,
+    #[serde(default = "default_lacunarity")]
+    pub lacunarity: f32,
+    #[serde(default = "default_gain")]
+    pub gain: f32,
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    /// Added to the config seed so layers of the same kind stay decorrelated.
+    #[serde(default)]
+    pub seed_offset: u32,
+    /// Sample in the XZ plane only, so the layer is constant along Y.
+    #[serde(default)]
+    pub planar: bool,
+    /// Worley distance metric.
+    #[serde(default)]
+    pub metric: DistanceMetric,
+    /// Worley output: `f1`, `f2`, `f2_minus_f1` or `cell_id` (0..1).
+    #[serde(default, rename = "return")]
+    pub cellular_return: CellularReturn,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warp: Option<DomainWarp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remap: Option<Remap>,
+}
+
+/// Linear remap of a layer's raw value from `from` to `to`, applied before weighting:
+///
+/// ```toml
+/// remap = { from = [-2.0, 2.0], to = [0.0, 1.0], clamp = true }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Remap {
+    pub from: [f32; 2],
+    pub to: [f32; 2],
+    #[serde(default)]
+    pub clamp: bool,
+}
+
+impl Remap {
+    pub fn apply(&self, v: f32) -> f32 {
+        let t = (v - self.from[0]) / (self.from[1] - self.from[0]);
+        let t = if self.clamp { t.clamp(0.0, 1.0) } else { t };
+        self.to[0] + t * (self.to[1] - self.to[0])
+    }
+
+    /// Derivative of `apply` at `v`; 0 where the clamp holds the output constant.
+    pub fn slope(&self, v: f32) -> f32 {
+        let t = (v - self.from[0]) / (self.from[1] - self.from[0]);
+        if self.clamp && !(0.0..=1.0).contains(&t) {
+            return 0.0;
+        }
+        (self.to[1] - self.to[0]) / (self.from[1] - self.from[0])
+    }
+}
+
+fn default_octaves() -> u32 {
+    1
+}
+
+fn default_lacunarity() -> f32 {
+    2.0
+}
+
+fn default_gain() -> f32 {
+    0.5
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+fn default_continentalness() -> NoiseLayer {
+    MapGenConfig::default().continentalness
+}
+
+fn default_erosion() -> NoiseLayer {
+    MapGenConfig::default().erosion
+}
+
+fn default_peaks_valleys() -> NoiseLayer {
+    MapGenConfig::default().peaks_valleys
+}
+
+fn default_weights() -> Weights {
+    MapGenConfig::default().weights
+}
+
+fn default_vertical() -> VerticalConfig {
+    MapGenConfig::default().vertical
+}
+
+fn default_material_thresholds() -> MaterialThresholds {
+    MapGenConfig::default().material_thresholds
+}
+
+fn default_density_threshold() -> f32 {
+    MapGenConfig::default().density_threshold
+}
+
 impl MapGenConfig {
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
         let contents = fs::read_to_string(path.as_ref())
@@ -54,9 +311,31 @@ impl MapGenConfig {
         
         let config: MapGenConfig = toml::from_str(&contents)
             .with_context(|| format!("Failed to parse config file: {:?}", path.as_ref()))?;
+
+        // A misspelled or forgotten section would otherwise silently take its default.
+        let table: toml::Table = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file: {:?}", path.as_ref()))?;
+        let generator = config.generator.as_deref().unwrap_or_else(|| implied_generator(&config));
+        for section in config.required_sections(generator) {
+            ensure!(
+                table.contains_key(*section),
+                "Config file {:?} has no [{section}] section, which the {generator} generator needs",
+                path.as_ref()
+            );
+        }
         
         Ok(config)
     }
+
+    /// Sections `generator` reads that have no sensible default for a hand-written file.
+    fn required_sections(&self, generator: &str) -> &'static [&'static str] {
+        match generator {
+            // `[shaping]` replaces the vertical gradient.
+            "multi_noise" if self.shaping.is_some() => &["continentalness", "erosion", "peaks_valleys"],
+            "multi_noise" => &["continentalness", "erosion", "peaks_valleys", "vertical"],
+            _ => &[],
+        }
+    }
     
     pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         let contents = toml::to_string_pretty(self)
@@ -75,12 +354,15 @@ impl Default for MapGenConfig {
             name: "Default Multi-Noise".to_string(),
             description: "Default stacked noise terrain generation".to_string(),
             seed: 42,
+            generator: None,
             
             continentalness: NoiseLayer {
                 scale: 3.0,
                 octaves: 5,
                 lacunarity: 2.0,
                 gain: 0.5,
+                basis: NoiseBasis::Perlin,
+                warp: None,
             },
             
             erosion: NoiseLayer {
@@ -88,6 +370,8 @@ impl Default for MapGenConfig {
                 octaves: 4,
                 lacunarity: 2.0,
                 gain: 0.5,
+                basis: NoiseBasis::Perlin,
+                warp: None,
             },
             
             peaks_valleys: NoiseLayer {
@@ -95,6 +379,8 @@ impl Default for MapGenConfig {
                 octaves: 5,
                 lacunarity: 2.0,
                 gain: 0.5,
+                basis: NoiseBasis::Perlin,
+                warp: None,
             },
             
             weights: Weights {
@@ -113,8 +399,52 @@ impl Default for MapGenConfig {
                 stone_max: 32,
                 grass_max: 64,
             },
+            surface_rules: Vec::new(),
             
             density_threshold: -0.2,
+
+            heightmap: None,
+            layers: Vec::new(),
+            graph: None,
+            flat: None,
+            debug: None,
+            climate: ClimateConfig::default(),
+            biomes: Vec::new(),
+            caves: None,
+            ores: Vec::new(),
+            structures: Vec::new(),
+            water: None,
+            shaping: None,
+            erosion_sim: None,
+            sampling: None,
+            wrap: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::registry::GeneratorRegistry;
+
+    #[test]
+    fn visualizer_layer_files_load() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/mapgen_configs/layers_example.toml");
+        let config = MapGenConfig::load_from_file(path).unwrap();
+        assert_eq!(config.seed, 42);
+        assert_eq!(config.layers.len(), 3);
+        assert_eq!(implied_generator(&config), "layered");
+        assert!(GeneratorRegistry::default().build(config).is_ok());
+    }
+
+    #[test]
+    fn misspelled_multi_noise_sections_are_reported() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/mapgen_configs/mountains.toml");
+        let contents = fs::read_to_string(path).unwrap().replace("[continentalness]", "[continentalnes]");
+        let path = std::env::temp_dir().join(format!("config_test_{}.toml", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let err = MapGenConfig::load_from_file(&path).unwrap_err().to_string();
+        fs::remove_file(&path).unwrap();
+        assert!(err.contains("has no [continentalness] section"), "{err}");
+    }
+}
@@ -0,0 +1,475 @@
+// Bake-time hydraulic (droplet) and thermal erosion on a heightfield extracted from a generator
+use super::biome::BiomeMap;
+use super::config::MapGenConfig;
+use super::generators::Generator;
+use super::interval::Interval;
+use super::noise::{hash_u32, HashRng};
+use crate::CS;
+use glam::{IVec3, Vec3};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// `[erosion_sim]` section, used when baking level files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErosionSimConfig {
+    /// Droplets start in square tiles of this many columns, simulated in parallel; each may
+    /// roll up to half a tile into the neighbouring ones.
+    #[serde(default = "default_tile_size")]
+    pub tile_size: usize,
+    /// Columns simulated around the baked region so its edges erode like the interior.
+    #[serde(default = "default_margin")]
+    pub margin: i32,
+    #[serde(default)]
+    pub seed_offset: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hydraulic: Option<HydraulicConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thermal: Option<ThermalConfig>,
+}
+
+fn default_tile_size() -> usize {
+    64
+}
+
+fn default_margin() -> i32 {
+    16
+}
+
+/// Droplet erosion: each droplet rolls downhill, picking up sediment where it speeds up
+/// and dropping it where it slows down or overflows its capacity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HydraulicConfig {
+    /// Droplets per column of the heightfield.
+    pub droplets_per_cell: f32,
+    pub max_steps: u32,
+    /// 0 follows the slope exactly, 1 never turns.
+    pub inertia: f32,
+    pub capacity: f32,
+    pub min_slope: f32,
+    pub erosion: f32,
+    pub deposition: f32,
+    pub evaporation: f32,
+    pub gravity: f32,
+}
+
+impl Default for HydraulicConfig {
+    fn default() -> Self {
+        Self {
+            droplets_per_cell: 1.0,
+            max_steps: 64,
+            inertia: 0.05,
+            capacity: 2.0,
+            min_slope: 0.01,
+            erosion: 0.1,
+            deposition: 0.3,
+            evaporation: 0.02,
+            gravity: 4.0,
+        }
+    }
+}
+
+/// Talus slumping: material slides off any step steeper than `talus` voxels per column.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThermalConfig {
+    pub iterations: u32,
+    pub talus: f32,
+    /// Fraction of the excess moved per iteration.
+    pub rate: f32,
+}
+
+impl Default for ThermalConfig {
+    fn default() -> Self {
+        Self {
+            iterations: 30,
+            talus: 1.5,
+            rate: 0.5,
+        }
+    }
+}
+
+/// Surface heights of a rectangle of columns, row-major by Z.
+#[derive(Debug, Clone)]
+pub struct Heightfield {
+    pub origin_x: i32,
+    pub origin_z: i32,
+    pub width: usize,
+    pub depth: usize,
+    pub heights: Vec<f32>,
+}
+
+impl Heightfield {
+    /// Highest solid voxel with air above it for every column, searched between
+    /// `min_y` and `max_y` (clamped to those when a column is all solid or all air).
+    pub fn from_generator<G: Generator + ?Sized>(
+        generator: &G,
+        origin_x: i32,
+        origin_z: i32,
+        width: usize,
+        depth: usize,
+        min_y: i32,
+        max_y: i32,
+    ) -> Self {
+        let threshold = generator.config().density_threshold;
+        let solid = |x: i32, y: i32, z: i32| generator.generate_density(x as f32, y as f32, z as f32) > threshold;
+        let heights = (0..width * depth)
+            .into_par_iter()
+            .map(|i| {
+                let (x, z) = (origin_x + (i % width) as i32, origin_z + (i / width) as i32);
+                // Coarse steps down to the first solid sample, then refine upwards
+                const STEP: i32 = 4;
+                let mut y = max_y;
+                while y >= min_y && !solid(x, y, z) {
+                    y -= STEP;
+                }
+                if y < min_y {
+                    return min_y as f32;
+                }
+                while y < max_y && solid(x, y + 1, z) {
+                    y += 1;
+                }
+                y as f32
+            })
+            .collect();
+        Self {
+            origin_x,
+            origin_z,
+            width,
+            depth,
+            heights,
+        }
+    }
+
+    fn at(&self, x: usize, z: usize) -> f32 {
+        self.heights[z * self.width + x]
+    }
+
+    /// Bilinear sample at world XZ, clamped to the edges.
+    pub fn sample(&self, wx: f32, wz: f32) -> f32 {
+        let u = (wx - self.origin_x as f32).clamp(0.0, (self.width - 1) as f32);
+        let v = (wz - self.origin_z as f32).clamp(0.0, (self.depth - 1) as f32);
+        let (x0, z0) = (u.floor() as usize, v.floor() as usize);
+        let (x1, z1) = ((x0 + 1).min(self.width - 1), (z0 + 1).min(self.depth - 1));
+        let (tx, tz) = (u - x0 as f32, v - z0 as f32);
+        let top = self.at(x0, z0) * (1.0 - tx) + self.at(x1, z0) * tx;
+        let bottom = self.at(x0, z1) * (1.0 - tx) + self.at(x1, z1) * tx;
+        top * (1.0 - tz) + bottom * tz
+    }
+}
+
+/// Height and gradient of a local tile at a fractional position.
+fn height_gradient(h: &[f32], w: usize, x: f32, z: f32) -> (f32, f32, f32) {
+    let (xi, zi) = (x as usize, z as usize);
+    let (u, v) = (x - xi as f32, z - zi as f32);
+    let i = zi * w + xi;
+    let (nw, ne, sw, se) = (h[i], h[i + 1], h[i + w], h[i + w + 1]);
+    let gx = (ne - nw) * (1.0 - v) + (se - sw) * v;
+    let gz = (sw - nw) * (1.0 - u) + (se - ne) * u;
+    let height = nw * (1.0 - u) * (1.0 - v) + ne * u * (1.0 - v) + sw * (1.0 - u) * v + se * u * v;
+    (height, gx, gz)
+}
+
+/// Runs droplets over a `w x d` region, starting inside the `size` columns at `start`.
+/// Droplets stop at the region border.
+fn erode_tile(
+    h: &mut [f32],
+    w: usize,
+    d: usize,
+    start: (usize, usize),
+    size: (usize, usize),
+    cfg: &HydraulicConfig,
+    rng: &mut HashRng,
+) {
+    if w < 3 || d < 3 {
+        return;
+    }
+    let (x_end, z_end) = ((start.0 + size.0).min(w - 1), (start.1 + size.1).min(d - 1));
+    if x_end <= start.0 || z_end <= start.1 {
+        return;
+    }
+    let droplets = (cfg.droplets_per_cell * (size.0 * size.1) as f32) as u32;
+    for _ in 0..droplets {
+        let mut x = rng.range(start.0 as f32, x_end as f32);
+        let mut z = rng.range(start.1 as f32, z_end as f32);
+        let (mut dx, mut dz) = (0.0f32, 0.0f32);
+        let (mut speed, mut water, mut sediment) = (1.0f32, 1.0f32, 0.0f32);
+
+        for _ in 0..cfg.max_steps {
+            let (xi, zi) = (x as usize, z as usize);
+            let (u, v) = (x - xi as f32, z - zi as f32);
+            let (height, gx, gz) = height_gradient(h, w, x, z);
+
+            dx = dx * cfg.inertia - gx * (1.0 - cfg.inertia);
+            dz = dz * cfg.inertia - gz * (1.0 - cfg.inertia);
+            let len = (dx * dx + dz * dz).sqrt();
+            if len < 1e-6 {
+                break;
+            }
+            dx /= len;
+            dz /= len;
+            let (nx, nz) = (x + dx, z + dz);
+            if nx < 0.0 || nz < 0.0 || nx >= (w - 1) as f32 || nz >= (d - 1) as f32 {
+                break;
+            }
+
+            let delta = height_gradient(h, w, nx, nz).0 - height;
+            let capacity = (-delta).max(cfg.min_slope) * speed * water * cfg.capacity;
+            let i = zi * w + xi;
+            let corners = [
+                (i, (1.0 - u) * (1.0 - v)),
+                (i + 1, u * (1.0 - v)),
+                (i + w, (1.0 - u) * v),
+                (i + w + 1, u * v),
+            ];
+
+            if sediment > capacity || delta > 0.0 {
+                // Uphill: fill the pit behind us; otherwise drop the excess
+                let amount = if delta > 0.0 {
+                    delta.min(sediment)
+                } else {
+                    (sediment - capacity) * cfg.deposition
+                };
+                sediment -= amount;
+                for (c, weight) in corners {
+                    h[c] += amount * weight;
+                }
+            } else {
+                let amount = ((capacity - sediment) * cfg.erosion).min(-delta);
+                for (c, weight) in corners {
+                    h[c] -= amount * weight;
+                    sediment += amount * weight;
+                }
+            }
+
+            speed = (speed * speed + delta * -cfg.gravity).max(0.0).sqrt();
+            water *= 1.0 - cfg.evaporation;
+            x = nx;
+            z = nz;
+        }
+    }
+}
+
+fn hydraulic(field: &mut Heightfield, cfg: &HydraulicConfig, tile: usize, seed: u32) {
+    let (w, d) = (field.width, field.depth);
+    let tiles_x = w.div_ceil(tile);
+    let tiles_z = d.div_ceil(tile);
+
+    // Four passes over a checkerboard of tiles. Droplets start in their tile but may run on
+    // for `halo` columns into its neighbours, so gullies carry on across tile borders. Two
+    // tiles of one pass are a whole tile apart, so their reach never overlaps and they run in
+    // parallel; the fixed pass order keeps the result independent of scheduling.
+    let halo = tile / 2;
+    for pass in 0..4 {
+        let tiles: Vec<(usize, usize)> = (0..tiles_z)
+            .flat_map(|tz| (0..tiles_x).map(move |tx| (tx, tz)))
+            .filter(|&(tx, tz)| (tx % 2) + 2 * (tz % 2) == pass)
+            .collect();
+        let results: Vec<_> = tiles
+            .par_iter()
+            .map(|&(tx, tz)| {
+                let (tile_x, tile_z) = (tx * tile, tz * tile);
+                let size = (tile.min(w - tile_x), tile.min(d - tile_z));
+                let (x0, z0) = (tile_x.saturating_sub(halo), tile_z.saturating_sub(halo));
+                let (tw, td) = ((tile_x + size.0 + halo).min(w) - x0, (tile_z + size.1 + halo).min(d) - z0);
+                let mut local = Vec::with_capacity(tw * td);
+                for z in z0..z0 + td {
+                    local.extend_from_slice(&field.heights[z * w + x0..z * w + x0 + tw]);
+                }
+                let mut rng = HashRng::new(tx as i32, pass as i32, tz as i32, seed);
+                erode_tile(&mut local, tw, td, (tile_x - x0, tile_z - z0), size, cfg, &mut rng);
+                ((x0, z0), tw, td, local)
+            })
+            .collect();
+        for ((x0, z0), tw, td, local) in results {
+            for z in 0..td {
+                field.heights[(z0 + z) * w + x0..(z0 + z) * w + x0 + tw].copy_from_slice(&local[z * tw..(z + 1) * tw]);
+            }
+        }
+    }
+}
+
+fn thermal(field: &mut Heightfield, cfg: &ThermalConfig) {
+    let (w, d) = (field.width, field.depth);
+    const NEIGHBOURS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+    for _ in 0..cfg.iterations {
+        let h = &field.heights;
+        // Each column gains from steeper higher neighbours and loses to lower ones; computed
+        // from the previous state only, so rows are independent.
+        let next: Vec<f32> = (0..w * d)
+            .into_par_iter()
+            .map(|i| {
+                let (x, z) = ((i % w) as i32, (i / w) as i32);
+                let mut change = 0.0;
+                for (ox, oz) in NEIGHBOURS {
+                    let (nx, nz) = (x + ox, z + oz);
+                    if nx < 0 || nz < 0 || nx >= w as i32 || nz >= d as i32 {
+                        continue;
+                    }
+                    let diff = h[nz as usize * w + nx as usize] - h[i];
+                    if diff.abs() > cfg.talus {
+                        // Split between the two columns, and across up to four neighbours
+                        change += diff.signum() * (diff.abs() - cfg.talus) * cfg.rate * 0.125;
+                    }
+                }
+                h[i] + change
+            })
+            .collect();
+        field.heights = next;
+    }
+}
+
+/// Erodes a heightfield in place: hydraulic droplets first, then thermal slumping.
+pub fn erode(field: &mut Heightfield, cfg: &ErosionSimConfig, seed: u32) {
+    let seed = seed.wrapping_add(cfg.seed_offset).wrapping_add(hash_u32(0xE205, 0));
+    if let Some(hydraulic_cfg) = &cfg.hydraulic {
+        hydraulic(field, hydraulic_cfg, cfg.tile_size.max(8), seed);
+    }
+    if let Some(thermal_cfg) = &cfg.thermal {
+        thermal(field, thermal_cfg);
+    }
+}
+
+/// Wraps a generator and shifts its terrain vertically by the difference between an eroded
+/// heightfield and the original, so every later stage (materials, caves, structures) sees
+/// the eroded surface.
+pub struct ErodedGenerator<'a, G: Generator + ?Sized> {
+    inner: &'a G,
+    offsets: Heightfield,
+    /// Smallest and largest offset, which bound every interpolated one.
+    offset_range: Interval,
+}
+
+impl<G: Generator + ?Sized> Generator for ErodedGenerator<'_, G> {
+    fn config(&self) -> &MapGenConfig {
+        self.inner.config()
+    }
+
+    fn biomes(&self) -> Option<&BiomeMap> {
+        self.inner.biomes()
+    }
+
+    fn generate_density(&self, wx: f32, wy: f32, wz: f32) -> f32 {
+        let offset = self.offsets.sample(wx, wz);
+        self.inner.generate_density(wx, wy - offset, wz)
+    }
+
+    fn generate_column(&self, wx: f32, wz: f32, ys: &[f32], out: &mut [f32]) {
+        let offset = self.offsets.sample(wx, wz);
+        let shifted: Vec<f32> = ys.iter().map(|wy| wy - offset).collect();
+        self.inner.generate_column(wx, wz, &shifted, out);
+    }
+
+    fn generate_density_and_gradient(&self, wx: f32, wy: f32, wz: f32) -> (f32, Vec3) {
+        const H: f32 = 0.05;
+        let offset = self.offsets.sample(wx, wz);
+        let (density, g) = self.inner.generate_density_and_gradient(wx, wy - offset, wz);
+        // Chain rule through the vertical shift
+        let ox = (self.offsets.sample(wx + H, wz) - self.offsets.sample(wx - H, wz)) / (2.0 * H);
+        let oz = (self.offsets.sample(wx, wz + H) - self.offsets.sample(wx, wz - H)) / (2.0 * H);
+        (density, Vec3::new(g.x - g.y * ox, g.y, g.z - g.y * oz))
+    }
+
+    fn density_bounds(&self, lo: Vec3, hi: Vec3) -> Option<Interval> {
+        let Interval { lo: down, hi: up } = self.offset_range;
+        self.inner.density_bounds(lo - Vec3::Y * up, hi - Vec3::Y * down)
+    }
+}
+
+/// Extracts the heightfield for the chunk range `min..=max` (plus the configured margin),
+/// erodes it and returns a generator with the result applied.
+pub fn erode_region<'a, G: Generator + ?Sized>(
+    generator: &'a G,
+    cfg: &ErosionSimConfig,
+    min: IVec3,
+    max: IVec3,
+) -> ErodedGenerator<'a, G> {
+    let cs = CS as i32;
+    // One extra column on each side covers the padding ring
+    let lo = min * cs - IVec3::splat(cfg.margin + 1);
+    let hi = (max + IVec3::ONE) * cs + IVec3::splat(cfg.margin + 1);
+    let (width, depth) = ((hi.x - lo.x) as usize, (hi.z - lo.z) as usize);
+
+    let original = Heightfield::from_generator(generator, lo.x, lo.z, width, depth, lo.y, hi.y);
+    let mut eroded = original.clone();
+    erode(&mut eroded, cfg, generator.config().seed);
+
+    let mut offsets = eroded;
+    for (o, h) in offsets.heights.iter_mut().zip(&original.heights) {
+        *o -= h;
+    }
+    let offset_range = Interval::new(
+        offsets.heights.iter().copied().fold(0.0, f32::min),
+        offsets.heights.iter().copied().fold(0.0, f32::max),
+    );
+    ErodedGenerator {
+        inner: generator,
+        offsets,
+        offset_range,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(width: usize, depth: usize, height: impl Fn(usize, usize) -> f32) -> Heightfield {
+        Heightfield {
+            origin_x: 0,
+            origin_z: 0,
+            width,
+            depth,
+            heights: (0..width * depth).map(|i| height(i % width, i / width)).collect(),
+        }
+    }
+
+    #[test]
+    fn thermal_slumping_spreads_a_spike() {
+        let mut spike = field(5, 5, |x, z| if (x, z) == (2, 2) { 10.0 } else { 0.0 });
+        let cfg = ErosionSimConfig {
+            tile_size: 64,
+            margin: 0,
+            seed_offset: 0,
+            hydraulic: None,
+            thermal: Some(ThermalConfig { iterations: 1, talus: 1.0, rate: 0.5 }),
+        };
+        erode(&mut spike, &cfg, 0);
+        // Each of the four steps moves (10 - 1) * 0.5 / 8 = 0.5625 downhill
+        assert_eq!(spike.at(2, 2), 7.75);
+        for (x, z) in [(1, 2), (3, 2), (2, 1), (2, 3)] {
+            assert_eq!(spike.at(x, z), 0.5625);
+        }
+        assert_eq!(spike.at(1, 1), 0.0);
+        assert_eq!(spike.heights.iter().sum::<f32>(), 10.0);
+    }
+
+    #[test]
+    fn droplets_carve_a_slope_the_same_way_every_time() {
+        // A ramp falling along X with a shallow valley along its middle
+        let ramp = field(96, 80, |x, z| 60.0 - x as f32 * 0.5 + (z as f32 - 40.0).abs() * 0.2);
+        let cfg = ErosionSimConfig {
+            tile_size: 32,
+            margin: 0,
+            seed_offset: 0,
+            hydraulic: Some(HydraulicConfig::default()),
+            thermal: None,
+        };
+        let run = |seed| {
+            let mut eroded = ramp.clone();
+            erode(&mut eroded, &cfg, seed);
+            eroded.heights
+        };
+        let eroded = run(7);
+        assert_eq!(eroded, run(7));
+        assert_ne!(eroded, run(8));
+
+        // Droplets only deposit what they picked up, and carry some off the edges
+        let (before, after): (f32, f32) = (ramp.heights.iter().sum(), eroded.iter().sum());
+        assert!(after < before, "{after} >= {before}");
+        let lowered = eroded.iter().zip(&ramp.heights).filter(|(e, h)| e < h).count();
+        assert!(lowered > eroded.len() / 4, "only {lowered} columns lowered");
+        // The valley floor erodes more than the slopes beside it
+        let dug = |z: usize| (10..86).map(|x| ramp.at(x, z) - eroded[z * 96 + x]).sum::<f32>();
+        assert!(dug(40) > dug(20) && dug(40) > dug(60), "{} vs {} and {}", dug(40), dug(20), dug(60));
+    }
+}
@@ -1,65 +1,161 @@
-use super::config::MapGenConfig;
-use super::noise::{fbm3d, ridged_fbm3d, smoothstep};
-use glam::IVec3;
-use crate::CS;
+use super::biome::{BiomeConfig, BiomeMap, BiomeModifiers};
+use super::caves::CaveCarver;
+use super::interval::Interval;
+use super::spline::{ChannelBounds, ChannelGradients, NoiseChannels};
+use super::ores::place_ores;
+use super::structures::place_structures;
+use super::sampling::{lattice_spacing, DensityLattice};
+use super::surface;
+use super::config::{LayerConfig, LayerKind, MapGenConfig, NoiseLayer};
+use super::registry::GeneratorRegistry;
+use super::noise::{
+    fbm3d, fbm3d_bounds, fbm3d_deriv, fbm3d_lanes, ridged_fbm3d, ridged_fbm3d_bounds, ridged_fbm3d_deriv,
+    ridged_fbm3d_lanes, smoothstep, smoothstep_deriv, worley2d, worley3d, CellularReturn, Lanes, LANES,
+};
+use anyhow::Result;
+use glam::{IVec3, Vec3};
+use crate::{get_zxy_index, CS, CS_P3};
 
 pub trait Generator: Send + Sync {
     fn generate_density(&self, wx: f32, wy: f32, wz: f32) -> f32;
     fn config(&self) -> &MapGenConfig;
+
+    /// Biomes used for surface materials not covered by `surface_rules`.
+    fn biomes(&self) -> Option<&BiomeMap> {
+        None
+    }
+
+    /// Densities of the column at (`wx`, `wz`) for each of `ys`, written to `out`.
+    /// Generators override this to compute column-level quantities (biome modifiers,
+    /// planar layers) once; the results must equal `generate_density`'s.
+    fn generate_column(&self, wx: f32, wz: f32, ys: &[f32], out: &mut [f32]) {
+        for (density, &wy) in out.iter_mut().zip(ys) {
+            *density = self.generate_density(wx, wy, wz);
+        }
+    }
+
+    /// Density and its gradient with respect to world position; the surface normal is
+    /// `-gradient`. The default takes central differences, generators override it with
+    /// analytic noise derivatives where they can. The density matches `generate_density`
+    /// up to float rounding.
+    fn generate_density_and_gradient(&self, wx: f32, wy: f32, wz: f32) -> (f32, Vec3) {
+        let gradient = central_differences(|x, y, z| self.generate_density(x, y, z), wx, wy, wz);
+        (self.generate_density(wx, wy, wz), gradient)
+    }
+
+    /// Range the density can take anywhere in the world box `lo..=hi`, or `None` when the
+    /// generator can't bound it. Boxes entirely on one side of `density_threshold` are
+    /// generated without evaluating the density, so the bounds must hold everywhere.
+    fn density_bounds(&self, _lo: Vec3, _hi: Vec3) -> Option<Interval> {
+        None
+    }
+}
+
+/// Gradient of `f` by central differences a twentieth of a voxel to either side.
+pub fn central_differences(f: impl Fn(f32, f32, f32) -> f32, wx: f32, wy: f32, wz: f32) -> Vec3 {
+    const H: f32 = 0.05;
+    Vec3::new(
+        f(wx + H, wy, wz) - f(wx - H, wy, wz),
+        f(wx, wy + H, wz) - f(wx, wy - H, wz),
+        f(wx, wy, wz + H) - f(wx, wy, wz - H),
+    ) / (2.0 * H)
 }
 
 pub struct MultiNoiseGenerator {
     config: MapGenConfig,
     erosion_flattens: f32,
+    biomes: Option<BiomeMap>,
+    column_channels: bool,
+}
+
+/// Inputs of `MultiNoiseGenerator` that only depend on the column: biome modifiers and,
+/// with `[sampling] column_channels`, raw continentalness and erosion.
+struct MultiNoiseColumn {
+    biome: BiomeModifiers,
+    channels: Option<(f32, f32)>,
+}
+
+fn channel_raw(layer: &NoiseLayer, wx: f32, wy: f32, wz: f32, seed: u32, ridged: bool) -> f32 {
+    let (x, y, z) = layer.coords(wx, wy, wz, seed);
+    let sample = if ridged { ridged_fbm3d } else { fbm3d };
+    sample(layer.basis, x, y, z, seed, layer.octaves, layer.lacunarity, layer.gain)
+}
+
+/// `channel_raw` at `LANES` heights of one column.
+fn channel_raw_lanes(layer: &NoiseLayer, wx: f32, wys: &Lanes, wz: f32, seed: u32, ridged: bool) -> Lanes {
+    let coords: [(f32, f32, f32); LANES] = wys.map(|wy| layer.coords(wx, wy, wz, seed));
+    let (x, y, z) = (coords.map(|c| c.0), coords.map(|c| c.1), coords.map(|c| c.2));
+    let sample = if ridged { ridged_fbm3d_lanes } else { fbm3d_lanes };
+    sample(layer.basis, &x, &y, &z, seed, layer.octaves, layer.lacunarity, layer.gain)
+}
+
+// Evaluates `ys` `LANES` at a time into `out`; a short last batch repeats its final height.
+fn column_in_lanes(ys: &[f32], out: &mut [f32], mut batch: impl FnMut(&Lanes) -> Lanes) {
+    for (ys, out) in ys.chunks(LANES).zip(out.chunks_mut(LANES)) {
+        let wys: Lanes = std::array::from_fn(|l| ys[l.min(ys.len() - 1)]);
+        let densities = batch(&wys);
+        out.copy_from_slice(&densities[..out.len()]);
+    }
 }
 
 impl MultiNoiseGenerator {
     pub fn new(config: MapGenConfig) -> Self {
+        let biomes = BiomeMap::new(&config);
+        let column_channels = config.sampling.as_ref().is_some_and(|s| s.column_channels);
         Self {
             config,
             erosion_flattens: 0.85,
+            biomes,
+            column_channels,
         }
     }
-}
 
-impl Generator for MultiNoiseGenerator {
-    fn config(&self) -> &MapGenConfig {
-        &self.config
+    fn column(&self, wx: f32, wz: f32) -> MultiNoiseColumn {
+        let cfg = &self.config;
+        let channels = self.column_channels.then(|| {
+            (
+                channel_raw(&cfg.continentalness, wx, 0.0, wz, cfg.seed + 11, false),
+                channel_raw(&cfg.erosion, wx, 0.0, wz, cfg.seed + 23, false),
+            )
+        });
+        MultiNoiseColumn {
+            biome: biome_modifiers(self.biomes.as_ref(), wx, wz),
+            channels,
+        }
     }
 
-    fn generate_density(&self, wx: f32, wy: f32, wz: f32) -> f32 {
+    fn density_in_column(&self, column: &MultiNoiseColumn, wx: f32, wy: f32, wz: f32) -> f32 {
         let cfg = &self.config;
 
         // Sample each layer
-        let cont_raw = fbm3d(
-            wx / cfg.continentalness.scale,
-            wy / cfg.continentalness.scale,
-            wz / cfg.continentalness.scale,
-            cfg.seed + 11,
-            cfg.continentalness.octaves,
-            cfg.continentalness.lacunarity,
-            cfg.continentalness.gain,
-        );
-
-        let eros_raw = fbm3d(
-            wx / cfg.erosion.scale,
-            wy / cfg.erosion.scale,
-            wz / cfg.erosion.scale,
-            cfg.seed + 23,
-            cfg.erosion.octaves,
-            cfg.erosion.lacunarity,
-            cfg.erosion.gain,
-        );
-
-        let pv_ridged = ridged_fbm3d(
-            wx / cfg.peaks_valleys.scale,
-            wy / cfg.peaks_valleys.scale,
-            wz / cfg.peaks_valleys.scale,
-            cfg.seed + 37,
-            cfg.peaks_valleys.octaves,
-            cfg.peaks_valleys.lacunarity,
-            cfg.peaks_valleys.gain,
-        );
+        let (cont_raw, eros_raw) = column.channels.unwrap_or_else(|| {
+            (
+                channel_raw(&cfg.continentalness, wx, wy, wz, cfg.seed + 11, false),
+                channel_raw(&cfg.erosion, wx, wy, wz, cfg.seed + 23, false),
+            )
+        });
+        let pv_ridged = channel_raw(&cfg.peaks_valleys, wx, wy, wz, cfg.seed + 37, true);
+        self.shape_density(column, cont_raw, eros_raw, pv_ridged, wy)
+    }
+
+    /// `density_in_column` for `LANES` heights, with the noise sampled in lanes.
+    fn density_in_column_lanes(&self, column: &MultiNoiseColumn, wx: f32, wys: &Lanes, wz: f32) -> Lanes {
+        let cfg = &self.config;
+        let (cont_raw, eros_raw) = match column.channels {
+            Some((cont, eros)) => ([cont; LANES], [eros; LANES]),
+            None => (
+                channel_raw_lanes(&cfg.continentalness, wx, wys, wz, cfg.seed + 11, false),
+                channel_raw_lanes(&cfg.erosion, wx, wys, wz, cfg.seed + 23, false),
+            ),
+        };
+        let pv_ridged = channel_raw_lanes(&cfg.peaks_valleys, wx, wys, wz, cfg.seed + 37, true);
+        std::array::from_fn(|l| self.shape_density(column, cont_raw[l], eros_raw[l], pv_ridged[l], wys[l]))
+    }
+
+    /// Density at height `wy` from the raw channel noise.
+    fn shape_density(&self, column: &MultiNoiseColumn, cont_raw: f32, eros_raw: f32, pv_ridged: f32, wy: f32) -> f32 {
+        let cfg = &self.config;
+        let biome = column.biome;
 
         // Normalize/remap (approximate)
         let cont = cont_raw.clamp(-2.0, 2.0) / 2.0; // ~[-1,1]
@@ -68,6 +164,19 @@ impl Generator for MultiNoiseGenerator {
 
         // Convert ridges to peaks+valleys
         let pv_centered = pv01 * 2.0 - 1.0;
+
+        if let Some(shaping) = &cfg.shaping {
+            let channels = NoiseChannels {
+                continentalness: cont,
+                erosion: eros01 * 2.0 - 1.0,
+                peaks_valleys: pv_centered,
+            };
+            let offset = shaping.offset.eval(&channels);
+            let factor = shaping.factor.eval(&channels);
+            let jaggedness = shaping.jaggedness.eval(&channels);
+            return factor * (offset + jaggedness * pv_centered - wy) + biome.offset;
+        }
+
         let pv = pv_centered.signum() * pv_centered.abs().powf(1.35);
 
         // Landmask from continentalness
@@ -77,63 +186,709 @@ impl Generator for MultiNoiseGenerator {
         // Erosion flattens peaks
         let peak_amp = 1.0 - self.erosion_flattens * eros01;
 
-        // Vertical gradient (Y is up)
-        let ynorm = ((wy - cfg.vertical.min_y) / (cfg.vertical.max_y - cfg.vertical.min_y))
-            .clamp(0.0, 1.0);
-        let vertical = (1.0 - ynorm) * cfg.vertical.bias;
+        let vertical = vertical_gradient(cfg, wy);
 
         // Stack
-        let density = cfg.weights.continentalness * cont
-            + cfg.weights.peaks_valleys * (pv * landmask * peak_amp)
-            - cfg.weights.erosion * (eros01 * 0.5)
-            + vertical;
+        let density = cfg.weights.continentalness * biome.continentalness * cont
+            + cfg.weights.peaks_valleys * biome.peaks_valleys * (pv * landmask * peak_amp)
+            - cfg.weights.erosion * biome.erosion * (eros01 * 0.5)
+            + vertical
+            + biome.offset;
 
         density
     }
 }
 
-// Helper function for generating chunk voxels
-pub fn generate_chunk_voxels<G: Generator>(
+fn biome_modifiers(biomes: Option<&BiomeMap>, wx: f32, wz: f32) -> BiomeModifiers {
+    biomes.map_or_else(BiomeModifiers::default, |b| b.modifiers(wx, wz))
+}
+
+/// Range of one biome modifier field over the whole world.
+fn modifier_bounds(biomes: Option<&BiomeMap>, field: fn(&BiomeModifiers) -> f32) -> Interval {
+    biomes.map_or_else(|| Interval::point(field(&BiomeModifiers::default())), |b| b.modifier_bounds(field))
+}
+
+/// Range of `vertical_gradient` over world heights `y`; it is monotonic in Y.
+fn vertical_bounds(cfg: &MapGenConfig, y: Interval) -> Interval {
+    y.map_monotonic(|wy| vertical_gradient(cfg, wy))
+}
+
+/// Biome modifiers and, with biomes, their slopes along X and Z.
+fn biome_modifiers_slope(
+    biomes: Option<&BiomeMap>,
+    wx: f32,
+    wz: f32,
+) -> (BiomeModifiers, Option<(BiomeModifiers, BiomeModifiers)>) {
+    match biomes {
+        Some(b) => {
+            let (m, dx, dz) = b.modifiers_slope(wx, wz);
+            (m, Some((dx, dz)))
+        }
+        None => (BiomeModifiers::default(), None),
+    }
+}
+
+/// Gradient of one modifier field from `biome_modifiers_slope`'s slopes.
+fn modifier_gradient(slopes: Option<(BiomeModifiers, BiomeModifiers)>, field: fn(&BiomeModifiers) -> f32) -> Vec3 {
+    slopes.map_or(Vec3::ZERO, |(dx, dz)| Vec3::new(field(&dx), 0.0, field(&dz)))
+}
+
+/// Unwarped fbm (or ridged fbm) of a noise layer with its gradient in world units.
+fn layer_deriv(layer: &NoiseLayer, wx: f32, wy: f32, wz: f32, seed: u32, ridged: bool) -> (f32, Vec3) {
+    let (x, y, z) = layer.coords(wx, wy, wz, seed);
+    let sample = if ridged { ridged_fbm3d_deriv } else { fbm3d_deriv };
+    let (n, d) = sample(layer.basis, x, y, z, seed, layer.octaves, layer.lacunarity, layer.gain);
+    (n, d / layer.scale)
+}
+
+// 1 inside the open range, 0 where a clamp to it cuts the slope off
+fn clamp_slope(v: f32, lo: f32, hi: f32) -> f32 {
+    if v > lo && v < hi { 1.0 } else { 0.0 }
+}
+
+impl Generator for MultiNoiseGenerator {
+    fn config(&self) -> &MapGenConfig {
+        &self.config
+    }
+
+    fn biomes(&self) -> Option<&BiomeMap> {
+        self.biomes.as_ref()
+    }
+
+    fn generate_density(&self, wx: f32, wy: f32, wz: f32) -> f32 {
+        self.density_in_column(&self.column(wx, wz), wx, wy, wz)
+    }
+
+    fn generate_column(&self, wx: f32, wz: f32, ys: &[f32], out: &mut [f32]) {
+        let column = self.column(wx, wz);
+        column_in_lanes(ys, out, |wys| self.density_in_column_lanes(&column, wx, wys, wz));
+    }
+
+    // Mirrors `generate_density` step by step with the chain rule.
+    fn generate_density_and_gradient(&self, wx: f32, wy: f32, wz: f32) -> (f32, Vec3) {
+        let cfg = &self.config;
+        // Warped coordinates would need the warp's Jacobian
+        if cfg.continentalness.warp.is_some() || cfg.erosion.warp.is_some() || cfg.peaks_valleys.warp.is_some() {
+            let gradient = central_differences(|x, y, z| self.generate_density(x, y, z), wx, wy, wz);
+            return (self.generate_density(wx, wy, wz), gradient);
+        }
+        let (biome, slopes) = biome_modifiers_slope(self.biomes.as_ref(), wx, wz);
+
+        let channel_y = if self.column_channels { 0.0 } else { wy };
+        let (cont_raw, mut d_cont_raw) = layer_deriv(&cfg.continentalness, wx, channel_y, wz, cfg.seed + 11, false);
+        let (eros_raw, mut d_eros_raw) = layer_deriv(&cfg.erosion, wx, channel_y, wz, cfg.seed + 23, false);
+        if self.column_channels {
+            d_cont_raw.y = 0.0;
+            d_eros_raw.y = 0.0;
+        }
+        let (pv_ridged, d_pv_ridged) = layer_deriv(&cfg.peaks_valleys, wx, wy, wz, cfg.seed + 37, true);
+
+        let cont = cont_raw.clamp(-2.0, 2.0) / 2.0;
+        let d_cont = d_cont_raw * (0.5 * clamp_slope(cont_raw, -2.0, 2.0));
+        let eros01 = (eros_raw.clamp(-2.0, 2.0) / 4.0 + 0.5).clamp(0.0, 1.0);
+        let d_eros01 = d_eros_raw * (0.25 * clamp_slope(eros_raw, -2.0, 2.0));
+        let pv01 = (pv_ridged / 4.0).clamp(0.0, 1.0);
+        let d_pv01 = d_pv_ridged * (0.25 * clamp_slope(pv_ridged, 0.0, 4.0));
+        let pv_centered = pv01 * 2.0 - 1.0;
+        let d_pv_centered = d_pv01 * 2.0;
+        let d_biome_offset = modifier_gradient(slopes, |m| m.offset);
+
+        if let Some(shaping) = &cfg.shaping {
+            let channels = NoiseChannels {
+                continentalness: cont,
+                erosion: eros01 * 2.0 - 1.0,
+                peaks_valleys: pv_centered,
+            };
+            let gradients = ChannelGradients {
+                continentalness: d_cont,
+                erosion: d_eros01 * 2.0,
+                peaks_valleys: d_pv_centered,
+            };
+            let (offset, d_offset) = shaping.offset.eval_gradient(&channels, &gradients);
+            let (factor, d_factor) = shaping.factor.eval_gradient(&channels, &gradients);
+            let (jaggedness, d_jaggedness) = shaping.jaggedness.eval_gradient(&channels, &gradients);
+            let height = offset + jaggedness * pv_centered - wy;
+            let d_height = d_offset + d_jaggedness * pv_centered + d_pv_centered * jaggedness - Vec3::Y;
+            let density = factor * height + biome.offset;
+            return (density, d_factor * height + d_height * factor + d_biome_offset);
+        }
+
+        let pv = pv_centered.signum() * pv_centered.abs().powf(1.35);
+        let d_pv = d_pv_centered * (1.35 * pv_centered.abs().powf(0.35));
+
+        let cont01 = (cont + 1.0) / 2.0;
+        let landmask = smoothstep(0.45, 0.65, cont01);
+        let d_landmask = d_cont * (0.5 * smoothstep_deriv(0.45, 0.65, cont01));
+
+        let peak_amp = 1.0 - self.erosion_flattens * eros01;
+        let d_peak_amp = d_eros01 * -self.erosion_flattens;
+
+        let w = &cfg.weights;
+        let peaks = pv * landmask * peak_amp;
+        let d_peaks = d_pv * (landmask * peak_amp) + d_landmask * (pv * peak_amp) + d_peak_amp * (pv * landmask);
+
+        let density = w.continentalness * biome.continentalness * cont
+            + w.peaks_valleys * biome.peaks_valleys * peaks
+            - w.erosion * biome.erosion * (eros01 * 0.5)
+            + vertical_gradient(cfg, wy)
+            + biome.offset;
+        let gradient = d_cont * (w.continentalness * biome.continentalness)
+            + d_peaks * (w.peaks_valleys * biome.peaks_valleys)
+            - d_eros01 * (w.erosion * biome.erosion * 0.5)
+            + Vec3::Y * vertical_gradient_slope(cfg, wy)
+            + modifier_gradient(slopes, |m| m.continentalness) * (w.continentalness * cont)
+            + modifier_gradient(slopes, |m| m.peaks_valleys) * (w.peaks_valleys * peaks)
+            - modifier_gradient(slopes, |m| m.erosion) * (w.erosion * eros01 * 0.5)
+            + d_biome_offset;
+        (density, gradient)
+    }
+
+    // Mirrors `generate_density` with the noise replaced by its range at any position, so
+    // only the height term narrows with the box.
+    fn density_bounds(&self, lo: Vec3, hi: Vec3) -> Option<Interval> {
+        let cfg = &self.config;
+        let modifier = |field| modifier_bounds(self.biomes.as_ref(), field);
+        let (pv_layer, y) = (&cfg.peaks_valleys, Interval::new(lo.y, hi.y));
+        let cont_raw = fbm3d_bounds(cfg.continentalness.basis, cfg.continentalness.octaves, cfg.continentalness.gain);
+        let eros_raw = fbm3d_bounds(cfg.erosion.basis, cfg.erosion.octaves, cfg.erosion.gain);
+        let pv_ridged = ridged_fbm3d_bounds(pv_layer.basis, pv_layer.octaves, pv_layer.gain);
+
+        let cont = cont_raw.clamp(-2.0, 2.0) * 0.5;
+        let eros01 = (eros_raw.clamp(-2.0, 2.0) * 0.25 + 0.5).clamp(0.0, 1.0);
+        let pv_centered = (pv_ridged * 0.25).clamp(0.0, 1.0) * 2.0 + -1.0;
+
+        if let Some(shaping) = &cfg.shaping {
+            let ranges = ChannelBounds {
+                continentalness: cont,
+                erosion: eros01 * 2.0 + -1.0,
+                peaks_valleys: pv_centered,
+            };
+            let height = shaping.offset.bounds(&ranges) + shaping.jaggedness.bounds(&ranges) * pv_centered - y;
+            return Some(shaping.factor.bounds(&ranges) * height + modifier(|m| m.offset));
+        }
+
+        let pv = pv_centered.map_monotonic(|v| v.signum() * v.abs().powf(1.35));
+        let landmask = ((cont + 1.0) * 0.5).map_monotonic(|v| smoothstep(0.45, 0.65, v));
+        let peak_amp = eros01 * -self.erosion_flattens + 1.0;
+
+        let w = &cfg.weights;
+        Some(
+            modifier(|m| m.continentalness) * cont * w.continentalness
+                + modifier(|m| m.peaks_valleys) * (pv * landmask * peak_amp) * w.peaks_valleys
+                - modifier(|m| m.erosion) * eros01 * (w.erosion * 0.5)
+                + vertical_bounds(cfg, y)
+                + modifier(|m| m.offset),
+        )
+    }
+}
+
+// Vertical gradient (Y is up): `bias` at `min_y`, fading to 0 at `max_y`.
+fn vertical_gradient(cfg: &MapGenConfig, wy: f32) -> f32 {
+    let ynorm = ((wy - cfg.vertical.min_y) / (cfg.vertical.max_y - cfg.vertical.min_y))
+        .clamp(0.0, 1.0);
+    (1.0 - ynorm) * cfg.vertical.bias
+}
+
+fn vertical_gradient_slope(cfg: &MapGenConfig, wy: f32) -> f32 {
+    let span = cfg.vertical.max_y - cfg.vertical.min_y;
+    -cfg.vertical.bias / span * clamp_slope(wy, cfg.vertical.min_y, cfg.vertical.max_y)
+}
+
+/// Density from an arbitrary `[[layers]]` stack: each layer is sampled, remapped and
+/// weighted, then the sum is added to the usual vertical gradient.
+pub struct LayeredGenerator {
+    config: MapGenConfig,
+    biomes: Option<BiomeMap>,
+}
+
+impl LayeredGenerator {
+    pub fn new(config: MapGenConfig) -> Result<Self> {
+        anyhow::ensure!(!config.layers.is_empty(), "Config has no [[layers]]");
+        for (i, layer) in config.layers.iter().enumerate() {
+            let name = if layer.name.is_empty() { format!("#{i}") } else { layer.name.clone() };
+            anyhow::ensure!(layer.scale > 0.0, "Layer {name} needs a positive scale");
+            if let Some(warp) = &layer.warp {
+                anyhow::ensure!(warp.scale > 0.0, "Layer {name} needs a positive warp scale");
+            }
+            if let Some(remap) = &layer.remap {
+                anyhow::ensure!(remap.from[0] != remap.from[1], "Layer {name} has an empty remap range");
+            }
+        }
+        let biomes = BiomeMap::new(&config);
+        Ok(Self { config, biomes })
+    }
+
+    fn sample_layer(&self, layer: &LayerConfig, wx: f32, wy: f32, wz: f32) -> f32 {
+        let seed = self.config.seed.wrapping_add(layer.seed_offset);
+        let (wx, wy, wz) = match (&layer.warp, layer.planar) {
+            (Some(warp), true) => {
+                let (wx, wz) = warp.warp2d(wx, wz, seed);
+                (wx, 0.0, wz)
+            }
+            (Some(warp), false) => warp.warp3d(wx, wy, wz, seed),
+            (None, true) => (wx, 0.0, wz),
+            (None, false) => (wx, wy, wz),
+        };
+        let (x, y, z) = (wx / layer.scale, wy / layer.scale, wz / layer.scale);
+        let raw = match layer.kind {
+            LayerKind::Fbm => fbm3d(layer.basis, x, y, z, seed, layer.octaves, layer.lacunarity, layer.gain),
+            LayerKind::RidgedFbm => ridged_fbm3d(layer.basis, x, y, z, seed, layer.octaves, layer.lacunarity, layer.gain),
+            LayerKind::Worley => {
+                let cell = if layer.planar {
+                    worley2d(x, z, seed, layer.metric)
+                } else {
+                    worley3d(x, y, z, seed, layer.metric)
+                };
+                cell.value(layer.cellular_return)
+            }
+        };
+        layer.remap.as_ref().map_or(raw, |r| r.apply(raw))
+    }
+
+    fn sample_layer_gradient(&self, layer: &LayerConfig, wx: f32, wy: f32, wz: f32) -> (f32, Vec3) {
+        // Warps would need their Jacobian and worley cells have creases; take this layer's
+        // differences numerically instead
+        if layer.warp.is_some() || layer.kind == LayerKind::Worley {
+            let gradient = central_differences(|x, y, z| self.sample_layer(layer, x, y, z), wx, wy, wz);
+            return (self.sample_layer(layer, wx, wy, wz), gradient);
+        }
+        let seed = self.config.seed.wrapping_add(layer.seed_offset);
+        let wy = if layer.planar { 0.0 } else { wy };
+        let (x, y, z) = (wx / layer.scale, wy / layer.scale, wz / layer.scale);
+        let sample = if layer.kind == LayerKind::RidgedFbm { ridged_fbm3d_deriv } else { fbm3d_deriv };
+        let (raw, mut d_raw) = sample(layer.basis, x, y, z, seed, layer.octaves, layer.lacunarity, layer.gain);
+        d_raw /= layer.scale;
+        if layer.planar {
+            d_raw.y = 0.0;
+        }
+        match &layer.remap {
+            Some(r) => (r.apply(raw), d_raw * r.slope(raw)),
+            None => (raw, d_raw),
+        }
+    }
+
+    /// `sample_layer` at `LANES` heights of one column. Unwarped fbm layers are sampled in
+    /// lanes; warps and worley cells go point by point.
+    fn sample_layer_lanes(&self, layer: &LayerConfig, wx: f32, wys: &Lanes, wz: f32) -> Lanes {
+        let sample = match (&layer.warp, layer.kind) {
+            (None, LayerKind::Fbm) => fbm3d_lanes,
+            (None, LayerKind::RidgedFbm) => ridged_fbm3d_lanes,
+            _ => return wys.map(|wy| self.sample_layer(layer, wx, wy, wz)),
+        };
+        let seed = self.config.seed.wrapping_add(layer.seed_offset);
+        let x = [wx / layer.scale; LANES];
+        let y = wys.map(|wy| (if layer.planar { 0.0 } else { wy }) / layer.scale);
+        let z = [wz / layer.scale; LANES];
+        let raw = sample(layer.basis, &x, &y, &z, seed, layer.octaves, layer.lacunarity, layer.gain);
+        raw.map(|raw| layer.remap.as_ref().map_or(raw, |r| r.apply(raw)))
+    }
+
+    /// Density at height `wy` from the weighted layer sum and the column's biome modifiers.
+    fn stack_density(&self, biome: &BiomeModifiers, layers: f32, wy: f32) -> f32 {
+        layers * biome.layers + vertical_gradient(&self.config, wy) + biome.offset
+    }
+}
+
+/// Range of `sample_layer` at any position, when the layer's raw value is bounded or a
+/// clamped remap bounds it.
+fn layer_bounds(layer: &LayerConfig) -> Option<Interval> {
+    let raw = match layer.kind {
+        LayerKind::Fbm => Some(fbm3d_bounds(layer.basis, layer.octaves, layer.gain)),
+        LayerKind::RidgedFbm => Some(ridged_fbm3d_bounds(layer.basis, layer.octaves, layer.gain)),
+        // Feature distances grow with the metric and jitter; only cell ids have a fixed range
+        LayerKind::Worley => (layer.cellular_return == CellularReturn::CellId).then(|| Interval::new(0.0, 1.0)),
+    };
+    match (&layer.remap, raw) {
+        (Some(r), Some(raw)) => Some(raw.map_monotonic(|v| r.apply(v))),
+        (Some(r), None) if r.clamp => Some(Interval::new(r.to[0], r.to[1])),
+        (_, raw) => raw,
+    }
+}
+
+impl Generator for LayeredGenerator {
+    fn config(&self) -> &MapGenConfig {
+        &self.config
+    }
+
+    fn biomes(&self) -> Option<&BiomeMap> {
+        self.biomes.as_ref()
+    }
+
+    fn generate_density(&self, wx: f32, wy: f32, wz: f32) -> f32 {
+        let biome = biome_modifiers(self.biomes.as_ref(), wx, wz);
+        let layers: f32 = self
+            .config
+            .layers
+            .iter()
+            .map(|layer| layer.weight * self.sample_layer(layer, wx, wy, wz))
+            .sum();
+        self.stack_density(&biome, layers, wy)
+    }
+
+    fn generate_column(&self, wx: f32, wz: f32, ys: &[f32], out: &mut [f32]) {
+        let biome = biome_modifiers(self.biomes.as_ref(), wx, wz);
+        // Planar layers ignore Y, so the whole column shares one sample
+        let planar: Vec<Option<f32>> = self
+            .config
+            .layers
+            .iter()
+            .map(|layer| layer.planar.then(|| self.sample_layer(layer, wx, 0.0, wz)))
+            .collect();
+        column_in_lanes(ys, out, |wys| {
+            let samples: Vec<Lanes> = self
+                .config
+                .layers
+                .iter()
+                .zip(&planar)
+                .map(|(layer, cached)| cached.map_or_else(|| self.sample_layer_lanes(layer, wx, wys, wz), |v| [v; LANES]))
+                .collect();
+            std::array::from_fn(|l| {
+                let layers: f32 = self.config.layers.iter().zip(&samples).map(|(layer, s)| layer.weight * s[l]).sum();
+                self.stack_density(&biome, layers, wys[l])
+            })
+        });
+    }
+
+    fn generate_density_and_gradient(&self, wx: f32, wy: f32, wz: f32) -> (f32, Vec3) {
+        let (biome, slopes) = biome_modifiers_slope(self.biomes.as_ref(), wx, wz);
+        let (layers, d_layers) = self.config.layers.iter().fold((0.0, Vec3::ZERO), |(v, d), layer| {
+            let (lv, ld) = self.sample_layer_gradient(layer, wx, wy, wz);
+            (v + layer.weight * lv, d + ld * layer.weight)
+        });
+        let density = self.stack_density(&biome, layers, wy);
+        let gradient = d_layers * biome.layers
+            + modifier_gradient(slopes, |m| m.layers) * layers
+            + Vec3::Y * vertical_gradient_slope(&self.config, wy)
+            + modifier_gradient(slopes, |m| m.offset);
+        (density, gradient)
+    }
+
+    fn density_bounds(&self, lo: Vec3, hi: Vec3) -> Option<Interval> {
+        let modifier = |field| modifier_bounds(self.biomes.as_ref(), field);
+        let layers = self
+            .config
+            .layers
+            .iter()
+            .try_fold(Interval::point(0.0), |sum, layer| Some(sum + layer_bounds(layer)? * layer.weight))?;
+        let y = Interval::new(lo.y, hi.y);
+        Some(layers * modifier(|m| m.layers) + vertical_bounds(&self.config, y) + modifier(|m| m.offset))
+    }
+}
+
+/// Builds the generator a config asks for with the built-in `GeneratorRegistry`.
+pub fn generator_from_config(config: MapGenConfig) -> Result<Box<dyn Generator>> {
+    GeneratorRegistry::default().build(config)
+}
+
+/// Material of a solid voxel with `depth` solid voxels above it: the first matching
+/// `surface_rules` entry, else the column's biome, else stone. Without rules or biomes the
+/// `material_thresholds` height bands apply.
+pub fn solid_material<G: Generator + ?Sized>(
+    generator: &G,
+    biome: Option<&BiomeConfig>,
+    wx: f32,
+    wy: f32,
+    wz: f32,
+    depth: u32,
+) -> u8 {
+    let cfg = generator.config();
+    if cfg.surface_rules.is_empty() && cfg.biomes.is_empty() {
+        return cfg.material_thresholds.material(wy);
+    }
+    surface::select_material(&cfg.surface_rules, generator, biome, wx, wy, wz, depth)
+        .unwrap_or_else(|| biome.map_or(1, |b| b.material(depth)))
+}
+
+/// Where the density of a world box lies relative to `density_threshold`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DensityClass {
+    /// Provably at or below the threshold everywhere.
+    Air,
+    /// Provably above the threshold everywhere.
+    Solid,
+    /// Might cross the threshold, or the generator can't tell.
+    Mixed,
+}
+
+/// Classifies world voxels `lo..=hi` from `Generator::density_bounds`.
+pub fn classify_box<G: Generator + ?Sized>(generator: &G, lo: IVec3, hi: IVec3) -> DensityClass {
+    let threshold = generator.config().density_threshold;
+    let Some(bounds) = generator.density_bounds(lo.as_vec3(), hi.as_vec3()) else {
+        return DensityClass::Mixed;
+    };
+    // The bounds and the density round differently; keep clear of the threshold
+    let slack = 1e-4 * (1.0 + bounds.magnitude());
+    if bounds.hi + slack <= threshold {
+        DensityClass::Air
+    } else if bounds.lo - slack > threshold {
+        DensityClass::Solid
+    } else {
+        DensityClass::Mixed
+    }
+}
+
+/// World voxels whose densities `generate_chunk_voxels` reads for a chunk: the padded
+/// chunk, the lookahead above it and, with a `[sampling]` lattice, the nodes around them.
+pub fn chunk_density_box<G: Generator + ?Sized>(chunk_pos: IVec3, generator: &G) -> (IVec3, IVec3) {
+    let cfg = generator.config();
+    let cs_p = CS as i32 + 2;
+    let lo = chunk_pos * CS as i32 - IVec3::ONE;
+    let hi = lo + IVec3::new(cs_p, cs_p + column_lookahead(generator), cs_p) - IVec3::ONE;
+    match lattice_spacing(cfg.sampling.as_ref()) {
+        Some(spacing) => DensityLattice::extent(spacing, lo, hi),
+        None => (lo, hi),
+    }
+}
+
+// Voxels sampled above a chunk so depth below the surface is known at its top
+fn column_lookahead<G: Generator + ?Sized>(generator: &G) -> i32 {
+    let cfg = generator.config();
+    generator
+        .biomes()
+        .map_or(0, |b| b.max_depth())
+        .max(cfg.caves.as_ref().map_or(0, |caves| caves.surface_margin))
+        .max(cfg.water.as_ref().map_or(0, |w| w.max_depth()))
+        .max(surface::max_depth(&cfg.surface_rules)) as i32
+}
+
+/// Padded voxels of a generated chunk. Chunks of a single material (open sky, deep rock)
+/// keep just that material.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkVoxels {
+    Uniform(u8),
+    Dense(Vec<u8>),
+}
+
+impl ChunkVoxels {
+    /// Collapses a padded buffer whose voxels are all equal.
+    pub fn from_dense(voxels: Vec<u8>) -> Self {
+        match voxels.first() {
+            Some(&first) if voxels.iter().all(|&v| v == first) => ChunkVoxels::Uniform(first),
+            _ => ChunkVoxels::Dense(voxels),
+        }
+    }
+
+    pub fn uniform(&self) -> Option<u8> {
+        match self {
+            ChunkVoxels::Uniform(material) => Some(*material),
+            ChunkVoxels::Dense(_) => None,
+        }
+    }
+
+    /// The padded `CS_P3` buffer.
+    pub fn into_dense(self) -> Vec<u8> {
+        match self {
+            ChunkVoxels::Uniform(material) => vec![material; CS_P3],
+            ChunkVoxels::Dense(voxels) => voxels,
+        }
+    }
+}
+
+/// `generate_chunk_voxels` with single-material chunks collapsed. Chunks `classify_box`
+/// proves uniform are returned without filling a buffer.
+pub fn generate_chunk<G: Generator + ?Sized>(chunk_pos: IVec3, generator: &G) -> (ChunkVoxels, usize) {
+    let chunk_pos = wrapped_chunk(chunk_pos, generator);
+    let class = classify_chunk(chunk_pos, generator);
+    if let Some(material) = uniform_material(chunk_pos, generator, class) {
+        return (ChunkVoxels::Uniform(material), if material == 0 { 0 } else { CS_P3 });
+    }
+    let (voxels, solid_count) = fill_chunk_voxels(chunk_pos, generator, class);
+    (ChunkVoxels::from_dense(voxels), solid_count)
+}
+
+// Helper function for generating chunk voxels.
+// Voxels use the same padded `get_zxy_index` layout as level files, so they feed `mesh` directly.
+// Columns are walked top-down so biome materials and the cave surface margin know how deep
+// below the surface each voxel is; sampling starts far enough above the chunk that both line
+// up across chunk borders. Carved voxels still count as solid for depth. Densities come one
+// column at a time from `generate_column`, or from a `[sampling]` lattice; chunks whose
+// density bounds keep them on one side of the threshold skip both.
+pub fn generate_chunk_voxels<G: Generator + ?Sized>(
     chunk_pos: IVec3,
     generator: &G,
 ) -> (Vec<u8>, usize) {
+    let chunk_pos = wrapped_chunk(chunk_pos, generator);
+    fill_chunk_voxels(chunk_pos, generator, classify_chunk(chunk_pos, generator))
+}
+
+// Chunks past the edge of a wrapped world are copies of the one they fold onto
+fn wrapped_chunk<G: Generator + ?Sized>(chunk_pos: IVec3, generator: &G) -> IVec3 {
+    generator.config().wrap.as_ref().map_or(chunk_pos, |w| w.chunk(chunk_pos))
+}
+
+fn classify_chunk<G: Generator + ?Sized>(chunk_pos: IVec3, generator: &G) -> DensityClass {
+    let (lo, hi) = chunk_density_box(chunk_pos, generator);
+    classify_box(generator, lo, hi)
+}
+
+// Material of a chunk `class` proves all air or all solid, unless water, ores, structures,
+// caves or depth-dependent materials could still break it up.
+fn uniform_material<G: Generator + ?Sized>(chunk_pos: IVec3, generator: &G, class: DensityClass) -> Option<u8> {
     let cfg = generator.config();
-    let cs_p3 = (CS + 2) * (CS + 2) * (CS + 2);
-    let mut voxels = vec![0u8; cs_p3];
-    let mut solid_count = 0;
+    if cfg.water.is_some() || !cfg.ores.is_empty() || !cfg.structures.is_empty() {
+        return None;
+    }
+    match class {
+        DensityClass::Air => Some(0),
+        DensityClass::Solid if cfg.caves.is_none() && cfg.surface_rules.is_empty() && cfg.biomes.is_empty() => {
+            // Each height band is one range of Y, so matching ends mean a single band
+            let bottom = (chunk_pos.y * CS as i32 - 1) as f32;
+            let material = cfg.material_thresholds.material(bottom);
+            (cfg.material_thresholds.material(bottom + CS as f32 + 1.0) == material).then_some(material)
+        }
+        _ => None,
+    }
+}
 
-    // Chunk origin in world space
-    let origin = chunk_pos * CS as i32;
+// `generate_chunk_voxels` for a folded chunk position whose densities `class` describes.
+fn fill_chunk_voxels<G: Generator + ?Sized>(chunk_pos: IVec3, generator: &G, class: DensityClass) -> (Vec<u8>, usize) {
+    let cfg = generator.config();
+    let fold = |w: i32| cfg.wrap.as_ref().map_or(w, |wrap| wrap.voxel(w));
+    let biomes = generator.biomes();
+    let carver = cfg.caves.clone().map(|caves| CaveCarver::new(cfg.seed, caves));
+    let water = cfg.water.as_ref();
+    let lookahead = column_lookahead(generator);
+    let cs_p = CS as i32 + 2;
+    let mut voxels = vec![0u8; (cs_p * cs_p * cs_p) as usize];
+    let mut solid_count = 0;
 
-    for lz in 0..CS as i32 + 2 {
-        for ly in 0..CS as i32 + 2 {
-            for lx in 0..CS as i32 + 2 {
-                let wx = (origin.x + lx - 1) as f32;
-                let wy = (origin.y + ly - 1) as f32;
-                let wz = (origin.z + lz - 1) as f32;
+    // World voxels of the padded chunk and the lookahead above it
+    let lo = chunk_pos * CS as i32 - IVec3::ONE;
+    let column_len = cs_p + lookahead;
+    let uniform_density = match class {
+        DensityClass::Air => Some(f32::NEG_INFINITY),
+        DensityClass::Solid => Some(f32::INFINITY),
+        DensityClass::Mixed => None,
+    };
+    let lattice = lattice_spacing(cfg.sampling.as_ref())
+        .filter(|_| uniform_density.is_none())
+        .map(|spacing| DensityLattice::new(generator, spacing, lo, lo + IVec3::new(cs_p, column_len, cs_p) - IVec3::ONE));
+    let ys: Vec<f32> = (0..column_len).map(|ly| (lo.y + ly) as f32).collect();
+    let mut column = vec![uniform_density.unwrap_or(0.0); column_len as usize];
 
-                let density = generator.generate_density(wx, wy, wz);
+    for lz in 0..cs_p {
+        for lx in 0..cs_p {
+            // The padding ring of seam chunks reads the far side of the world
+            let wx = fold(lo.x + lx) as f32;
+            let wz = fold(lo.z + lz) as f32;
+            let biome = biomes.map(|b| b.biome_at(wx, wz));
+            match &lattice {
+                _ if uniform_density.is_some() => {}
+                Some(lattice) => {
+                    for (ly, density) in column.iter_mut().enumerate() {
+                        *density = lattice.sample(lo.x + lx, lo.y + ly as i32, lo.z + lz);
+                    }
+                }
+                None => generator.generate_column(wx, wz, &ys, &mut column),
+            }
 
-                let voxel = if density > cfg.density_threshold {
-                    solid_count += 1;
-                    // Pick material based on height
-                    let h = wy as i32;
-                    if h < cfg.material_thresholds.stone_max {
-                        1 // stone-like
-                    } else if h < cfg.material_thresholds.grass_max {
-                        3 // grass-like
-                    } else {
-                        2 // dirt-like
+            // Solid voxels directly above the current one
+            let mut depth = 0;
+            for ly in (0..column_len).rev() {
+                let wy = ys[ly as usize];
+                if column[ly as usize] <= cfg.density_threshold {
+                    depth = 0;
+                    // Air, or water below sea level
+                    if ly < cs_p
+                        && let Some(fill) = water.and_then(|w| w.fill(wy, biome))
+                    {
+                        solid_count += 1;
+                        voxels[get_zxy_index(lx as usize, ly as usize, lz as usize)] = fill;
                     }
-                } else {
-                    0 // air
-                };
+                    continue;
+                }
 
-                let idx = (lz as usize) * (CS + 2) * (CS + 2) + (ly as usize) * (CS + 2) + (lx as usize);
-                voxels[idx] = voxel;
+                if ly < cs_p && !carver.as_ref().is_some_and(|c| c.carves(wx, wy, wz, depth)) {
+                    solid_count += 1;
+                    let material = solid_material(generator, biome, wx, wy, wz, depth);
+                    voxels[get_zxy_index(lx as usize, ly as usize, lz as usize)] =
+                        water.map_or(material, |w| w.shore(wy, depth, material));
+                }
+                depth += 1;
             }
         }
     }
 
+    if !cfg.ores.is_empty() {
+        place_ores(&mut voxels, chunk_pos, cfg.seed, &cfg.ores, cfg.wrap.as_ref());
+    }
+    if !cfg.structures.is_empty() {
+        solid_count += place_structures(&mut voxels, chunk_pos, generator);
+    }
+
     (voxels, solid_count)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::config::VerticalConfig;
+
+    fn load(name: &str) -> MapGenConfig {
+        let path = format!("{}/mapgen_configs/{name}.toml", env!("CARGO_MANIFEST_DIR"));
+        MapGenConfig::load_from_file(path).unwrap()
+    }
+
+    #[test]
+    fn classified_chunks_match_full_evaluation() {
+        // The visualizer's layer stack, under a vertical gradient steep enough to bound it
+        let mut layered = load("layers_example");
+        layered.vertical = VerticalConfig { bias: 12.0, min_y: -64.0, max_y: 64.0 };
+        layered.density_threshold = 4.0;
+        let configs = [
+            ("splines", load("splines")),
+            ("layers_example", layered),
+            ("heightmap_island", load("heightmap_island")),
+            ("graph_example", load("graph_example")),
+        ];
+        for (name, config) in configs {
+            let generator = generator_from_config(config).unwrap();
+            let mut checked = [false; 2];
+            for y in [-4, 4, -3, 3, -2, 2] {
+                let chunk_pos = IVec3::new(0, y, 0);
+                let class = classify_chunk(chunk_pos, &*generator);
+                let solid = match class {
+                    DensityClass::Mixed => continue,
+                    _ if checked[(class == DensityClass::Solid) as usize] => continue,
+                    _ => class == DensityClass::Solid,
+                };
+                checked[solid as usize] = true;
+                let (exact, exact_count) = fill_chunk_voxels(chunk_pos, &*generator, DensityClass::Mixed);
+                let (voxels, count) = generate_chunk(chunk_pos, &*generator);
+                assert_eq!(voxels.into_dense(), exact, "{name} chunk {chunk_pos} ({class:?})");
+                assert_eq!(count, exact_count, "{name} chunk {chunk_pos} ({class:?})");
+                assert_eq!(generate_chunk_voxels(chunk_pos, &*generator), (exact, exact_count));
+            }
+            assert_eq!(checked, [true, true], "{name} should have all-air and all-solid chunks");
+        }
+    }
+
+    #[test]
+    fn analytic_gradients_match_central_differences() {
+        // Finer steps than `central_differences`, whose 0.05 spans the top octaves of some configs
+        const H: f32 = 1e-3;
+        const SAMPLES: usize = 500;
+        for name in ["default", "splines", "biomes", "layers_example", "heightmap_island", "mountains"] {
+            let generator = generator_from_config(load(name)).unwrap();
+            let mut creased = 0;
+            for i in 0..SAMPLES {
+                // Off the island heightmap's texel centres, where bilinear slopes jump
+                let (x, y, z) = (i * 37 % 500, i % 50, i * 11 % 300);
+                let p = Vec3::new(x as f32 - 250.3, y as f32 * 2.0 - 40.7, z as f32 + 0.9);
+                let (density, gradient) = generator.generate_density_and_gradient(p.x, p.y, p.z);
+                let expected = generator.generate_density(p.x, p.y, p.z);
+                let tolerance = 1e-4 * (1.0 + expected.abs());
+                assert!((density - expected).abs() < tolerance, "{name} at {p}: {density} vs {expected}");
+                let density_at = |p: Vec3| generator.generate_density(p.x, p.y, p.z) as f64;
+                let numeric = Vec3::AXES.map(|axis| {
+                    let difference = density_at(p + axis * H) - density_at(p - axis * H);
+                    (difference / (2.0 * H as f64)) as f32
+                });
+                let numeric = Vec3::from_array(numeric);
+                creased += ((gradient - numeric).length() > 1e-2 * (1.0 + numeric.length())) as usize;
+            }
+            // Ridged noise and clamps crease the density; a sample may straddle one
+            assert!(creased * 100 <= SAMPLES, "{name}: {creased} of {SAMPLES} gradients differ");
+        }
+    }
+}
@@ -0,0 +1,585 @@
+// Density functions assembled from a graph of named nodes in the config
+use super::biome::BiomeMap;
+use super::config::MapGenConfig;
+use super::generators::Generator;
+use super::interval::Interval;
+use super::noise::{fbm3d, fbm3d_bounds, ridged_fbm3d, ridged_fbm3d_bounds, DomainWarp, NoiseBasis};
+use super::spline::{hermite, hermite_bounds};
+use anyhow::{bail, Context, Result};
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// `[graph]` section: the density is the value of the `output` node, and nodes take their
+/// inputs from other nodes by name.
+///
+/// ```toml
+/// [graph]
+/// output = "density"
+///
+/// [graph.nodes.continents]
+/// kind = "noise"
+/// scale = 300.0
+/// octaves = 4
+///
+/// [graph.nodes.height]
+/// kind = "spline"
+/// input = "continents"
+/// points = [{ location = -0.5, value = -20.0 }, { location = 0.5, value = 40.0 }]
+///
+/// [graph.nodes.density]
+/// kind = "add"
+/// inputs = ["height", "falloff"]
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphConfig {
+    pub output: String,
+    pub nodes: BTreeMap<String, GraphNode>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GraphNode {
+    Constant {
+        value: f32,
+    },
+    Noise(GraphNoise),
+    /// `from_value` at `from_y`, `to_value` at `to_y`, linear in between and held beyond.
+    YGradient {
+        from_y: f32,
+        to_y: f32,
+        from_value: f32,
+        to_value: f32,
+    },
+    /// Cubic curve through `points` over the value of `input`, held at the end values.
+    Spline {
+        input: String,
+        points: Vec<CurvePoint>,
+    },
+    Add {
+        inputs: Vec<String>,
+    },
+    Mul {
+        inputs: Vec<String>,
+    },
+    Min {
+        inputs: Vec<String>,
+    },
+    Max {
+        inputs: Vec<String>,
+    },
+    Clamp {
+        input: String,
+        min: f32,
+        max: f32,
+    },
+    /// Evaluates `input` once per column, at y = 0, and reuses it at every height. Only
+    /// exact for inputs that don't depend on y, such as planar noise.
+    Cache {
+        input: String,
+    },
+}
+
+/// Fbm noise of world position, as in `[[layers]]`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphNoise {
+    pub scale: f32,
+    #[serde(default = "default_octaves")]
+    pub octaves: u32,
+    #[serde(default = "default_lacunarity")]
+    pub lacunarity: f32,
+    #[serde(default = "default_gain")]
+    pub gain: f32,
+    #[serde(default)]
+    pub basis: NoiseBasis,
+    /// Sum `1 - |noise|` octaves for sharp ridges.
+    #[serde(default)]
+    pub ridged: bool,
+    /// Sample in the XZ plane only, so the noise is constant along Y.
+    #[serde(default)]
+    pub planar: bool,
+    #[serde(default)]
+    pub seed_offset: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warp: Option<DomainWarp>,
+}
+
+fn default_octaves() -> u32 {
+    1
+}
+
+fn default_lacunarity() -> f32 {
+    2.0
+}
+
+fn default_gain() -> f32 {
+    0.5
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CurvePoint {
+    pub location: f32,
+    pub value: f32,
+    /// Slope at this point; when omitted it follows the neighbouring points (Catmull-Rom).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derivative: Option<f32>,
+}
+
+impl GraphNode {
+    fn inputs(&self) -> &[String] {
+        match self {
+            GraphNode::Constant { .. } | GraphNode::Noise(_) | GraphNode::YGradient { .. } => &[],
+            GraphNode::Spline { input, .. } | GraphNode::Clamp { input, .. } | GraphNode::Cache { input } => {
+                std::slice::from_ref(input)
+            }
+            GraphNode::Add { inputs } | GraphNode::Mul { inputs } | GraphNode::Min { inputs } | GraphNode::Max { inputs } => {
+                inputs
+            }
+        }
+    }
+
+    fn validate(&self, name: &str) -> Result<()> {
+        match self {
+            GraphNode::Noise(noise) => {
+                anyhow::ensure!(noise.scale > 0.0, "Graph node \"{name}\" needs a positive scale");
+                if let Some(warp) = &noise.warp {
+                    anyhow::ensure!(warp.scale > 0.0, "Graph node \"{name}\" needs a positive warp scale");
+                }
+            }
+            GraphNode::YGradient { from_y, to_y, .. } => {
+                anyhow::ensure!(from_y != to_y, "Graph node \"{name}\" needs from_y and to_y to differ");
+            }
+            GraphNode::Spline { points, .. } => {
+                anyhow::ensure!(!points.is_empty(), "Graph node \"{name}\" has no spline points");
+                for pair in points.windows(2) {
+                    anyhow::ensure!(
+                        pair[1].location > pair[0].location,
+                        "Graph node \"{name}\" has spline points out of order ({} then {})",
+                        pair[0].location,
+                        pair[1].location
+                    );
+                }
+            }
+            GraphNode::Clamp { min, max, .. } => {
+                anyhow::ensure!(min <= max, "Graph node \"{name}\" has an empty clamp range {min}..={max}");
+            }
+            GraphNode::Add { inputs } | GraphNode::Mul { inputs } | GraphNode::Min { inputs } | GraphNode::Max { inputs } => {
+                anyhow::ensure!(!inputs.is_empty(), "Graph node \"{name}\" has no inputs");
+            }
+            GraphNode::Constant { .. } | GraphNode::Cache { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fold {
+    Add,
+    Mul,
+    Min,
+    Max,
+}
+
+impl Fold {
+    fn apply(self, a: f32, b: f32) -> f32 {
+        match self {
+            Fold::Add => a + b,
+            Fold::Mul => a * b,
+            Fold::Min => a.min(b),
+            Fold::Max => a.max(b),
+        }
+    }
+
+    fn apply_bounds(self, a: Interval, b: Interval) -> Interval {
+        match self {
+            Fold::Add => a + b,
+            Fold::Mul => a * b,
+            Fold::Min => Interval::new(a.lo.min(b.lo), a.hi.min(b.hi)),
+            Fold::Max => Interval::new(a.lo.max(b.lo), a.hi.max(b.hi)),
+        }
+    }
+}
+
+// A node with its inputs resolved to indices of earlier nodes
+#[derive(Debug, Clone)]
+enum Op {
+    Constant(f32),
+    Noise(GraphNoise),
+    YGradient { from_y: f32, to_y: f32, from_value: f32, to_value: f32 },
+    Spline { input: usize, points: Vec<CurvePoint> },
+    Fold(Fold, Vec<usize>),
+    Clamp { input: usize, min: f32, max: f32 },
+    Cache(usize),
+}
+
+/// A `GraphConfig` compiled into evaluation order: every node comes after its inputs, and
+/// only nodes the output depends on are kept.
+#[derive(Debug, Clone)]
+pub struct DensityGraph {
+    ops: Vec<Op>,
+    /// Evaluated at every point; the rest only feed `cache` nodes.
+    per_voxel: Vec<bool>,
+    /// Evaluated once per column at y = 0 for the `cache` nodes.
+    per_column: Vec<bool>,
+}
+
+impl DensityGraph {
+    /// Resolves node names, rejecting unknown names and cycles.
+    pub fn compile(config: &GraphConfig) -> Result<Self> {
+        for (name, node) in &config.nodes {
+            node.validate(name)?;
+            if let Some(unknown) = node.inputs().iter().find(|input| !config.nodes.contains_key(*input)) {
+                bail!("Graph node \"{name}\" refers to unknown node \"{unknown}\"");
+            }
+        }
+        anyhow::ensure!(
+            config.nodes.contains_key(&config.output),
+            "Graph output \"{}\" is not a node",
+            config.output
+        );
+
+        // Depth-first from the output; nodes still on `path` when reached again close a cycle
+        let mut order = Vec::new();
+        let mut index = HashMap::new();
+        let mut path = Vec::new();
+        visit(config, &config.output, &mut path, &mut index, &mut order)?;
+        // Unused nodes are checked for cycles too, but not kept
+        let (mut unused_index, mut unused_order) = (index.clone(), Vec::new());
+        for name in config.nodes.keys() {
+            visit(config, name, &mut path, &mut unused_index, &mut unused_order)?;
+        }
+
+        let ops: Vec<Op> = order
+            .iter()
+            .map(|name| {
+                let at = |input: &String| index[input.as_str()];
+                match &config.nodes[*name] {
+                    GraphNode::Constant { value } => Op::Constant(*value),
+                    GraphNode::Noise(noise) => Op::Noise(noise.clone()),
+                    &GraphNode::YGradient { from_y, to_y, from_value, to_value } => {
+                        Op::YGradient { from_y, to_y, from_value, to_value }
+                    }
+                    GraphNode::Spline { input, points } => Op::Spline { input: at(input), points: points.clone() },
+                    GraphNode::Add { inputs } => Op::Fold(Fold::Add, inputs.iter().map(at).collect()),
+                    GraphNode::Mul { inputs } => Op::Fold(Fold::Mul, inputs.iter().map(at).collect()),
+                    GraphNode::Min { inputs } => Op::Fold(Fold::Min, inputs.iter().map(at).collect()),
+                    GraphNode::Max { inputs } => Op::Fold(Fold::Max, inputs.iter().map(at).collect()),
+                    &GraphNode::Clamp { ref input, min, max } => Op::Clamp { input: at(input), min, max },
+                    GraphNode::Cache { input } => Op::Cache(at(input)),
+                }
+            })
+            .collect();
+
+        // Walk back from the output (the last node); a cache's inputs move to the column pass
+        let n = ops.len();
+        let (mut per_voxel, mut per_column) = (vec![false; n], vec![false; n]);
+        per_voxel[n - 1] = true;
+        for i in (0..n).rev() {
+            let cache = matches!(ops[i], Op::Cache(_));
+            per_column[i] |= cache;
+            let inputs = match &ops[i] {
+                Op::Spline { input, .. } | Op::Clamp { input, .. } | Op::Cache(input) => std::slice::from_ref(input),
+                Op::Fold(_, inputs) => inputs.as_slice(),
+                _ => &[],
+            };
+            for &input in inputs {
+                per_column[input] |= per_column[i];
+                per_voxel[input] |= per_voxel[i] && !cache;
+            }
+        }
+        Ok(Self { ops, per_voxel, per_column })
+    }
+
+    // Value of node `i` from the values of earlier nodes; `column` holds the cached values
+    // outside the column pass itself
+    fn op_value(&self, i: usize, p: Vec3, seed: u32, values: &[f32], column: Option<&[f32]>) -> f32 {
+        match &self.ops[i] {
+            Op::Constant(v) => *v,
+            Op::Noise(noise) => sample_noise(noise, p, seed),
+            &Op::YGradient { from_y, to_y, from_value, to_value } => {
+                let t = ((p.y - from_y) / (to_y - from_y)).clamp(0.0, 1.0);
+                from_value + t * (to_value - from_value)
+            }
+            Op::Spline { input, points } => curve(points, values[*input]),
+            Op::Fold(fold, inputs) => {
+                let (first, rest) = inputs.split_first().unwrap();
+                rest.iter().fold(values[*first], |acc, &input| fold.apply(acc, values[input]))
+            }
+            &Op::Clamp { input, min, max } => values[input].clamp(min, max),
+            &Op::Cache(input) => column.map_or(values[input], |column| column[i]),
+        }
+    }
+
+    /// Values of the cached nodes (and their inputs) in the column at (`wx`, `wz`).
+    fn column_values(&self, wx: f32, wz: f32, seed: u32, values: &mut [f32]) {
+        for i in (0..self.ops.len()).filter(|&i| self.per_column[i]) {
+            values[i] = self.op_value(i, Vec3::new(wx, 0.0, wz), seed, values, None);
+        }
+    }
+
+    fn eval(&self, p: Vec3, seed: u32, column: &[f32], values: &mut [f32]) -> f32 {
+        for i in (0..self.ops.len()).filter(|&i| self.per_voxel[i]) {
+            values[i] = self.op_value(i, p, seed, values, Some(column));
+        }
+        values[self.ops.len() - 1]
+    }
+
+    /// Range of the output over the world box `lo..=hi`.
+    fn bounds(&self, lo: Vec3, hi: Vec3) -> Interval {
+        let mut column = vec![Interval::point(0.0); self.ops.len()];
+        let mut values = column.clone();
+        for i in 0..self.ops.len() {
+            if self.per_column[i] {
+                column[i] = self.op_bounds(i, Interval::point(0.0), &column, None);
+            }
+        }
+        for i in 0..self.ops.len() {
+            if self.per_voxel[i] {
+                values[i] = self.op_bounds(i, Interval::new(lo.y, hi.y), &values, Some(&column));
+            }
+        }
+        values[self.ops.len() - 1]
+    }
+
+    fn op_bounds(&self, i: usize, y: Interval, values: &[Interval], column: Option<&[Interval]>) -> Interval {
+        match &self.ops[i] {
+            Op::Constant(v) => Interval::point(*v),
+            Op::Noise(noise) if noise.ridged => ridged_fbm3d_bounds(noise.basis, noise.octaves, noise.gain),
+            Op::Noise(noise) => fbm3d_bounds(noise.basis, noise.octaves, noise.gain),
+            &Op::YGradient { from_y, to_y, from_value, to_value } => y.map_monotonic(|y| {
+                let t = ((y - from_y) / (to_y - from_y)).clamp(0.0, 1.0);
+                from_value + t * (to_value - from_value)
+            }),
+            Op::Spline { input, points } => curve_bounds(points, values[*input]),
+            Op::Fold(fold, inputs) => {
+                let (first, rest) = inputs.split_first().unwrap();
+                rest.iter().fold(values[*first], |acc, &input| fold.apply_bounds(acc, values[input]))
+            }
+            &Op::Clamp { input, min, max } => values[input].clamp(min, max),
+            &Op::Cache(input) => column.map_or(values[input], |column| column[i]),
+        }
+    }
+}
+
+// Appends `name` and the nodes it depends on to `order`, inputs first
+fn visit<'a>(
+    config: &'a GraphConfig,
+    name: &'a str,
+    path: &mut Vec<&'a str>,
+    index: &mut HashMap<&'a str, usize>,
+    order: &mut Vec<&'a str>,
+) -> Result<()> {
+    if index.contains_key(name) {
+        return Ok(());
+    }
+    if let Some(start) = path.iter().position(|&p| p == name) {
+        let cycle = path[start..].iter().chain([&name]).copied().collect::<Vec<_>>().join(" -> ");
+        bail!("Graph nodes form a cycle: {cycle}");
+    }
+    path.push(name);
+    for input in config.nodes[name].inputs() {
+        visit(config, input, path, index, order)?;
+    }
+    path.pop();
+    index.insert(name, order.len());
+    order.push(name);
+    Ok(())
+}
+
+fn sample_noise(noise: &GraphNoise, p: Vec3, seed: u32) -> f32 {
+    let seed = seed.wrapping_add(noise.seed_offset);
+    let (wx, wy, wz) = match (&noise.warp, noise.planar) {
+        (Some(warp), true) => {
+            let (wx, wz) = warp.warp2d(p.x, p.z, seed);
+            (wx, 0.0, wz)
+        }
+        (Some(warp), false) => warp.warp3d(p.x, p.y, p.z, seed),
+        (None, true) => (p.x, 0.0, p.z),
+        (None, false) => (p.x, p.y, p.z),
+    };
+    let (x, y, z) = (wx / noise.scale, wy / noise.scale, wz / noise.scale);
+    let sample = if noise.ridged { ridged_fbm3d } else { fbm3d };
+    sample(noise.basis, x, y, z, seed, noise.octaves, noise.lacunarity, noise.gain)
+}
+
+// Catmull-Rom style slope at point `k` unless the point sets its own
+fn curve_slope(points: &[CurvePoint], k: usize) -> f32 {
+    if let Some(d) = points[k].derivative {
+        return d;
+    }
+    let (lo, hi) = (k.saturating_sub(1), (k + 1).min(points.len() - 1));
+    (points[hi].value - points[lo].value) / (points[hi].location - points[lo].location)
+}
+
+fn curve(points: &[CurvePoint], x: f32) -> f32 {
+    let last = points.len() - 1;
+    if x <= points[0].location {
+        return points[0].value;
+    }
+    if x >= points[last].location {
+        return points[last].value;
+    }
+    // A NaN input (e.g. 0 * inf upstream) fails both end checks and lands on the first segment
+    let i = points.partition_point(|p| p.location <= x).saturating_sub(1);
+    let (x0, x1) = (points[i].location, points[i + 1].location);
+    let h = x1 - x0;
+    hermite((x - x0) / h, h, points[i].value, points[i + 1].value, curve_slope(points, i), curve_slope(points, i + 1))
+}
+
+fn curve_bounds(points: &[CurvePoint], x: Interval) -> Interval {
+    let last = points.len() - 1;
+    let value = |k: usize| Interval::point(points[k].value);
+    let before = (x.lo <= points[0].location).then(|| value(0));
+    let after = (x.hi >= points[last].location).then(|| value(last));
+    let segments = (0..last)
+        .filter(|&i| x.hi >= points[i].location && x.lo <= points[i + 1].location)
+        .map(|i| {
+            let h = points[i + 1].location - points[i].location;
+            let (m0, m1) = (curve_slope(points, i).abs(), curve_slope(points, i + 1).abs());
+            hermite_bounds(h, value(i), value(i + 1), m0, m1)
+        });
+    before
+        .into_iter()
+        .chain(after)
+        .chain(segments)
+        .reduce(Interval::hull)
+        .expect("an input range always reaches a point or a segment")
+}
+
+// Runs `f` with two zeroed buffers of `len` node values, on the stack for typical graphs
+fn with_buffers<R>(len: usize, f: impl FnOnce(&mut [f32], &mut [f32]) -> R) -> R {
+    const STACK_NODES: usize = 32;
+    if len <= STACK_NODES {
+        let (mut column, mut values) = ([0.0; STACK_NODES], [0.0; STACK_NODES]);
+        f(&mut column[..len], &mut values[..len])
+    } else {
+        f(&mut vec![0.0; len], &mut vec![0.0; len])
+    }
+}
+
+/// Density from a `[graph]` of nodes.
+pub struct GraphGenerator {
+    config: MapGenConfig,
+    graph: DensityGraph,
+    biomes: Option<BiomeMap>,
+}
+
+impl GraphGenerator {
+    pub fn new(config: MapGenConfig) -> Result<Self> {
+        let graph = DensityGraph::compile(config.graph.as_ref().context("Config has no [graph]")?)?;
+        let biomes = BiomeMap::new(&config);
+        Ok(Self { config, graph, biomes })
+    }
+}
+
+impl Generator for GraphGenerator {
+    fn config(&self) -> &MapGenConfig {
+        &self.config
+    }
+
+    fn biomes(&self) -> Option<&BiomeMap> {
+        self.biomes.as_ref()
+    }
+
+    // Structures and slope rules evaluate single points, so this path doesn't allocate
+    // for graphs of typical size
+    fn generate_density(&self, wx: f32, wy: f32, wz: f32) -> f32 {
+        with_buffers(self.graph.ops.len(), |column, values| {
+            self.graph.column_values(wx, wz, self.config.seed, column);
+            self.graph.eval(Vec3::new(wx, wy, wz), self.config.seed, column, values)
+        })
+    }
+
+    fn generate_column(&self, wx: f32, wz: f32, ys: &[f32], out: &mut [f32]) {
+        with_buffers(self.graph.ops.len(), |column, values| {
+            self.graph.column_values(wx, wz, self.config.seed, column);
+            for (density, &wy) in out.iter_mut().zip(ys) {
+                *density = self.graph.eval(Vec3::new(wx, wy, wz), self.config.seed, column, values);
+            }
+        })
+    }
+
+    fn density_bounds(&self, lo: Vec3, hi: Vec3) -> Option<Interval> {
+        Some(self.graph.bounds(lo, hi))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile_error(graph: &str) -> String {
+        let config: GraphConfig = toml::from_str(graph).unwrap();
+        match DensityGraph::compile(&config) {
+            Ok(_) => panic!("graph compiled:\n{graph}"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn cycles_are_reported_with_their_path() {
+        let two = compile_error(
+            r#"
+            output = "a"
+            nodes.a = { kind = "add", inputs = ["b"] }
+            nodes.b = { kind = "clamp", input = "a", min = 0.0, max = 1.0 }
+            "#,
+        );
+        assert_eq!(two, "Graph nodes form a cycle: a -> b -> a");
+
+        let own = compile_error(
+            r#"
+            output = "a"
+            nodes.a = { kind = "max", inputs = ["a"] }
+            "#,
+        );
+        assert_eq!(own, "Graph nodes form a cycle: a -> a");
+
+        // The output doesn't reach x or y, but their cycle is still an error
+        let unused = compile_error(
+            r#"
+            output = "c"
+            nodes.c = { kind = "constant", value = 1.0 }
+            nodes.x = { kind = "add", inputs = ["c", "y"] }
+            nodes.y = { kind = "cache", input = "x" }
+            "#,
+        );
+        assert_eq!(unused, "Graph nodes form a cycle: x -> y -> x");
+    }
+
+    #[test]
+    fn unknown_names_are_reported() {
+        let input = compile_error(
+            r#"
+            output = "a"
+            nodes.a = { kind = "mul", inputs = ["b", "typo"] }
+            nodes.b = { kind = "constant", value = 2.0 }
+            "#,
+        );
+        assert_eq!(input, "Graph node \"a\" refers to unknown node \"typo\"");
+
+        let output = compile_error(
+            r#"
+            output = "density"
+            nodes.a = { kind = "constant", value = 2.0 }
+            "#,
+        );
+        assert_eq!(output, "Graph output \"density\" is not a node");
+    }
+
+    #[test]
+    fn acyclic_graphs_compile() {
+        let config: GraphConfig = toml::from_str(
+            r#"
+            output = "sum"
+            nodes.sum = { kind = "add", inputs = ["two", "two", "three"] }
+            nodes.two = { kind = "constant", value = 2.0 }
+            nodes.three = { kind = "constant", value = 3.0 }
+            nodes.unused = { kind = "constant", value = 4.0 }
+            "#,
+        )
+        .unwrap();
+        let graph = DensityGraph::compile(&config).unwrap();
+        assert_eq!(graph.bounds(Vec3::ZERO, Vec3::ONE), Interval::point(7.0));
+    }
+}
@@ -0,0 +1,342 @@
+// Grayscale heightmap images (PGM / PNG) as a terrain source
+use super::biome::BiomeMap;
+use super::config::{HeightmapConfig, MapGenConfig};
+use super::generators::Generator;
+use super::interval::Interval;
+use anyhow::{bail, Context, Result};
+use glam::Vec3;
+use std::fs;
+use std::path::Path;
+
+/// Row-major samples normalized to 0..1, row 0 at world -Z.
+#[derive(Debug, Clone)]
+pub struct Heightmap {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<f32>,
+}
+
+impl Heightmap {
+    /// Picks the decoder from the file extension (`.pgm` / `.pnm` or `.png`).
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).with_context(|| format!("Failed to read heightmap: {}", path.display()))?;
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase)
+            .unwrap_or_default();
+        let map = match ext.as_str() {
+            "pgm" | "pnm" => Self::from_pgm(&bytes),
+            "png" => Self::from_png(&bytes),
+            _ => bail!("Unsupported heightmap format '{ext}' (expected .pgm or .png)"),
+        };
+        map.with_context(|| format!("Failed to decode heightmap: {}", path.display()))
+    }
+
+    /// Binary (P5) and ASCII (P2) graymaps, 8 or 16 bits.
+    pub fn from_pgm(bytes: &[u8]) -> Result<Self> {
+        let mut pos = 0;
+        let mut token = || -> Result<String> {
+            loop {
+                while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+                    pos += 1;
+                }
+                if pos < bytes.len() && bytes[pos] == b'#' {
+                    while pos < bytes.len() && bytes[pos] != b'\n' {
+                        pos += 1;
+                    }
+                    continue;
+                }
+                break;
+            }
+            let start = pos;
+            while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            anyhow::ensure!(pos > start, "Truncated PGM header");
+            Ok(String::from_utf8_lossy(&bytes[start..pos]).into_owned())
+        };
+
+        let magic = token()?;
+        let width: usize = token()?.parse().context("Invalid PGM width")?;
+        let height: usize = token()?.parse().context("Invalid PGM height")?;
+        let maxval: u32 = token()?.parse().context("Invalid PGM maxval")?;
+        anyhow::ensure!((1..=65535).contains(&maxval), "Invalid PGM maxval {maxval}");
+        anyhow::ensure!(width > 0 && height > 0, "PGM image is empty ({width}x{height})");
+        let count = width.checked_mul(height).context("PGM image is too large")?;
+
+        let raw: Vec<u32> = match magic.as_str() {
+            "P5" => {
+                // Exactly one whitespace byte separates the header from the raster.
+                let start = pos + 1;
+                let bpp = if maxval > 255 { 2 } else { 1 };
+                let len = count.checked_mul(bpp).context("PGM image is too large")?;
+                let raster = bytes
+                    .get(start..)
+                    .and_then(|rest| rest.get(..len))
+                    .context("Truncated PGM raster")?;
+                if bpp == 2 {
+                    raster.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]]) as u32).collect()
+                } else {
+                    raster.iter().map(|&v| v as u32).collect()
+                }
+            }
+            "P2" => (0..count)
+                .map(|_| token()?.parse::<u32>().context("Invalid PGM sample"))
+                .collect::<Result<_>>()?,
+            _ => bail!("Not a PGM file (magic '{magic}')"),
+        };
+
+        Ok(Self {
+            width,
+            height,
+            data: raw.iter().map(|&v| v.min(maxval) as f32 / maxval as f32).collect(),
+        })
+    }
+
+    /// Gray or RGB(A) PNGs; color images are converted by luminance.
+    pub fn from_png(bytes: &[u8]) -> Result<Self> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info().context("Invalid PNG header")?;
+        let mut buf = vec![0u8; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).context("Failed to decode PNG")?;
+
+        let channels = info.color_type.samples();
+        let wide = info.bit_depth == png::BitDepth::Sixteen;
+        let bytes_per_sample = if wide { 2 } else { 1 };
+        let max = if wide { 65535.0 } else { 255.0 };
+
+        let sample = |i: usize| -> f32 {
+            let at = i * bytes_per_sample;
+            let v = if wide {
+                u16::from_be_bytes([buf[at], buf[at + 1]]) as f32
+            } else {
+                buf[at] as f32
+            };
+            v / max
+        };
+
+        let (width, height) = (info.width as usize, info.height as usize);
+        anyhow::ensure!(width > 0 && height > 0, "PNG image is empty ({width}x{height})");
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let row = y * info.line_size / bytes_per_sample;
+            for x in 0..width {
+                let px = row + x * channels;
+                let v = if channels >= 3 {
+                    0.2126 * sample(px) + 0.7152 * sample(px + 1) + 0.0722 * sample(px + 2)
+                } else {
+                    sample(px)
+                };
+                data.push(v);
+            }
+        }
+
+        Ok(Self { width, height, data })
+    }
+
+    fn texel(&self, x: i64, y: i64) -> f32 {
+        let x = x.clamp(0, self.width as i64 - 1) as usize;
+        let y = y.clamp(0, self.height as i64 - 1) as usize;
+        self.data[y * self.width + x]
+    }
+
+    /// Bilinear sample at pixel coordinates (pixel centers on integers), clamped to the edges.
+    pub fn sample_bilinear(&self, u: f32, v: f32) -> f32 {
+        let x0 = u.floor();
+        let y0 = v.floor();
+        let tx = u - x0;
+        let ty = v - y0;
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.texel(x0, y0) * (1.0 - tx) + self.texel(x0 + 1, y0) * tx;
+        let bottom = self.texel(x0, y0 + 1) * (1.0 - tx) + self.texel(x0 + 1, y0 + 1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+
+    /// Range of `sample_bilinear` over pixel coordinates within `u` and `v`: the texels
+    /// the bilinear footprints touch.
+    pub fn sample_bounds(&self, u: Interval, v: Interval) -> Interval {
+        let texel_range = |lo: f32, hi: f32, size: usize| {
+            let last = size as i64 - 1;
+            ((lo.floor() as i64).clamp(0, last) as usize, (hi.floor() as i64 + 1).clamp(0, last) as usize)
+        };
+        let (x0, x1) = texel_range(u.lo, u.hi, self.width);
+        let (y0, y1) = texel_range(v.lo, v.hi, self.height);
+        let (mut lo, mut hi) = (f32::INFINITY, f32::NEG_INFINITY);
+        for y in y0..=y1 {
+            for &t in &self.data[y * self.width + x0..=y * self.width + x1] {
+                lo = lo.min(t);
+                hi = hi.max(t);
+            }
+        }
+        Interval::new(lo, hi)
+    }
+
+    /// `sample_bilinear` with its derivatives along `u` and `v`.
+    pub fn sample_bilinear_deriv(&self, u: f32, v: f32) -> (f32, f32, f32) {
+        let (x0, y0) = (u.floor(), v.floor());
+        let (tx, ty) = (u - x0, v - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let (a, b) = (self.texel(x0, y0), self.texel(x0 + 1, y0));
+        let (c, d) = (self.texel(x0, y0 + 1), self.texel(x0 + 1, y0 + 1));
+        let top = a * (1.0 - tx) + b * tx;
+        let bottom = c * (1.0 - tx) + d * tx;
+        let du = (b - a) * (1.0 - ty) + (d - c) * ty;
+        (top * (1.0 - ty) + bottom * ty, du, bottom - top)
+    }
+}
+
+/// Terrain from an image: the surface height is `sea_level + (value - sea_value) * vertical_scale`,
+/// with the image centered on the world origin. Materials come from the usual
+/// `surface_rules` or biomes in `generate_chunk_voxels`; biome
+/// density modifiers are not applied to images.
+pub struct HeightmapGenerator {
+    config: MapGenConfig,
+    settings: HeightmapConfig,
+    heightmap: Heightmap,
+    biomes: Option<BiomeMap>,
+}
+
+impl HeightmapGenerator {
+    /// Loads the image named by `config.heightmap`.
+    pub fn new(config: MapGenConfig) -> Result<Self> {
+        let settings = config
+            .heightmap
+            .clone()
+            .context("Config has no [heightmap] section")?;
+        anyhow::ensure!(
+            settings.horizontal_scale > 0.0,
+            "[heightmap] horizontal_scale must be positive, got {}",
+            settings.horizontal_scale
+        );
+        let heightmap = Heightmap::load_from_file(&settings.path)?;
+        Ok(Self::with_heightmap(config, settings, heightmap))
+    }
+
+    pub fn with_heightmap(config: MapGenConfig, settings: HeightmapConfig, heightmap: Heightmap) -> Self {
+        let biomes = BiomeMap::new(&config);
+        Self {
+            config,
+            settings,
+            heightmap,
+            biomes,
+        }
+    }
+
+    pub fn surface_height(&self, wx: f32, wz: f32) -> f32 {
+        let s = &self.settings;
+        let u = wx / s.horizontal_scale + (self.heightmap.width as f32 - 1.0) * 0.5;
+        let v = wz / s.horizontal_scale + (self.heightmap.height as f32 - 1.0) * 0.5;
+        let value = self.heightmap.sample_bilinear(u, v);
+        s.sea_level + (value - s.sea_value) * s.vertical_scale
+    }
+
+    /// Range of `surface_height` over the columns in `lo..=hi` (X and Z).
+    pub fn surface_height_bounds(&self, lo: Vec3, hi: Vec3) -> Interval {
+        let s = &self.settings;
+        let u = Interval::new(lo.x, hi.x) * s.horizontal_scale.recip() + (self.heightmap.width as f32 - 1.0) * 0.5;
+        let v = Interval::new(lo.z, hi.z) * s.horizontal_scale.recip() + (self.heightmap.height as f32 - 1.0) * 0.5;
+        (self.heightmap.sample_bounds(u, v) + -s.sea_value) * s.vertical_scale + s.sea_level
+    }
+
+    /// `surface_height` with its slopes along X and Z.
+    pub fn surface_height_slope(&self, wx: f32, wz: f32) -> (f32, f32, f32) {
+        let s = &self.settings;
+        let u = wx / s.horizontal_scale + (self.heightmap.width as f32 - 1.0) * 0.5;
+        let v = wz / s.horizontal_scale + (self.heightmap.height as f32 - 1.0) * 0.5;
+        let (value, du, dv) = self.heightmap.sample_bilinear_deriv(u, v);
+        let k = s.vertical_scale / s.horizontal_scale;
+        (s.sea_level + (value - s.sea_value) * s.vertical_scale, du * k, dv * k)
+    }
+}
+
+impl Generator for HeightmapGenerator {
+    fn config(&self) -> &MapGenConfig {
+        &self.config
+    }
+
+    fn biomes(&self) -> Option<&BiomeMap> {
+        self.biomes.as_ref()
+    }
+
+    // Density is the signed distance in voxels to the surface, so `density_threshold`
+    // shifts the surface by that many voxels.
+    fn generate_density(&self, wx: f32, wy: f32, wz: f32) -> f32 {
+        self.surface_height(wx, wz) - wy
+    }
+
+    fn generate_column(&self, wx: f32, wz: f32, ys: &[f32], out: &mut [f32]) {
+        let height = self.surface_height(wx, wz);
+        for (density, &wy) in out.iter_mut().zip(ys) {
+            *density = height - wy;
+        }
+    }
+
+    fn generate_density_and_gradient(&self, wx: f32, wy: f32, wz: f32) -> (f32, Vec3) {
+        let (height, dx, dz) = self.surface_height_slope(wx, wz);
+        (height - wy, Vec3::new(dx, -1.0, dz))
+    }
+
+    fn density_bounds(&self, lo: Vec3, hi: Vec3) -> Option<Interval> {
+        Some(self.surface_height_bounds(lo, hi) - Interval::new(lo.y, hi.y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_and_binary_graymaps_decode_the_same() {
+        let ascii = Heightmap::from_pgm(b"P2\n# a comment\n3 2\n255\n0 51 102\n153 204 255\n").unwrap();
+        let mut binary = b"P5 3 2 255\n".to_vec();
+        binary.extend([0, 51, 102, 153, 204, 255]);
+        let binary = Heightmap::from_pgm(&binary).unwrap();
+        assert_eq!((ascii.width, ascii.height), (3, 2));
+        assert_eq!(ascii.data, vec![0.0, 0.2, 0.4, 0.6, 0.8, 1.0]);
+        assert_eq!(binary.data, ascii.data);
+    }
+
+    #[test]
+    fn sixteen_bit_graymaps_are_big_endian() {
+        let mut bytes = b"P5 2 1 65535\n".to_vec();
+        bytes.extend([0x00, 0x00, 0xff, 0xff]);
+        assert_eq!(Heightmap::from_pgm(&bytes).unwrap().data, vec![0.0, 1.0]);
+        let mut bytes = b"P5 2 1 1000\n".to_vec();
+        bytes.extend([0x01, 0xf4, 0x03, 0xe8]);
+        assert_eq!(Heightmap::from_pgm(&bytes).unwrap().data, vec![0.5, 1.0]);
+    }
+
+    #[test]
+    fn truncated_graymaps_are_rejected() {
+        let mut bytes = b"P5 3 2 255\n".to_vec();
+        bytes.extend([0, 51, 102, 153, 204]);
+        let err = Heightmap::from_pgm(&bytes).unwrap_err();
+        assert_eq!(err.to_string(), "Truncated PGM raster");
+        let err = Heightmap::from_pgm(b"P2 2 2 255\n1 2 3").unwrap_err();
+        assert_eq!(err.to_string(), "Truncated PGM header");
+        let err = Heightmap::from_pgm(b"P6 1 1 255\n\0\0\0").unwrap_err();
+        assert_eq!(err.to_string(), "Not a PGM file (magic 'P6')");
+    }
+
+    #[test]
+    fn sixteen_bit_pngs_keep_their_precision() {
+        let samples: [u16; 6] = [0, 1, 257, 32768, 65534, 65535];
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, 3, 2);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Sixteen);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&samples.iter().flat_map(|s| s.to_be_bytes()).collect::<Vec<_>>()).unwrap();
+        writer.finish().unwrap();
+
+        let map = Heightmap::from_png(&bytes).unwrap();
+        assert_eq!((map.width, map.height), (3, 2));
+        let expected: Vec<f32> = samples.iter().map(|&s| s as f32 / 65535.0).collect();
+        assert_eq!(map.data, expected);
+    }
+}
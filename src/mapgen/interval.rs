@@ -0,0 +1,100 @@
+// Closed value ranges for bounding densities over a region of the world
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// Every value in `lo..=hi`. Arithmetic on intervals contains every result of the same
+/// arithmetic on their members, so bounds can be propagated through a density formula.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub lo: f32,
+    pub hi: f32,
+}
+
+impl Interval {
+    pub fn new(a: f32, b: f32) -> Self {
+        Self { lo: a.min(b), hi: a.max(b) }
+    }
+
+    pub fn point(v: f32) -> Self {
+        Self { lo: v, hi: v }
+    }
+
+    /// Smallest interval containing both.
+    pub fn hull(self, other: Self) -> Self {
+        Self {
+            lo: self.lo.min(other.lo),
+            hi: self.hi.max(other.hi),
+        }
+    }
+
+    /// Image under a function that is monotonic (either way) over the interval.
+    pub fn map_monotonic(self, f: impl Fn(f32) -> f32) -> Self {
+        Self::new(f(self.lo), f(self.hi))
+    }
+
+    pub fn clamp(self, lo: f32, hi: f32) -> Self {
+        self.map_monotonic(|v| v.clamp(lo, hi))
+    }
+
+    /// Largest magnitude of any member.
+    pub fn magnitude(self) -> f32 {
+        self.lo.abs().max(self.hi.abs())
+    }
+}
+
+impl Add for Interval {
+    type Output = Self;
+    fn add(self, o: Self) -> Self {
+        Self {
+            lo: self.lo + o.lo,
+            hi: self.hi + o.hi,
+        }
+    }
+}
+
+impl Sub for Interval {
+    type Output = Self;
+    fn sub(self, o: Self) -> Self {
+        Self {
+            lo: self.lo - o.hi,
+            hi: self.hi - o.lo,
+        }
+    }
+}
+
+impl Neg for Interval {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self {
+            lo: -self.hi,
+            hi: -self.lo,
+        }
+    }
+}
+
+impl Mul for Interval {
+    type Output = Self;
+    fn mul(self, o: Self) -> Self {
+        let products = [self.lo * o.lo, self.lo * o.hi, self.hi * o.lo, self.hi * o.hi];
+        Self {
+            lo: products.into_iter().fold(f32::INFINITY, f32::min),
+            hi: products.into_iter().fold(f32::NEG_INFINITY, f32::max),
+        }
+    }
+}
+
+impl Mul<f32> for Interval {
+    type Output = Self;
+    fn mul(self, k: f32) -> Self {
+        Self::new(self.lo * k, self.hi * k)
+    }
+}
+
+impl Add<f32> for Interval {
+    type Output = Self;
+    fn add(self, k: f32) -> Self {
+        Self {
+            lo: self.lo + k,
+            hi: self.hi + k,
+        }
+    }
+}
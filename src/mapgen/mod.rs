@@ -1,7 +1,24 @@
 // Map generation module
 pub mod noise;
+pub mod interval;
 pub mod config;
+pub mod biome;
+pub mod caves;
+pub mod ores;
+pub mod sampling;
+pub mod spline;
+pub mod structures;
+pub mod surface;
+pub mod water;
+pub mod wrap;
 pub mod generators;
+pub mod heightmap;
+pub mod analytic;
+pub mod registry;
+pub mod graph;
+pub mod erosion;
+pub mod bake;
+pub mod combinators;
 
 pub use config::MapGenConfig;
 pub use generators::Generator;
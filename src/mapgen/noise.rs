@@ -1,6 +1,70 @@
 // Procedural noise functions
+use super::interval::Interval;
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
 use std::f32::consts::SQRT_2;
 
+/// Gradient noise used as the octave basis of `fbm3d` / `ridged_fbm3d`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseBasis {
+    #[default]
+    Perlin,
+    Simplex,
+    /// Perlin noise repeating every `period` lattice cells along X and Z, see
+    /// `perlin3d_periodic`. Set up by `[wrap]` rather than written in configs.
+    #[serde(skip)]
+    PeriodicPerlin { period: u32 },
+}
+
+impl NoiseBasis {
+    pub fn sample3d(self, x: f32, y: f32, z: f32, seed: u32) -> f32 {
+        match self {
+            NoiseBasis::Perlin => perlin3d(x, y, z, seed),
+            NoiseBasis::Simplex => simplex3d(x, y, z, seed),
+            NoiseBasis::PeriodicPerlin { period } => perlin3d_periodic(x, y, z, seed, period),
+        }
+    }
+
+    /// Basis of an fbm octave sampled at `freq` times the first one's frequency: a period
+    /// of `n` cells spans `n * freq` cells there. `freq` must be a whole number to tile.
+    pub fn octave(self, freq: f32) -> Self {
+        match self {
+            NoiseBasis::PeriodicPerlin { period } => NoiseBasis::PeriodicPerlin {
+                period: (period as f32 * freq).round() as u32,
+            },
+            basis => basis,
+        }
+    }
+
+    /// `sample3d` at `LANES` points.
+    pub fn sample3d_lanes(self, x: &Lanes, y: &Lanes, z: &Lanes, seed: u32) -> Lanes {
+        match self {
+            NoiseBasis::Perlin => perlin3d_lanes(x, y, z, seed),
+            NoiseBasis::Simplex => std::array::from_fn(|l| simplex3d(x[l], y[l], z[l], seed)),
+            NoiseBasis::PeriodicPerlin { period } => perlin3d_lanes_periodic(x, y, z, seed, period),
+        }
+    }
+
+    /// Largest magnitude a sample can reach. Perlin noise with unit gradients stays within
+    /// √3/2; the simplex kernels are scaled so their sum peaks just under 1.
+    pub fn bound(self) -> f32 {
+        match self {
+            NoiseBasis::Perlin | NoiseBasis::PeriodicPerlin { .. } => 0.75f32.sqrt(),
+            NoiseBasis::Simplex => 1.0,
+        }
+    }
+
+    /// Value and gradient, see `perlin3d_deriv` / `simplex3d_deriv`.
+    pub fn sample3d_deriv(self, x: f32, y: f32, z: f32, seed: u32) -> (f32, Vec3) {
+        match self {
+            NoiseBasis::Perlin => perlin3d_deriv(x, y, z, seed),
+            NoiseBasis::Simplex => simplex3d_deriv(x, y, z, seed),
+            NoiseBasis::PeriodicPerlin { period } => perlin3d_deriv_periodic(x, y, z, seed, period),
+        }
+    }
+}
+
 pub fn hash_u32(x: u32, seed: u32) -> u32 {
     let mut h = x ^ seed;
     h = h.wrapping_mul(0x9E3779B1);
@@ -16,11 +80,23 @@ pub fn fade(t: f32) -> f32 {
     t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
 }
 
+fn fade_deriv(t: f32) -> f32 {
+    30.0 * t * t * (t - 1.0) * (t - 1.0)
+}
+
 pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
     a + t * (b - a)
 }
 
 pub fn perlin3d(x: f32, y: f32, z: f32, seed: u32) -> f32 {
+    perlin3d_periodic(x, y, z, seed, 0)
+}
+
+/// `perlin3d` repeating every `period` lattice cells along X and Z, so
+/// `perlin3d_periodic(x + period, y, z, ..)` equals the value at `x` up to float rounding.
+/// Y never repeats; a period of 0 doesn't repeat at all and gives `perlin3d` exactly.
+#[inline]
+pub fn perlin3d_periodic(x: f32, y: f32, z: f32, seed: u32, period: u32) -> f32 {
     let x0 = x.floor() as i32;
     let y0 = y.floor() as i32;
     let z0 = z.floor() as i32;
@@ -30,6 +106,7 @@ pub fn perlin3d(x: f32, y: f32, z: f32, seed: u32) -> f32 {
     let zf = z - z0 as f32;
 
     let h = |ix: i32, iy: i32, iz: i32| -> u32 {
+        let (ix, iz) = (wrap_cell(ix, period), wrap_cell(iz, period));
         let key = (ix as u32)
             .wrapping_mul(73856093)
             .wrapping_add((iy as u32).wrapping_mul(19349663))
@@ -94,19 +171,324 @@ pub fn perlin3d(x: f32, y: f32, z: f32, seed: u32) -> f32 {
     lerp(nxy0, nxy1, w)
 }
 
-pub fn fbm3d(x: f32, y: f32, z: f32, seed: u32, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+/// Points evaluated together by the `_lanes` noise functions.
+pub const LANES: usize = 8;
+
+/// One value per lane.
+pub type Lanes = [f32; LANES];
+
+/// `perlin3d` at `LANES` points. Every lane repeats the scalar steps in the same order, so
+/// results are bit-identical; working on whole lane arrays lets the hashing and blending
+/// vectorize across points.
+pub fn perlin3d_lanes(x: &Lanes, y: &Lanes, z: &Lanes, seed: u32) -> Lanes {
+    perlin3d_lanes_periodic(x, y, z, seed, 0)
+}
+
+/// `perlin3d_periodic` at `LANES` points.
+#[inline]
+pub fn perlin3d_lanes_periodic(x: &Lanes, y: &Lanes, z: &Lanes, seed: u32, period: u32) -> Lanes {
+    let cell = |v: &Lanes| -> ([i32; LANES], Lanes) {
+        let i: [i32; LANES] = v.map(|v| v.floor() as i32);
+        (i, std::array::from_fn(|l| v[l] - i[l] as f32))
+    };
+    let ((x0, xf), (y0, yf), (z0, zf)) = (cell(x), cell(y), cell(z));
+    // Lattice X and Z of both corners along each axis, folded once for all eight corners
+    let fold = |i: [i32; LANES]| if period == 0 { i } else { i.map(|i| wrap_cell(i, period)) };
+    let xs = [fold(x0), fold(x0.map(|i| i + 1))];
+    let zs = [fold(z0), fold(z0.map(|i| i + 1))];
+
+    // Dot product of each lane's gradient at one corner with the offset to that corner.
+    // The gradient is decoded from its `SIMPLEX_GRADS3` index with bit masks instead of a
+    // table lookup so it vectorizes: indices 0-3 are XY, 4-7 XZ and 8-11 YZ edges, bit 0
+    // negates the first axis and bit 1 the second.
+    let corner = |i: i32, j: i32, k: i32| -> Lanes {
+        let index: [u32; LANES] = std::array::from_fn(|l| lattice_hash3(xs[i as usize][l], y0[l] + j, zs[k as usize][l], seed) % 12);
+        let (dx, dy, dz) = (xf.map(|f| f - i as f32), yf.map(|f| f - j as f32), zf.map(|f| f - k as f32));
+        std::array::from_fn(|l| {
+            let (first, second) = (0x3f80_0000 | (index[l] & 1) << 31, 0x3f80_0000 | (index[l] & 2) << 30);
+            let plane = index[l] >> 2;
+            let keep = |on: bool| (on as u32).wrapping_neg();
+            let gx = f32::from_bits(first & keep(plane != 2));
+            let gy = f32::from_bits((second & keep(plane == 0)) | (first & keep(plane == 2)));
+            let gz = f32::from_bits(second & keep(plane != 0));
+            (gx * dx[l] + gy * dy[l] + gz * dz[l]) / SQRT_2
+        })
+    };
+    let (n000, n100, n010, n110) = (corner(0, 0, 0), corner(1, 0, 0), corner(0, 1, 0), corner(1, 1, 0));
+    let (n001, n101, n011, n111) = (corner(0, 0, 1), corner(1, 0, 1), corner(0, 1, 1), corner(1, 1, 1));
+
+    let (u, v, w) = (xf.map(fade), yf.map(fade), zf.map(fade));
+    std::array::from_fn(|l| {
+        let nxy0 = lerp(lerp(n000[l], n100[l], u[l]), lerp(n010[l], n110[l], u[l]), v[l]);
+        let nxy1 = lerp(lerp(n001[l], n101[l], u[l]), lerp(n011[l], n111[l], u[l]), v[l]);
+        lerp(nxy0, nxy1, w[l])
+    })
+}
+
+/// `perlin3d` with its analytic gradient. Values match `perlin3d` up to float rounding.
+pub fn perlin3d_deriv(x: f32, y: f32, z: f32, seed: u32) -> (f32, Vec3) {
+    perlin3d_deriv_periodic(x, y, z, seed, 0)
+}
+
+/// `perlin3d_periodic` with its analytic gradient.
+#[inline]
+pub fn perlin3d_deriv_periodic(x: f32, y: f32, z: f32, seed: u32, period: u32) -> (f32, Vec3) {
+    let (x0, y0, z0) = (x.floor() as i32, y.floor() as i32, z.floor() as i32);
+    let f = Vec3::new(x - x0 as f32, y - y0 as f32, z - z0 as f32);
+
+    // Gradient and dot product at each corner, ordered by (x, y, z) bits
+    let corner = |i: i32, j: i32, k: i32| -> (Vec3, f32) {
+        // Same 12 directions and hash as `perlin3d`
+        let (gx, gy, gz) = SIMPLEX_GRADS3[(lattice_hash3(wrap_cell(x0 + i, period), y0 + j, wrap_cell(z0 + k, period), seed) % 12) as usize];
+        let g = Vec3::new(gx, gy, gz) / SQRT_2;
+        (g, g.dot(f - Vec3::new(i as f32, j as f32, k as f32)))
+    };
+    let (ga, a) = corner(0, 0, 0);
+    let (gb, b) = corner(1, 0, 0);
+    let (gc, c) = corner(0, 1, 0);
+    let (gd, d) = corner(1, 1, 0);
+    let (ge, e) = corner(0, 0, 1);
+    let (gf, ff) = corner(1, 0, 1);
+    let (gg, g) = corner(0, 1, 1);
+    let (gh, h) = corner(1, 1, 1);
+
+    let (u, v, w) = (fade(f.x), fade(f.y), fade(f.z));
+    let du = Vec3::new(fade_deriv(f.x), fade_deriv(f.y), fade_deriv(f.z));
+
+    // Trilinear interpolation expanded into polynomial coefficients
+    let k1 = b - a;
+    let k2 = c - a;
+    let k3 = e - a;
+    let k4 = a - b - c + d;
+    let k5 = a - c - e + g;
+    let k6 = a - b - e + ff;
+    let k7 = -a + b + c - d + e - ff - g + h;
+    let value = a + k1 * u + k2 * v + k3 * w + k4 * u * v + k5 * v * w + k6 * w * u + k7 * u * v * w;
+
+    let gradients = ga
+        + (gb - ga) * u
+        + (gc - ga) * v
+        + (ge - ga) * w
+        + (ga - gb - gc + gd) * u * v
+        + (ga - gc - ge + gg) * v * w
+        + (ga - gb - ge + gf) * w * u
+        + (-ga + gb + gc - gd + ge - gf - gg + gh) * u * v * w;
+    let weights = Vec3::new(
+        k1 + k4 * v + k6 * w + k7 * v * w,
+        k2 + k5 * w + k4 * u + k7 * w * u,
+        k3 + k6 * u + k5 * v + k7 * u * v,
+    );
+    (value, gradients + du * weights)
+}
+
+// Lattice index folded into `0..period`; a period of 0 leaves it alone
+fn wrap_cell(i: i32, period: u32) -> i32 {
+    if period == 0 {
+        i
+    } else {
+        i.rem_euclid(period as i32)
+    }
+}
+
+// Same lattice hashing as `perlin3d`, so both bases react to `seed` the same way.
+fn lattice_hash2(ix: i32, iy: i32, seed: u32) -> u32 {
+    let key = (ix as u32)
+        .wrapping_mul(73856093)
+        .wrapping_add((iy as u32).wrapping_mul(19349663));
+    hash_u32(key, seed)
+}
+
+/// Hash of an integer lattice point, as used by `perlin3d`.
+pub fn lattice_hash3(ix: i32, iy: i32, iz: i32, seed: u32) -> u32 {
+    let key = (ix as u32)
+        .wrapping_mul(73856093)
+        .wrapping_add((iy as u32).wrapping_mul(19349663))
+        .wrapping_add((iz as u32).wrapping_mul(83492791));
+    hash_u32(key, seed)
+}
+
+// Eight unit directions for 2D and the 12 cube-edge directions for 3D.
+const SIMPLEX_GRADS2: [(f32, f32); 8] = [
+    (1.0, 0.0),
+    (-1.0, 0.0),
+    (0.0, 1.0),
+    (0.0, -1.0),
+    (0.70710677, 0.70710677),
+    (-0.70710677, 0.70710677),
+    (0.70710677, -0.70710677),
+    (-0.70710677, -0.70710677),
+];
+
+const SIMPLEX_GRADS3: [(f32, f32, f32); 12] = [
+    (1.0, 1.0, 0.0),
+    (-1.0, 1.0, 0.0),
+    (1.0, -1.0, 0.0),
+    (-1.0, -1.0, 0.0),
+    (1.0, 0.0, 1.0),
+    (-1.0, 0.0, 1.0),
+    (1.0, 0.0, -1.0),
+    (-1.0, 0.0, -1.0),
+    (0.0, 1.0, 1.0),
+    (0.0, -1.0, 1.0),
+    (0.0, 1.0, -1.0),
+    (0.0, -1.0, -1.0),
+];
+
+/// 2D simplex noise in roughly [-1, 1]. The triangular lattice avoids the axis-aligned
+/// artifacts of `perlin3d`'s cubic grid.
+pub fn simplex2d(x: f32, y: f32, seed: u32) -> f32 {
+    const F2: f32 = 0.3660254; // (sqrt(3) - 1) / 2
+    const G2: f32 = 0.21132487; // (3 - sqrt(3)) / 6
+
+    let s = (x + y) * F2;
+    let i = (x + s).floor() as i32;
+    let j = (y + s).floor() as i32;
+    let t = (i + j) as f32 * G2;
+    let x0 = x - (i as f32 - t);
+    let y0 = y - (j as f32 - t);
+
+    // Lower or upper triangle of the skewed cell
+    let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+
+    let corners = [
+        (x0, y0, 0, 0),
+        (x0 - i1 as f32 + G2, y0 - j1 as f32 + G2, i1, j1),
+        (x0 - 1.0 + 2.0 * G2, y0 - 1.0 + 2.0 * G2, 1, 1),
+    ];
+
+    let mut out = 0.0;
+    for (dx, dy, ci, cj) in corners {
+        let t = 0.5 - dx * dx - dy * dy;
+        if t > 0.0 {
+            let (gx, gy) = SIMPLEX_GRADS2[(lattice_hash2(i + ci, j + cj, seed) % 8) as usize];
+            let t2 = t * t;
+            out += t2 * t2 * (gx * dx + gy * dy);
+        }
+    }
+    out * 99.2
+}
+
+/// 3D simplex noise in roughly [-1, 1], hashed like `perlin3d`.
+pub fn simplex3d(x: f32, y: f32, z: f32, seed: u32) -> f32 {
+    const F3: f32 = 1.0 / 3.0;
+    const G3: f32 = 1.0 / 6.0;
+
+    let s = (x + y + z) * F3;
+    let i = (x + s).floor() as i32;
+    let j = (y + s).floor() as i32;
+    let k = (z + s).floor() as i32;
+    let t = (i + j + k) as f32 * G3;
+    let x0 = x - (i as f32 - t);
+    let y0 = y - (j as f32 - t);
+    let z0 = z - (k as f32 - t);
+
+    // Pick the tetrahedron containing the point by ordering the offsets
+    let (o1, o2) = if x0 >= y0 {
+        if y0 >= z0 {
+            ((1, 0, 0), (1, 1, 0))
+        } else if x0 >= z0 {
+            ((1, 0, 0), (1, 0, 1))
+        } else {
+            ((0, 0, 1), (1, 0, 1))
+        }
+    } else if y0 < z0 {
+        ((0, 0, 1), (0, 1, 1))
+    } else if x0 < z0 {
+        ((0, 1, 0), (0, 1, 1))
+    } else {
+        ((0, 1, 0), (1, 1, 0))
+    };
+
+    let corners = [(0, 0, 0), o1, o2, (1, 1, 1)];
+    let mut out = 0.0;
+    for (n, (ci, cj, ck)) in corners.into_iter().enumerate() {
+        let g = n as f32 * G3;
+        let dx = x0 - ci as f32 + g;
+        let dy = y0 - cj as f32 + g;
+        let dz = z0 - ck as f32 + g;
+        let t = 0.6 - dx * dx - dy * dy - dz * dz;
+        if t > 0.0 {
+            let (gx, gy, gz) = SIMPLEX_GRADS3[(lattice_hash3(i + ci, j + cj, k + ck, seed) % 12) as usize];
+            let t2 = t * t;
+            out += t2 * t2 * (gx * dx + gy * dy + gz * dz);
+        }
+    }
+    out * 32.0
+}
+
+/// `simplex3d` with its analytic gradient.
+pub fn simplex3d_deriv(x: f32, y: f32, z: f32, seed: u32) -> (f32, Vec3) {
+    const F3: f32 = 1.0 / 3.0;
+    const G3: f32 = 1.0 / 6.0;
+
+    let s = (x + y + z) * F3;
+    let i = (x + s).floor() as i32;
+    let j = (y + s).floor() as i32;
+    let k = (z + s).floor() as i32;
+    let t = (i + j + k) as f32 * G3;
+    let p0 = Vec3::new(x - (i as f32 - t), y - (j as f32 - t), z - (k as f32 - t));
+
+    let (o1, o2) = if p0.x >= p0.y {
+        if p0.y >= p0.z {
+            ((1, 0, 0), (1, 1, 0))
+        } else if p0.x >= p0.z {
+            ((1, 0, 0), (1, 0, 1))
+        } else {
+            ((0, 0, 1), (1, 0, 1))
+        }
+    } else if p0.y < p0.z {
+        ((0, 0, 1), (0, 1, 1))
+    } else if p0.x < p0.z {
+        ((0, 1, 0), (0, 1, 1))
+    } else {
+        ((0, 1, 0), (1, 1, 0))
+    };
+
+    let corners = [(0, 0, 0), o1, o2, (1, 1, 1)];
+    let (mut value, mut gradient) = (0.0, Vec3::ZERO);
+    for (n, (ci, cj, ck)) in corners.into_iter().enumerate() {
+        // The offset moves one-to-one with the input point, so d(offset)/dp is the identity
+        let d = p0 - Vec3::new(ci as f32, cj as f32, ck as f32) + Vec3::splat(n as f32 * G3);
+        let t = 0.6 - d.length_squared();
+        if t > 0.0 {
+            let (gx, gy, gz) = SIMPLEX_GRADS3[(lattice_hash3(i + ci, j + cj, k + ck, seed) % 12) as usize];
+            let g = Vec3::new(gx, gy, gz);
+            let (t2, dot) = (t * t, g.dot(d));
+            value += t2 * t2 * dot;
+            gradient += t2 * t2 * g - 8.0 * t2 * t * dot * d;
+        }
+    }
+    (value * 32.0, gradient * 32.0)
+}
+
+/// Sum of `octaves` samples of `basis`, each at `lacunarity` times the frequency and `gain`
+/// times the amplitude of the one before. With `NoiseBasis::PeriodicPerlin` and a whole
+/// `lacunarity` every octave repeats over the basis's period, so the sum tiles too.
+#[allow(clippy::too_many_arguments)]
+pub fn fbm3d(
+    basis: NoiseBasis,
+    x: f32,
+    y: f32,
+    z: f32,
+    seed: u32,
+    octaves: u32,
+    lacunarity: f32,
+    gain: f32,
+) -> f32 {
     let mut out = 0.0;
     let mut amp = 1.0;
     let mut freq = 1.0;
     for i in 0..octaves {
-        out += amp * perlin3d(x * freq, y * freq, z * freq, seed + i * 1013);
+        out += amp * basis.octave(freq).sample3d(x * freq, y * freq, z * freq, seed.wrapping_add(i.wrapping_mul(1013)));
         freq *= lacunarity;
         amp *= gain;
     }
     out
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn ridged_fbm3d(
+    basis: NoiseBasis,
     x: f32,
     y: f32,
     z: f32,
@@ -119,7 +501,7 @@ pub fn ridged_fbm3d(
     let mut amp = 1.0;
     let mut freq = 1.0;
     for i in 0..octaves {
-        let n = perlin3d(x * freq, y * freq, z * freq, seed + i * 1013);
+        let n = basis.octave(freq).sample3d(x * freq, y * freq, z * freq, seed.wrapping_add(i.wrapping_mul(1013)));
         let r = 1.0 - n.abs();
         out += amp * r;
         freq *= lacunarity;
@@ -128,7 +510,551 @@ pub fn ridged_fbm3d(
     out
 }
 
+/// `fbm3d` at `LANES` points, bit-identical to it lane by lane.
+#[allow(clippy::too_many_arguments)]
+pub fn fbm3d_lanes(
+    basis: NoiseBasis,
+    x: &Lanes,
+    y: &Lanes,
+    z: &Lanes,
+    seed: u32,
+    octaves: u32,
+    lacunarity: f32,
+    gain: f32,
+) -> Lanes {
+    let mut out = [0.0; LANES];
+    let mut amp = 1.0;
+    let mut freq = 1.0;
+    for i in 0..octaves {
+        let n = basis.octave(freq).sample3d_lanes(&x.map(|v| v * freq), &y.map(|v| v * freq), &z.map(|v| v * freq), seed.wrapping_add(i.wrapping_mul(1013)));
+        for (out, n) in out.iter_mut().zip(n) {
+            *out += amp * n;
+        }
+        freq *= lacunarity;
+        amp *= gain;
+    }
+    out
+}
+
+/// `ridged_fbm3d` at `LANES` points, bit-identical to it lane by lane.
+#[allow(clippy::too_many_arguments)]
+pub fn ridged_fbm3d_lanes(
+    basis: NoiseBasis,
+    x: &Lanes,
+    y: &Lanes,
+    z: &Lanes,
+    seed: u32,
+    octaves: u32,
+    lacunarity: f32,
+    gain: f32,
+) -> Lanes {
+    let mut out = [0.0; LANES];
+    let mut amp = 1.0;
+    let mut freq = 1.0;
+    for i in 0..octaves {
+        let n = basis.octave(freq).sample3d_lanes(&x.map(|v| v * freq), &y.map(|v| v * freq), &z.map(|v| v * freq), seed.wrapping_add(i.wrapping_mul(1013)));
+        for (out, n) in out.iter_mut().zip(n) {
+            let r = 1.0 - n.abs();
+            *out += amp * r;
+        }
+        freq *= lacunarity;
+        amp *= gain;
+    }
+    out
+}
+
+/// `fbm3d` with its gradient with respect to `(x, y, z)`.
+#[allow(clippy::too_many_arguments)]
+pub fn fbm3d_deriv(
+    basis: NoiseBasis,
+    x: f32,
+    y: f32,
+    z: f32,
+    seed: u32,
+    octaves: u32,
+    lacunarity: f32,
+    gain: f32,
+) -> (f32, Vec3) {
+    let (mut out, mut gradient) = (0.0, Vec3::ZERO);
+    let mut amp = 1.0;
+    let mut freq = 1.0;
+    for i in 0..octaves {
+        let (n, dn) = basis.octave(freq).sample3d_deriv(x * freq, y * freq, z * freq, seed.wrapping_add(i.wrapping_mul(1013)));
+        out += amp * n;
+        gradient += dn * (amp * freq);
+        freq *= lacunarity;
+        amp *= gain;
+    }
+    (out, gradient)
+}
+
+/// `ridged_fbm3d` with its gradient with respect to `(x, y, z)`. The ridge crease itself
+/// has no derivative; either side's slope is used there.
+#[allow(clippy::too_many_arguments)]
+pub fn ridged_fbm3d_deriv(
+    basis: NoiseBasis,
+    x: f32,
+    y: f32,
+    z: f32,
+    seed: u32,
+    octaves: u32,
+    lacunarity: f32,
+    gain: f32,
+) -> (f32, Vec3) {
+    let (mut out, mut gradient) = (0.0, Vec3::ZERO);
+    let mut amp = 1.0;
+    let mut freq = 1.0;
+    for i in 0..octaves {
+        let (n, dn) = basis.octave(freq).sample3d_deriv(x * freq, y * freq, z * freq, seed.wrapping_add(i.wrapping_mul(1013)));
+        out += amp * (1.0 - n.abs());
+        gradient -= dn * (n.signum() * amp * freq);
+        freq *= lacunarity;
+        amp *= gain;
+    }
+    (out, gradient)
+}
+
+/// Range of `fbm3d` at any position.
+pub fn fbm3d_bounds(basis: NoiseBasis, octaves: u32, gain: f32) -> Interval {
+    octave_bounds(Interval::new(-basis.bound(), basis.bound()), octaves, gain)
+}
+
+/// Range of `ridged_fbm3d` at any position.
+pub fn ridged_fbm3d_bounds(basis: NoiseBasis, octaves: u32, gain: f32) -> Interval {
+    octave_bounds(Interval::new(1.0 - basis.bound(), 1.0), octaves, gain)
+}
+
+// Sum of `octaves` terms each within `term`, scaled by the octave amplitudes
+fn octave_bounds(term: Interval, octaves: u32, gain: f32) -> Interval {
+    (0..octaves).fold(Interval::point(0.0), |sum, i| sum + term * gain.powi(i as i32))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceMetric {
+    #[default]
+    Euclidean,
+    Manhattan,
+    Chebyshev,
+}
+
+impl DistanceMetric {
+    fn distance(self, dx: f32, dy: f32, dz: f32) -> f32 {
+        match self {
+            DistanceMetric::Euclidean => (dx * dx + dy * dy + dz * dz).sqrt(),
+            DistanceMetric::Manhattan => dx.abs() + dy.abs() + dz.abs(),
+            DistanceMetric::Chebyshev => dx.abs().max(dy.abs()).max(dz.abs()),
+        }
+    }
+}
+
+/// Which value of a `Cellular` sample a noise layer uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CellularReturn {
+    #[default]
+    F1,
+    F2,
+    F2MinusF1,
+    CellId,
+}
+
+/// Distances to the nearest and second nearest feature points, plus the hash of the
+/// cell owning the nearest one.
+#[derive(Debug, Clone, Copy)]
+pub struct Cellular {
+    pub f1: f32,
+    pub f2: f32,
+    pub cell_id: u32,
+}
+
+impl Cellular {
+    /// `CellId` maps the cell hash to [0, 1) so it can be weighted like the distances.
+    pub fn value(&self, ret: CellularReturn) -> f32 {
+        match ret {
+            CellularReturn::F1 => self.f1,
+            CellularReturn::F2 => self.f2,
+            CellularReturn::F2MinusF1 => self.f2 - self.f1,
+            CellularReturn::CellId => (self.cell_id >> 8) as f32 / (1u32 << 24) as f32,
+        }
+    }
+
+    fn push(&mut self, d: f32, cell_id: u32) {
+        if d < self.f1 {
+            self.f2 = self.f1;
+            self.f1 = d;
+            self.cell_id = cell_id;
+        } else if d < self.f2 {
+            self.f2 = d;
+        }
+    }
+}
+
+/// Maps a hash to [0, 1).
+pub fn unit_from_hash(h: u32) -> f32 {
+    (h >> 8) as f32 / (1u32 << 24) as f32
+}
+
+/// Deterministic stream of values in [0, 1) for one lattice cell (e.g. a chunk),
+/// built on `hash_u32` so it only depends on the seed and the cell.
+pub struct HashRng {
+    state: u32,
+    counter: u32,
+}
+
+impl HashRng {
+    pub fn new(x: i32, y: i32, z: i32, seed: u32) -> Self {
+        let state = hash_u32(z as u32, hash_u32(y as u32, hash_u32(x as u32, seed)));
+        Self { state, counter: 0 }
+    }
+
+    pub fn unit(&mut self) -> f32 {
+        self.counter += 1;
+        unit_from_hash(hash_u32(self.counter, self.state))
+    }
+
+    /// Uniform in `[lo, hi)`.
+    pub fn range(&mut self, lo: f32, hi: f32) -> f32 {
+        lo + (hi - lo) * self.unit()
+    }
+}
+
+/// 2D Worley noise with one feature point per unit cell, jittered through `hash_u32`.
+pub fn worley2d(x: f32, y: f32, seed: u32, metric: DistanceMetric) -> Cellular {
+    let (xi, yi) = (x.floor() as i32, y.floor() as i32);
+    let (fx, fy) = (x - xi as f32, y - yi as f32);
+    let mut out = Cellular { f1: f32::MAX, f2: f32::MAX, cell_id: 0 };
+    for r in 0.. {
+        if ring_out_of_reach(r, out.f2) {
+            break;
+        }
+        for oy in -r..=r {
+            for ox in -r..=r {
+                if ox.abs().max(oy.abs()) != r || metric.distance(cell_gap(ox, fx), cell_gap(oy, fy), 0.0) >= out.f2 {
+                    continue;
+                }
+                let (cx, cy) = (xi + ox, yi + oy);
+                let id = lattice_hash2(cx, cy, seed);
+                let px = cx as f32 + unit_from_hash(hash_u32(id, 1));
+                let py = cy as f32 + unit_from_hash(hash_u32(id, 2));
+                out.push(metric.distance(px - x, py - y, 0.0), id);
+            }
+        }
+    }
+    out
+}
+
+/// 3D Worley noise; see `worley2d`.
+pub fn worley3d(x: f32, y: f32, z: f32, seed: u32, metric: DistanceMetric) -> Cellular {
+    let (xi, yi, zi) = (x.floor() as i32, y.floor() as i32, z.floor() as i32);
+    let (fx, fy, fz) = (x - xi as f32, y - yi as f32, z - zi as f32);
+    let mut out = Cellular { f1: f32::MAX, f2: f32::MAX, cell_id: 0 };
+    for r in 0.. {
+        if ring_out_of_reach(r, out.f2) {
+            break;
+        }
+        for oz in -r..=r {
+            for oy in -r..=r {
+                for ox in -r..=r {
+                    if ox.abs().max(oy.abs()).max(oz.abs()) != r
+                        || metric.distance(cell_gap(ox, fx), cell_gap(oy, fy), cell_gap(oz, fz)) >= out.f2
+                    {
+                        continue;
+                    }
+                    let (cx, cy, cz) = (xi + ox, yi + oy, zi + oz);
+                    let id = lattice_hash3(cx, cy, cz, seed);
+                    let px = cx as f32 + unit_from_hash(hash_u32(id, 1));
+                    let py = cy as f32 + unit_from_hash(hash_u32(id, 2));
+                    let pz = cz as f32 + unit_from_hash(hash_u32(id, 3));
+                    out.push(metric.distance(px - x, py - y, pz - z), id);
+                }
+            }
+        }
+    }
+    out
+}
+
+// Feature points can sit anywhere in their cell, so the second nearest may be more than one
+// cell away. Cells are searched in rings of growing Chebyshev distance `r`; every cell of
+// ring `r` is at least `r - 1` away along some axis, which no metric undercuts, so once
+// that reaches F2 no further ring can change the result.
+fn ring_out_of_reach(r: i32, f2: f32) -> bool {
+    r >= 2 && (r - 1) as f32 >= f2
+}
+
+// Distance along one axis from a point at fraction `f` of its cell to the cell `offset`
+// cells away
+fn cell_gap(offset: i32, f: f32) -> f32 {
+    match offset {
+        0 => 0.0,
+        o if o > 0 => o as f32 - f,
+        o => f - (o + 1) as f32,
+    }
+}
+
+/// Domain warp: sample points are displaced by a vector of fbm noise before a layer is
+/// evaluated. With `iterations > 1` the warp feeds back into itself
+/// (`p + A * f(p + A * f(p))` for two iterations). Distances are in world voxels.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainWarp {
+    /// Maximum displacement, roughly, in voxels.
+    pub amplitude: f32,
+    /// Feature size of the warp noise in voxels.
+    pub scale: f32,
+    #[serde(default = "default_warp_octaves")]
+    pub octaves: u32,
+    #[serde(default = "default_warp_lacunarity")]
+    pub lacunarity: f32,
+    #[serde(default = "default_warp_gain")]
+    pub gain: f32,
+    #[serde(default = "default_warp_iterations")]
+    pub iterations: u32,
+    #[serde(default)]
+    pub basis: NoiseBasis,
+    /// Added to the layer's seed so the warp is decorrelated from the layer itself.
+    #[serde(default = "default_warp_seed_offset")]
+    pub seed_offset: u32,
+}
+
+fn default_warp_octaves() -> u32 {
+    3
+}
+
+fn default_warp_lacunarity() -> f32 {
+    2.0
+}
+
+fn default_warp_gain() -> f32 {
+    0.5
+}
+
+fn default_warp_iterations() -> u32 {
+    1
+}
+
+fn default_warp_seed_offset() -> u32 {
+    5003
+}
+
+impl DomainWarp {
+    fn offset(&self, x: f32, y: f32, z: f32, seed: u32) -> (f32, f32, f32) {
+        let (sx, sy, sz) = (x / self.scale, y / self.scale, z / self.scale);
+        let axis = |n: u32| {
+            let seed = seed.wrapping_add(n.wrapping_mul(7919));
+            fbm3d(self.basis, sx, sy, sz, seed, self.octaves, self.lacunarity, self.gain)
+        };
+        (axis(0), axis(1), axis(2))
+    }
+
+    /// Warped copy of a world-space point.
+    pub fn warp3d(&self, x: f32, y: f32, z: f32, seed: u32) -> (f32, f32, f32) {
+        let seed = seed.wrapping_add(self.seed_offset);
+        let (mut ox, mut oy, mut oz) = (0.0, 0.0, 0.0);
+        for i in 0..self.iterations {
+            let a = self.amplitude;
+            (ox, oy, oz) = self.offset(x + a * ox, y + a * oy, z + a * oz, seed.wrapping_add(i.wrapping_mul(104729)));
+        }
+        let a = self.amplitude;
+        (x + a * ox, y + a * oy, z + a * oz)
+    }
+
+    /// Warp in the XZ plane only, for layers that ignore Y.
+    pub fn warp2d(&self, x: f32, z: f32, seed: u32) -> (f32, f32) {
+        let seed = seed.wrapping_add(self.seed_offset);
+        let (mut ox, mut oz) = (0.0, 0.0);
+        for i in 0..self.iterations {
+            let a = self.amplitude;
+            let (dx, _, dz) = self.offset(x + a * ox, 0.0, z + a * oz, seed.wrapping_add(i.wrapping_mul(104729)));
+            (ox, oz) = (dx, dz);
+        }
+        let a = self.amplitude;
+        (x + a * ox, z + a * oz)
+    }
+    /// Furthest any point moves along each axis, in voxels.
+    pub fn max_displacement(&self) -> f32 {
+        self.amplitude.abs() * fbm3d_bounds(self.basis, self.octaves, self.gain).magnitude()
+    }
+}
+
 pub fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
     let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
     t * t * (3.0 - 2.0 * t)
 }
+
+/// Derivative of `smoothstep` with respect to `x`.
+pub fn smoothstep_deriv(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = (x - edge0) / (edge1 - edge0);
+    if t <= 0.0 || t >= 1.0 {
+        return 0.0;
+    }
+    6.0 * t * (1.0 - t) / (edge1 - edge0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Scattered points over a few hundred lattice cells, negative coordinates included
+    fn points(seed: u32, n: usize) -> Vec<Vec3> {
+        let mut rng = HashRng::new(0, 0, 0, seed);
+        (0..n)
+            .map(|_| Vec3::new(rng.range(-300.0, 300.0), rng.range(-300.0, 300.0), rng.range(-300.0, 300.0)))
+            .collect()
+    }
+
+    #[test]
+    fn simplex_stays_within_bound() {
+        let bound = NoiseBasis::Simplex.bound();
+        for seed in [0, 1, 42, 0xDEAD_BEEF] {
+            for p in points(seed, 20_000) {
+                let v2 = simplex2d(p.x, p.z, seed);
+                let v3 = simplex3d(p.x, p.y, p.z, seed);
+                assert!(v2.abs() <= bound, "simplex2d({p}, {seed}) = {v2}");
+                assert!(v3.abs() <= bound, "simplex3d({p}, {seed}) = {v3}");
+            }
+        }
+    }
+
+    #[test]
+    fn fbm_stays_within_bounds() {
+        for basis in [NoiseBasis::Perlin, NoiseBasis::Simplex] {
+            let (fbm, ridged) = (fbm3d_bounds(basis, 5, 0.5), ridged_fbm3d_bounds(basis, 5, 0.5));
+            for p in points(7, 20_000) {
+                let v = fbm3d(basis, p.x, p.y, p.z, 7, 5, 2.0, 0.5);
+                let r = ridged_fbm3d(basis, p.x, p.y, p.z, 7, 5, 2.0, 0.5);
+                assert!(fbm.lo <= v && v <= fbm.hi, "{basis:?} fbm3d({p}) = {v} outside {fbm:?}");
+                assert!(ridged.lo <= r && r <= ridged.hi, "{basis:?} ridged_fbm3d({p}) = {r} outside {ridged:?}");
+            }
+        }
+    }
+
+    // Lane arrays of scattered points, negative coordinates included
+    fn lane_points(seed: u32, batches: usize) -> Vec<(Lanes, Lanes, Lanes)> {
+        points(seed, batches * LANES)
+            .chunks_exact(LANES)
+            .map(|p| (std::array::from_fn(|l| p[l].x), std::array::from_fn(|l| p[l].y), std::array::from_fn(|l| p[l].z)))
+            .collect()
+    }
+
+    #[test]
+    fn perlin_lanes_match_scalar_bits() {
+        for seed in [0, 5, 0xFFFF_FFFF] {
+            for period in [0, 1, 3, 16, 257] {
+                for (x, y, z) in lane_points(seed, 2000) {
+                    let lanes = perlin3d_lanes_periodic(&x, &y, &z, seed, period);
+                    for l in 0..LANES {
+                        let scalar = perlin3d_periodic(x[l], y[l], z[l], seed, period);
+                        assert_eq!(
+                            lanes[l].to_bits(),
+                            scalar.to_bits(),
+                            "period {period} at ({}, {}, {}): {} vs {scalar}",
+                            x[l],
+                            y[l],
+                            z[l],
+                            lanes[l]
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fbm_lanes_match_scalar_bits() {
+        let bases = [NoiseBasis::Perlin, NoiseBasis::Simplex, NoiseBasis::PeriodicPerlin { period: 12 }];
+        for basis in bases {
+            for (x, y, z) in lane_points(9, 500) {
+                let fbm = fbm3d_lanes(basis, &x, &y, &z, 9, 4, 2.0, 0.5);
+                let ridged = ridged_fbm3d_lanes(basis, &x, &y, &z, 9, 4, 3.0, 0.45);
+                for l in 0..LANES {
+                    assert_eq!(fbm[l].to_bits(), fbm3d(basis, x[l], y[l], z[l], 9, 4, 2.0, 0.5).to_bits(), "{basis:?}");
+                    assert_eq!(
+                        ridged[l].to_bits(),
+                        ridged_fbm3d(basis, x[l], y[l], z[l], 9, 4, 3.0, 0.45).to_bits(),
+                        "{basis:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn octave_seeds_wrap_near_the_top_of_the_range() {
+        let seed = u32::MAX - 1;
+        let (x, y, z) = ([0.3; LANES], [-1.7; LANES], [2.2; LANES]);
+        for basis in [NoiseBasis::Perlin, NoiseBasis::Simplex] {
+            let fbm = fbm3d(basis, 0.3, -1.7, 2.2, seed, 6, 2.0, 0.5);
+            assert_eq!(fbm3d_lanes(basis, &x, &y, &z, seed, 6, 2.0, 0.5)[0].to_bits(), fbm.to_bits());
+            assert!((fbm3d_deriv(basis, 0.3, -1.7, 2.2, seed, 6, 2.0, 0.5).0 - fbm).abs() < 1e-5);
+            let ridged = ridged_fbm3d(basis, 0.3, -1.7, 2.2, seed, 6, 2.0, 0.5);
+            assert_eq!(ridged_fbm3d_lanes(basis, &x, &y, &z, seed, 6, 2.0, 0.5)[0].to_bits(), ridged.to_bits());
+            assert!((ridged_fbm3d_deriv(basis, 0.3, -1.7, 2.2, seed, 6, 2.0, 0.5).0 - ridged).abs() < 1e-5);
+        }
+        let warp = DomainWarp {
+            amplitude: 4.0,
+            scale: 16.0,
+            octaves: 3,
+            lacunarity: 2.0,
+            gain: 0.5,
+            iterations: 3,
+            basis: NoiseBasis::Perlin,
+            seed_offset: 0,
+        };
+        let (wx, _, wz) = warp.warp3d(5.0, 0.0, -9.0, seed);
+        assert!(wx.is_finite() && wz.is_finite());
+        let (wx, wz) = warp.warp2d(5.0, -9.0, seed);
+        assert!(wx.is_finite() && wz.is_finite());
+    }
+
+    // F1 and F2 over every cell within `reach`, for checking the pruned ring search
+    fn worley_brute_force(p: Vec3, seed: u32, metric: DistanceMetric, reach: i32, planar: bool) -> (f32, f32) {
+        let (mut f1, mut f2) = (f32::MAX, f32::MAX);
+        let (xi, yi, zi) = (p.x.floor() as i32, p.y.floor() as i32, p.z.floor() as i32);
+        let z_reach = if planar { 0 } else { reach };
+        for cz in zi - z_reach..=zi + z_reach {
+            for cy in yi - reach..=yi + reach {
+                for cx in xi - reach..=xi + reach {
+                    let (id, pz) = if planar {
+                        (lattice_hash2(cx, cy, seed), p.z)
+                    } else {
+                        let id = lattice_hash3(cx, cy, cz, seed);
+                        (id, cz as f32 + unit_from_hash(hash_u32(id, 3)))
+                    };
+                    let px = cx as f32 + unit_from_hash(hash_u32(id, 1));
+                    let py = cy as f32 + unit_from_hash(hash_u32(id, 2));
+                    let d = metric.distance(px - p.x, py - p.y, pz - p.z);
+                    if d < f1 {
+                        (f1, f2) = (d, f1);
+                    } else if d < f2 {
+                        f2 = d;
+                    }
+                }
+            }
+        }
+        (f1, f2)
+    }
+
+    #[test]
+    fn worley_finds_the_two_nearest_points() {
+        for metric in [DistanceMetric::Euclidean, DistanceMetric::Manhattan, DistanceMetric::Chebyshev] {
+            for p in points(17, 4000) {
+                let c2 = worley2d(p.x, p.y, 17, metric);
+                assert_eq!((c2.f1, c2.f2), worley_brute_force(p, 17, metric, 4, true), "{metric:?} 2D at {p}");
+                let c3 = worley3d(p.x, p.y, p.z, 17, metric);
+                assert_eq!((c3.f1, c3.f2), worley_brute_force(p, 17, metric, 4, false), "{metric:?} 3D at {p}");
+            }
+        }
+    }
+
+    #[test]
+    fn simplex_is_deterministic_per_seed() {
+        let pts = points(3, 1000);
+        let sample = |seed| -> Vec<(f32, f32)> {
+            pts.iter().map(|p| (simplex2d(p.x, p.z, seed), simplex3d(p.x, p.y, p.z, seed))).collect()
+        };
+        assert_eq!(sample(11), sample(11));
+        let (a, b) = (sample(11), sample(12));
+        let differing = a.iter().zip(&b).filter(|(a, b)| a.0 != b.0 && a.1 != b.1).count();
+        assert!(differing > pts.len() * 9 / 10, "only {differing} of {} points changed with the seed", pts.len());
+    }
+}
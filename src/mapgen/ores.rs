@@ -0,0 +1,181 @@
+// Ore veins and mineral blobs placed inside host materials
+use super::noise::{hash_u32, HashRng};
+use super::wrap::WrapConfig;
+use crate::{get_zxy_index, CS, CS_P};
+use glam::{IVec3, Vec3};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OreShape {
+    /// Roughly spherical cluster, `size` voxels across.
+    #[default]
+    Blob,
+    /// Wandering tube, `size` voxels long.
+    Vein,
+}
+
+/// One `[[ores]]` entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OreConfig {
+    #[serde(default)]
+    pub name: String,
+    pub material: u8,
+    /// Materials the ore may replace; air is never replaced.
+    #[serde(default = "default_host")]
+    pub host: Vec<u8>,
+    #[serde(default)]
+    pub shape: OreShape,
+    /// Capped below `CS` so a deposit never reaches past the neighbouring chunk.
+    pub size: f32,
+    /// Average deposits started per chunk; the fraction is a probability.
+    pub per_chunk: f32,
+    #[serde(default = "default_min_y")]
+    pub min_y: f32,
+    #[serde(default = "default_max_y")]
+    pub max_y: f32,
+}
+
+fn default_host() -> Vec<u8> {
+    vec![1]
+}
+
+fn default_min_y() -> f32 {
+    f32::MIN
+}
+
+fn default_max_y() -> f32 {
+    f32::MAX
+}
+
+fn random_dir(rng: &mut HashRng) -> Vec3 {
+    let v = Vec3::new(rng.unit(), rng.unit(), rng.unit()) * 2.0 - Vec3::ONE;
+    v.try_normalize().unwrap_or(Vec3::X)
+}
+
+/// Spheres making up every deposit of `ore` that starts in `chunk`. Depends only on the
+/// seed and chunk position, so any neighbour reproduces the same deposits.
+fn deposits(ore: &OreConfig, chunk: IVec3, seed: u32) -> Vec<(Vec3, f32)> {
+    let mut rng = HashRng::new(chunk.x, chunk.y, chunk.z, seed);
+    let size = ore.size.clamp(1.0, (CS - 2) as f32);
+    let count = ore.per_chunk.floor() as u32 + u32::from(rng.unit() < ore.per_chunk.fract());
+    let origin = (chunk * CS as i32).as_vec3();
+
+    let mut spheres = Vec::new();
+    for _ in 0..count {
+        let start = origin + Vec3::new(rng.unit(), rng.unit(), rng.unit()) * CS as f32;
+        if start.y < ore.min_y || start.y > ore.max_y {
+            continue;
+        }
+        match ore.shape {
+            OreShape::Blob => {
+                // A few overlapping spheres around the center for an irregular outline
+                let radius = size * 0.5;
+                spheres.push((start, radius * 0.7));
+                for _ in 0..3 {
+                    let offset = random_dir(&mut rng) * radius * 0.4 * rng.unit();
+                    spheres.push((start + offset, radius * (0.4 + 0.3 * rng.unit())));
+                }
+            }
+            OreShape::Vein => {
+                let mut pos = start;
+                let mut dir = random_dir(&mut rng);
+                for _ in 0..size as u32 {
+                    spheres.push((pos, 0.8 + 0.6 * rng.unit()));
+                    dir = (dir + random_dir(&mut rng) * 0.5).try_normalize().unwrap_or(dir);
+                    pos += dir;
+                }
+            }
+        }
+    }
+    spheres
+}
+
+/// Replaces host voxels of a padded chunk with ore. Deposits from the 26 neighbours are
+/// included, so veins continue across chunk borders (and through the padding ring). In a
+/// wrapped world, neighbours past the edge bring the deposits of the chunk they fold onto.
+pub fn place_ores(voxels: &mut [u8], chunk_pos: IVec3, seed: u32, ores: &[OreConfig], wrap: Option<&WrapConfig>) {
+    // World position of padded index 0
+    let base = chunk_pos * CS as i32 - IVec3::ONE;
+
+    for (index, ore) in ores.iter().enumerate() {
+        let ore_seed = seed.wrapping_add(hash_u32(index as u32, 0x0E5));
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let chunk = chunk_pos + IVec3::new(dx, dy, dz);
+                    let home = wrap.map_or(chunk, |w| w.chunk(chunk));
+                    let shift = ((chunk - home) * CS as i32).as_vec3();
+                    for (center, radius) in deposits(ore, home, ore_seed) {
+                        let center = center + shift;
+                        let lo = ((center - radius).floor().as_ivec3() - base).max(IVec3::ZERO);
+                        let hi = ((center + radius).ceil().as_ivec3() - base).min(IVec3::splat(CS_P as i32 - 1));
+                        for y in lo.y..=hi.y {
+                            for x in lo.x..=hi.x {
+                                for z in lo.z..=hi.z {
+                                    let p = (base + IVec3::new(x, y, z)).as_vec3() + Vec3::splat(0.5);
+                                    if p.distance_squared(center) > radius * radius {
+                                        continue;
+                                    }
+                                    let i = get_zxy_index(x as usize, y as usize, z as usize);
+                                    if voxels[i] != 0 && ore.host.contains(&voxels[i]) {
+                                        voxels[i] = ore.material;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CS_P3;
+
+    // A chunk of solid stone with `ores` placed into it
+    fn stone_with_ores(chunk_pos: IVec3, ores: &[OreConfig]) -> Vec<u8> {
+        let mut voxels = vec![1u8; CS_P3];
+        place_ores(&mut voxels, chunk_pos, 7, ores, None);
+        voxels
+    }
+
+    #[test]
+    fn neighbours_agree_on_shared_voxels_in_either_order() {
+        #[derive(Deserialize)]
+        struct Ores {
+            ores: Vec<OreConfig>,
+        }
+        let Ores { ores } = toml::from_str(
+            r#"
+            ores = [
+                { material = 7, shape = "vein", size = 40.0, per_chunk = 12.0 },
+                { material = 5, host = [1, 7], shape = "blob", size = 9.0, per_chunk = 8.0 },
+            ]
+            "#,
+        )
+        .unwrap();
+        let (west_pos, east_pos) = (IVec3::new(-1, 2, 0), IVec3::new(0, 2, 0));
+
+        let west = stone_with_ores(west_pos, &ores);
+        let east = stone_with_ores(east_pos, &ores);
+        let east_first = (stone_with_ores(east_pos, &ores), stone_with_ores(west_pos, &ores));
+        assert_eq!(east_first, (east.clone(), west.clone()));
+
+        // West's last layer is east's padding and west's padding east's first layer
+        let mut ore_on_border = false;
+        for z in 0..CS_P {
+            for y in 0..CS_P {
+                for (w, e) in [(CS, 0), (CS + 1, 1)] {
+                    let voxel = west[get_zxy_index(w, y, z)];
+                    assert_eq!(east[get_zxy_index(e, y, z)], voxel, "x {w}/{e}, y {y}, z {z}");
+                    ore_on_border |= voxel != 1;
+                }
+            }
+        }
+        assert!(ore_on_border, "no deposit crosses the border");
+    }
+}
@@ -0,0 +1,163 @@
+// Generator algorithms selectable by name from a config
+use super::analytic::{DebugGenerator, FlatGenerator};
+use super::config::MapGenConfig;
+use super::generators::{Generator, LayeredGenerator, MultiNoiseGenerator};
+use super::graph::GraphGenerator;
+use super::heightmap::HeightmapGenerator;
+use super::wrap::WrappedGenerator;
+use anyhow::{bail, Result};
+
+/// Builds a generator from a config that has already been validated.
+pub type GeneratorConstructor = fn(MapGenConfig) -> Result<Box<dyn Generator>>;
+
+/// Generator constructors by the name a config selects them with, `generator = "..."`.
+/// Each built-in one reads its own sections of the config:
+///
+/// - `multi_noise`: `[continentalness]`, `[erosion]`, `[peaks_valleys]`, `[weights]` and
+///   `[shaping]`
+/// - `layered`: `[[layers]]`
+/// - `heightmap`: `[heightmap]`
+/// - `graph`: `[graph]`
+/// - `flat`: `[flat]`
+/// - `debug`: `[debug]`
+pub struct GeneratorRegistry {
+    constructors: Vec<(&'static str, GeneratorConstructor)>,
+}
+
+impl Default for GeneratorRegistry {
+    fn default() -> Self {
+        let constructors: Vec<(&'static str, GeneratorConstructor)> = vec![
+            ("multi_noise", |config| Ok(Box::new(MultiNoiseGenerator::new(config)))),
+            ("layered", |config| Ok(Box::new(LayeredGenerator::new(config)?))),
+            ("heightmap", |config| Ok(Box::new(HeightmapGenerator::new(config)?))),
+            ("graph", |config| Ok(Box::new(GraphGenerator::new(config)?))),
+            ("flat", |config| Ok(Box::new(FlatGenerator::new(config)?))),
+            ("debug", |config| Ok(Box::new(DebugGenerator::new(config)?))),
+        ];
+        Self { constructors }
+    }
+}
+
+impl GeneratorRegistry {
+    /// Adds a generator, replacing any registered under the same name.
+    pub fn register(&mut self, name: &'static str, constructor: GeneratorConstructor) {
+        match self.constructors.iter_mut().find(|(n, _)| *n == name) {
+            Some(entry) => entry.1 = constructor,
+            None => self.constructors.push((name, constructor)),
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.constructors.iter().map(|(name, _)| *name)
+    }
+
+    /// Validates `config`, applies its presets and `[wrap]`, and builds the generator it
+    /// names. Configs without a `generator` key pick one from their sections, see
+    /// `implied_generator`.
+    pub fn build(&self, mut config: MapGenConfig) -> Result<Box<dyn Generator>> {
+        let name = config.generator.clone().unwrap_or_else(|| implied_generator(&config).to_string());
+        let Some(&(_, constructor)) = self.constructors.iter().find(|(n, _)| *n == name) else {
+            let known: Vec<_> = self.names().collect();
+            bail!("Unknown generator \"{name}\"; expected one of {}", known.join(", "));
+        };
+
+        for rule in &config.surface_rules {
+            rule.validate()?;
+        }
+        if let Some(sampling) = &config.sampling {
+            sampling.validate()?;
+        }
+        for structure in &config.structures {
+            structure.validate()?;
+        }
+        if let Some(shaping) = &config.shaping {
+            shaping.validate()?;
+        }
+        let wrap = config.wrap.clone();
+        if let Some(wrap) = &wrap {
+            wrap.validate(&config, &name)?;
+            wrap.tile(&mut config)?;
+        }
+
+        let generator = constructor(config)?;
+        Ok(match wrap {
+            Some(wrap) => Box::new(WrappedGenerator::new(generator, wrap)),
+            None => generator,
+        })
+    }
+}
+
+/// Generator for configs written before the `generator` key: a `[heightmap]` section selects
+/// `heightmap`, a `[graph]` selects `graph`, a `[[layers]]` stack selects `layered`, and
+/// anything else is `multi_noise`.
+pub fn implied_generator(config: &MapGenConfig) -> &'static str {
+    if config.heightmap.is_some() {
+        "heightmap"
+    } else if config.graph.is_some() {
+        "graph"
+    } else if !config.layers.is_empty() {
+        "layered"
+    } else {
+        "multi_noise"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::analytic::FlatConfig;
+
+    fn build_error(registry: &GeneratorRegistry, config: MapGenConfig) -> String {
+        match registry.build(config) {
+            Ok(_) => panic!("generator built"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    fn named(name: &str) -> MapGenConfig {
+        MapGenConfig {
+            generator: Some(name.to_string()),
+            ..MapGenConfig::default()
+        }
+    }
+
+    #[test]
+    fn unknown_names_list_the_registered_generators() {
+        let mut registry = GeneratorRegistry::default();
+        assert_eq!(
+            build_error(&registry, named("voronoi")),
+            "Unknown generator \"voronoi\"; expected one of multi_noise, layered, heightmap, graph, flat, debug"
+        );
+        registry.register("voronoi", |config| Ok(Box::new(FlatGenerator::new(config)?)));
+        assert!(registry.build(named("voronoi")).is_ok());
+        assert_eq!(
+            build_error(&registry, named("Flat")),
+            "Unknown generator \"Flat\"; expected one of multi_noise, layered, heightmap, graph, flat, debug, voronoi"
+        );
+    }
+
+    #[test]
+    fn registering_a_known_name_replaces_it() {
+        let mut registry = GeneratorRegistry::default();
+        registry.register("multi_noise", |mut config| {
+            config.flat = Some(FlatConfig { height: 3.0 });
+            Ok(Box::new(FlatGenerator::new(config)?))
+        });
+        assert_eq!(registry.names().filter(|&n| n == "multi_noise").count(), 1);
+        let generator = registry.build(MapGenConfig::default()).unwrap();
+        assert_eq!(generator.generate_density(0.0, 3.0, 0.0), generator.generate_density(50.0, 3.0, -50.0));
+        assert!(generator.generate_density(0.0, 2.0, 0.0) > generator.generate_density(0.0, 4.0, 0.0));
+    }
+
+    #[test]
+    fn configs_without_a_name_imply_one_from_their_sections() {
+        let load = |name: &str| {
+            let path = format!("{}/mapgen_configs/{name}.toml", env!("CARGO_MANIFEST_DIR"));
+            MapGenConfig::load_from_file(path).unwrap()
+        };
+        assert_eq!(implied_generator(&load("heightmap_island")), "heightmap");
+        assert_eq!(implied_generator(&load("graph_example")), "graph");
+        assert_eq!(implied_generator(&load("layers_example")), "layered");
+        assert_eq!(implied_generator(&load("default")), "multi_noise");
+    }
+}
@@ -0,0 +1,171 @@
+// Coarse density lattice: evaluate every few voxels and interpolate trilinearly
+use super::generators::Generator;
+use glam::IVec3;
+use serde::{Deserialize, Serialize};
+
+/// `[sampling]` section. Trades exactness for speed; `level_tool bench` reports both.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SamplingConfig {
+    /// Voxels between density evaluations; 1 evaluates every voxel.
+    #[serde(default = "default_spacing")]
+    pub spacing: u32,
+    /// Sample continentalness and erosion once per column (at y = 0) instead of per voxel.
+    /// Changes the terrain slightly, so it applies to every sampling path alike.
+    #[serde(default)]
+    pub column_channels: bool,
+}
+
+fn default_spacing() -> u32 {
+    4
+}
+
+impl SamplingConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            (1..=32).contains(&self.spacing),
+            "[sampling] spacing must be within 1..=32, got {}",
+            self.spacing
+        );
+        Ok(())
+    }
+}
+
+/// Densities on a lattice aligned to world multiples of `spacing`, covering a box of world
+/// voxels. Every box sees the same node values, so interpolated densities agree wherever
+/// two boxes overlap (neighbouring chunks, structure surface searches).
+pub struct DensityLattice {
+    spacing: i32,
+    /// World position of the first node.
+    origin: IVec3,
+    /// Node counts per axis.
+    dims: IVec3,
+    /// Node columns, each contiguous in Y, ordered by Z then X.
+    values: Vec<f32>,
+}
+
+impl DensityLattice {
+    /// Evaluates the nodes covering world voxels `lo..=hi`, one `generate_column` call per
+    /// node column so column-level quantities are computed once.
+    pub fn new<G: Generator + ?Sized>(generator: &G, spacing: u32, lo: IVec3, hi: IVec3) -> Self {
+        let (origin, last) = Self::extent(spacing, lo, hi);
+        let spacing = spacing.max(1) as i32;
+        let dims = (last - origin) / spacing + IVec3::ONE;
+
+        let ys: Vec<f32> = (0..dims.y).map(|i| (origin.y + i * spacing) as f32).collect();
+        let mut values = vec![0.0; (dims.x * dims.y * dims.z) as usize];
+        for (n, column) in values.chunks_mut(dims.y as usize).enumerate() {
+            let (ix, iz) = (n as i32 % dims.x, n as i32 / dims.x);
+            let wx = (origin.x + ix * spacing) as f32;
+            let wz = (origin.z + iz * spacing) as f32;
+            generator.generate_column(wx, wz, &ys, column);
+        }
+        Self {
+            spacing,
+            origin,
+            dims,
+            values,
+        }
+    }
+
+    /// World positions of the first and last node covering world voxels `lo..=hi`;
+    /// sampled densities only depend on nodes in between.
+    pub fn extent(spacing: u32, lo: IVec3, hi: IVec3) -> (IVec3, IVec3) {
+        let spacing = spacing.max(1) as i32;
+        let origin = lo.div_euclid(IVec3::splat(spacing)) * spacing;
+        let last = (hi + IVec3::splat(spacing - 1)).div_euclid(IVec3::splat(spacing)) * spacing;
+        (origin, last)
+    }
+
+    fn node(&self, x: i32, y: i32, z: i32) -> f32 {
+        self.values[((z * self.dims.x + x) * self.dims.y + y) as usize]
+    }
+
+    /// Trilinear density at a world voxel inside the box the lattice was built for.
+    pub fn sample(&self, wx: i32, wy: i32, wz: i32) -> f32 {
+        let p = IVec3::new(wx, wy, wz) - self.origin;
+        let i = p / self.spacing;
+        let t = (p % self.spacing).as_vec3() / self.spacing as f32;
+        // On the last node the upper neighbour has zero weight
+        let j = (i + IVec3::ONE).min(self.dims - IVec3::ONE);
+
+        let lerp = |a: f32, b: f32, t: f32| a + t * (b - a);
+        let plane = |y: i32| {
+            let near = lerp(self.node(i.x, y, i.z), self.node(j.x, y, i.z), t.x);
+            let far = lerp(self.node(i.x, y, j.z), self.node(j.x, y, j.z), t.x);
+            lerp(near, far, t.z)
+        };
+        lerp(plane(i.y), plane(j.y), t.y)
+    }
+}
+
+/// Lattice spacing a config asks for, `None` for the exact per-voxel path.
+pub fn lattice_spacing(sampling: Option<&SamplingConfig>) -> Option<u32> {
+    sampling.map(|s| s.spacing).filter(|&spacing| spacing > 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::analytic::FlatConfig;
+    use crate::mapgen::config::MapGenConfig;
+    use crate::mapgen::generators::generator_from_config;
+
+    fn load(name: &str) -> MapGenConfig {
+        let path = format!("{}/mapgen_configs/{name}.toml", env!("CARGO_MANIFEST_DIR"));
+        MapGenConfig::load_from_file(path).unwrap()
+    }
+
+    #[test]
+    fn nodes_sample_the_exact_density() {
+        let generator = generator_from_config(load("default")).unwrap();
+        let (lo, hi) = (IVec3::new(-13, -30, 5), IVec3::new(20, 9, 41));
+        for spacing in [2, 4, 5] {
+            let lattice = DensityLattice::new(&*generator, spacing, lo, hi);
+            let s = spacing as i32;
+            let first = lo.div_euclid(IVec3::splat(s)) * s + IVec3::splat(s);
+            for z in (first.z..=hi.z).step_by(spacing as usize) {
+                for y in (first.y..=hi.y).step_by(spacing as usize) {
+                    for x in (first.x..=hi.x).step_by(spacing as usize) {
+                        let exact = generator.generate_density(x as f32, y as f32, z as f32);
+                        let sampled = lattice.sample(x, y, z);
+                        let at = format!("spacing {spacing} at ({x}, {y}, {z})");
+                        assert!((sampled - exact).abs() < 1e-5, "{at}: {sampled} vs {exact}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn linear_densities_interpolate_exactly() {
+        // Flat ground's density falls linearly with height, so trilinear samples are exact
+        let config = MapGenConfig {
+            generator: Some("flat".to_string()),
+            flat: Some(FlatConfig { height: 7.0 }),
+            ..MapGenConfig::default()
+        };
+        let generator = generator_from_config(config).unwrap();
+        let (lo, hi) = (IVec3::new(-9, -20, -9), IVec3::new(9, 20, 9));
+        let lattice = DensityLattice::new(&*generator, 8, lo, hi);
+        for y in lo.y..=hi.y {
+            for (x, z) in [(-9, -9), (0, 3), (9, 9), (-1, 8)] {
+                let exact = generator.generate_density(x as f32, y as f32, z as f32);
+                assert!((lattice.sample(x, y, z) - exact).abs() < 1e-5, "({x}, {y}, {z})");
+            }
+        }
+    }
+
+    #[test]
+    fn overlapping_boxes_agree() {
+        let generator = generator_from_config(load("default")).unwrap();
+        let a = DensityLattice::new(&*generator, 4, IVec3::new(-1, -1, -1), IVec3::new(62, 62, 62));
+        let b = DensityLattice::new(&*generator, 4, IVec3::new(61, -1, -1), IVec3::new(124, 62, 62));
+        for z in -1..=62 {
+            for y in -1..=62 {
+                for x in [61, 62] {
+                    assert_eq!(a.sample(x, y, z).to_bits(), b.sample(x, y, z).to_bits(), "({x}, {y}, {z})");
+                }
+            }
+        }
+    }
+}
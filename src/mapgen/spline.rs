@@ -0,0 +1,393 @@
+// Piecewise cubic splines over the noise channels, for designer-controlled terrain shaping
+use super::interval::Interval;
+use anyhow::{bail, Result};
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SplineCoordinate {
+    Continentalness,
+    Erosion,
+    PeaksValleys,
+}
+
+/// Noise channel values a spline is evaluated at, each roughly in [-1, 1].
+#[derive(Debug, Clone, Copy)]
+pub struct NoiseChannels {
+    pub continentalness: f32,
+    pub erosion: f32,
+    pub peaks_valleys: f32,
+}
+
+impl NoiseChannels {
+    fn get(&self, coordinate: SplineCoordinate) -> f32 {
+        match coordinate {
+            SplineCoordinate::Continentalness => self.continentalness,
+            SplineCoordinate::Erosion => self.erosion,
+            SplineCoordinate::PeaksValleys => self.peaks_valleys,
+        }
+    }
+}
+
+/// Gradients of the channels with respect to world position.
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelGradients {
+    pub continentalness: Vec3,
+    pub erosion: Vec3,
+    pub peaks_valleys: Vec3,
+}
+
+impl ChannelGradients {
+    fn get(&self, coordinate: SplineCoordinate) -> Vec3 {
+        match coordinate {
+            SplineCoordinate::Continentalness => self.continentalness,
+            SplineCoordinate::Erosion => self.erosion,
+            SplineCoordinate::PeaksValleys => self.peaks_valleys,
+        }
+    }
+}
+
+/// Ranges the channels can take over a region.
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelBounds {
+    pub continentalness: Interval,
+    pub erosion: Interval,
+    pub peaks_valleys: Interval,
+}
+
+impl ChannelBounds {
+    fn get(&self, coordinate: SplineCoordinate) -> Interval {
+        match coordinate {
+            SplineCoordinate::Continentalness => self.continentalness,
+            SplineCoordinate::Erosion => self.erosion,
+            SplineCoordinate::PeaksValleys => self.peaks_valleys,
+        }
+    }
+}
+
+/// A constant or a nested spline, so curves can vary along several channels:
+///
+/// ```toml
+/// offset = { coordinate = "continentalness", points = [
+///     { location = -0.5, value = -20.0 },
+///     { location = 0.2, value = { coordinate = "erosion", points = [
+///         { location = -1.0, value = 60.0 },
+///         { location = 1.0, value = 8.0 },
+///     ] } },
+/// ] }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SplineValue {
+    Constant(f32),
+    Spline(Box<Spline>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Spline {
+    pub coordinate: SplineCoordinate,
+    /// Sorted by strictly increasing `location`.
+    pub points: Vec<SplinePoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplinePoint {
+    pub location: f32,
+    pub value: SplineValue,
+    /// Slope at this point; when omitted it follows the neighbouring points (Catmull-Rom).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derivative: Option<f32>,
+}
+
+impl SplineValue {
+    pub fn eval(&self, channels: &NoiseChannels) -> f32 {
+        match self {
+            SplineValue::Constant(v) => *v,
+            SplineValue::Spline(spline) => spline.eval(channels),
+        }
+    }
+
+    /// Value and gradient with respect to world position, given the channel gradients.
+    pub fn eval_gradient(&self, channels: &NoiseChannels, gradients: &ChannelGradients) -> (f32, Vec3) {
+        match self {
+            SplineValue::Constant(v) => (*v, Vec3::ZERO),
+            SplineValue::Spline(spline) => spline.eval_gradient(channels, gradients),
+        }
+    }
+
+    /// Range of `eval` while the channels stay within `ranges`.
+    pub fn bounds(&self, ranges: &ChannelBounds) -> Interval {
+        match self {
+            SplineValue::Constant(v) => Interval::point(*v),
+            SplineValue::Spline(spline) => spline.bounds(ranges),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            SplineValue::Constant(_) => Ok(()),
+            SplineValue::Spline(spline) => spline.validate(),
+        }
+    }
+}
+
+impl Spline {
+    pub fn validate(&self) -> Result<()> {
+        anyhow::ensure!(!self.points.is_empty(), "Spline over {:?} has no points", self.coordinate);
+        for pair in self.points.windows(2) {
+            if pair[1].location <= pair[0].location {
+                bail!(
+                    "Spline over {:?} has points out of order ({} then {})",
+                    self.coordinate,
+                    pair[0].location,
+                    pair[1].location
+                );
+            }
+        }
+        self.points.iter().try_for_each(|p| p.value.validate())
+    }
+
+    /// Cubic Hermite interpolation between the two points around the channel value;
+    /// clamped to the end values outside the point range.
+    pub fn eval(&self, channels: &NoiseChannels) -> f32 {
+        let x = channels.get(self.coordinate);
+        let points = &self.points;
+        let last = points.len() - 1;
+        if x <= points[0].location {
+            return points[0].value.eval(channels);
+        }
+        if x >= points[last].location {
+            return points[last].value.eval(channels);
+        }
+
+        // A NaN channel fails both end checks and lands on the first segment
+        let i = points.partition_point(|p| p.location <= x).saturating_sub(1);
+        // Points `i - 1 ..= i + 2` feed the segment; each is evaluated at most once, so
+        // nested splines cost no more than their own points
+        let first = i.saturating_sub(1);
+        let values: [Cell<Option<f32>>; 4] = Default::default();
+        let value = |k: usize| cached(&values[k - first], || points[k].value.eval(channels));
+        let slope = |k: usize| -> f32 {
+            if let Some(d) = points[k].derivative {
+                return d;
+            }
+            let (lo, hi) = (k.saturating_sub(1), (k + 1).min(last));
+            (value(hi) - value(lo)) / (points[hi].location - points[lo].location)
+        };
+
+        let (x0, x1) = (points[i].location, points[i + 1].location);
+        let h = x1 - x0;
+        hermite((x - x0) / h, h, value(i), value(i + 1), slope(i), slope(i + 1))
+    }
+
+    /// `eval` with its gradient: the chain rule through the channel this spline follows
+    /// plus the gradients of nested point values.
+    pub fn eval_gradient(&self, channels: &NoiseChannels, gradients: &ChannelGradients) -> (f32, Vec3) {
+        let x = channels.get(self.coordinate);
+        let points = &self.points;
+        let last = points.len() - 1;
+        if x <= points[0].location {
+            return points[0].value.eval_gradient(channels, gradients);
+        }
+        if x >= points[last].location {
+            return points[last].value.eval_gradient(channels, gradients);
+        }
+
+        let i = points.partition_point(|p| p.location <= x).saturating_sub(1);
+        let first = i.saturating_sub(1);
+        let values: [Cell<Option<(f32, Vec3)>>; 4] = Default::default();
+        let value = |k: usize| cached(&values[k - first], || points[k].value.eval_gradient(channels, gradients));
+        let slope = |k: usize| -> (f32, Vec3) {
+            if let Some(d) = points[k].derivative {
+                return (d, Vec3::ZERO);
+            }
+            let (lo, hi) = (k.saturating_sub(1), (k + 1).min(last));
+            let span = points[hi].location - points[lo].location;
+            let ((v_lo, g_lo), (v_hi, g_hi)) = (value(lo), value(hi));
+            ((v_hi - v_lo) / span, (g_hi - g_lo) / span)
+        };
+
+        let (x0, x1) = (points[i].location, points[i + 1].location);
+        let h = x1 - x0;
+        let t = (x - x0) / h;
+        let (t2, t3) = (t * t, t * t * t);
+        let ((v0, g0), (v1, g1)) = (value(i), value(i + 1));
+        let ((m0, gm0), (m1, gm1)) = (slope(i), slope(i + 1));
+        let (h00, h10, h01, h11) = (2.0 * t3 - 3.0 * t2 + 1.0, t3 - 2.0 * t2 + t, -2.0 * t3 + 3.0 * t2, t3 - t2);
+        // Basis derivatives with respect to t
+        let (d00, d10, d01, d11) = (6.0 * t2 - 6.0 * t, 3.0 * t2 - 4.0 * t + 1.0, -6.0 * t2 + 6.0 * t, 3.0 * t2 - 2.0 * t);
+
+        let value = h00 * v0 + h10 * h * m0 + h01 * v1 + h11 * h * m1;
+        let d_dx = (d00 * v0 + d10 * h * m0 + d01 * v1 + d11 * h * m1) / h;
+        let gradient = gradients.get(self.coordinate) * d_dx + g0 * h00 + gm0 * (h10 * h) + g1 * h01 + gm1 * (h11 * h);
+        (value, gradient)
+    }
+
+    /// Range of `eval` while the channels stay within `ranges`.
+    pub fn bounds(&self, ranges: &ChannelBounds) -> Interval {
+        let x = ranges.get(self.coordinate);
+        let points = &self.points;
+        let last = points.len() - 1;
+        let values: Vec<Cell<Option<Interval>>> = points.iter().map(|_| Cell::new(None)).collect();
+        let value = |k: usize| cached(&values[k], || points[k].value.bounds(ranges));
+        let slope = |k: usize| -> f32 {
+            if let Some(d) = points[k].derivative {
+                return d.abs();
+            }
+            let (lo, hi) = (k.saturating_sub(1), (k + 1).min(last));
+            ((value(hi) - value(lo)) * (points[hi].location - points[lo].location).recip()).magnitude()
+        };
+
+        let before = (x.lo <= points[0].location).then(|| value(0));
+        let after = (x.hi >= points[last].location).then(|| value(last));
+        let segments = (0..last)
+            .filter(|&i| x.hi >= points[i].location && x.lo <= points[i + 1].location)
+            .map(|i| {
+                let h = points[i + 1].location - points[i].location;
+                hermite_bounds(h, value(i), value(i + 1), slope(i), slope(i + 1))
+            });
+        before
+            .into_iter()
+            .chain(after)
+            .chain(segments)
+            .reduce(Interval::hull)
+            .expect("a channel range always reaches a point or a segment")
+    }
+}
+
+// The value in `cell`, computed by `f` on first use
+fn cached<T: Copy>(cell: &Cell<Option<T>>, f: impl FnOnce() -> T) -> T {
+    cell.get().unwrap_or_else(|| {
+        let v = f();
+        cell.set(Some(v));
+        v
+    })
+}
+
+/// Cubic Hermite curve from `v0` to `v1` over a segment `h` long with end slopes `m0` and
+/// `m1`, at `t` in 0..1.
+pub fn hermite(t: f32, h: f32, v0: f32, v1: f32, m0: f32, m1: f32) -> f32 {
+    let (t2, t3) = (t * t, t * t * t);
+    (2.0 * t3 - 3.0 * t2 + 1.0) * v0 + (t3 - 2.0 * t2 + t) * h * m0 + (-2.0 * t3 + 3.0 * t2) * v1 + (t3 - t2) * h * m1
+}
+
+/// Range of `hermite` over a whole segment, for end values within `v0` and `v1` and slopes
+/// of magnitude at most `m0` and `m1`. The curve blends the end values with weights in
+/// 0..1, and the tangent terms add at most `4/27 * h * (|m0| + |m1|)`.
+pub fn hermite_bounds(h: f32, v0: Interval, v1: Interval, m0: f32, m1: f32) -> Interval {
+    let tangent = 4.0 / 27.0 * h * (m0 + m1);
+    v0.hull(v1) + Interval::new(-tangent, tangent)
+}
+
+/// `[shaping]` section. When present, `MultiNoiseGenerator` drops its fixed remapping and uses
+/// `density = factor * (offset + jaggedness * peaks_valleys - y)`: `offset` is the surface
+/// height in voxels, `factor` the density change per voxel and `jaggedness` the height of
+/// the 3D peaks noise on top.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerrainShaping {
+    pub offset: SplineValue,
+    #[serde(default = "default_factor")]
+    pub factor: SplineValue,
+    #[serde(default = "default_jaggedness")]
+    pub jaggedness: SplineValue,
+}
+
+fn default_factor() -> SplineValue {
+    SplineValue::Constant(0.05)
+}
+
+fn default_jaggedness() -> SplineValue {
+    SplineValue::Constant(0.0)
+}
+
+impl TerrainShaping {
+    pub fn validate(&self) -> Result<()> {
+        self.offset.validate()?;
+        self.factor.validate()?;
+        self.jaggedness.validate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::config::MapGenConfig;
+
+    fn shaping() -> TerrainShaping {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/mapgen_configs/splines.toml");
+        MapGenConfig::load_from_file(path).unwrap().shaping.unwrap()
+    }
+
+    // Channel values on a grid over a range, knots and the range ends included
+    fn grid(range: Interval) -> impl Iterator<Item = f32> + Clone {
+        (0..=40).map(move |i| range.lo + (range.hi - range.lo) * i as f32 / 40.0)
+    }
+
+    #[test]
+    fn bounds_contain_sampled_values() {
+        let shaping = shaping();
+        // Slopes climbing into the segment from both ends overshoot both point values
+        let overshoot: SplineValue = toml::from_str::<TerrainShaping>(
+            "offset = { coordinate = \"continentalness\", points = [\n\
+                 { location = -0.2, value = 0.0, derivative = 80.0 },\n\
+                 { location = 0.2, value = 4.0, derivative = -80.0 },\n\
+             ] }",
+        )
+        .unwrap()
+        .offset;
+        let ranges = [(-1.0, 1.0), (-0.35, -0.05), (-0.05, 0.1), (0.1, 0.25), (0.15, 0.9)];
+        for spline in [&shaping.offset, &shaping.jaggedness, &overshoot] {
+            for &(c_lo, c_hi) in &ranges {
+                for &(e_lo, e_hi) in &ranges {
+                    let ranges = ChannelBounds {
+                        continentalness: Interval::new(c_lo, c_hi),
+                        erosion: Interval::new(e_lo, e_hi),
+                        peaks_valleys: Interval::new(-1.0, 1.0),
+                    };
+                    let bounds = spline.bounds(&ranges);
+                    for continentalness in grid(ranges.continentalness) {
+                        for erosion in grid(ranges.erosion) {
+                            let channels = NoiseChannels { continentalness, erosion, peaks_valleys: 0.0 };
+                            let v = spline.eval(&channels);
+                            assert!(bounds.lo <= v && v <= bounds.hi, "{v} at {channels:?} outside {bounds:?}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        let shaping = shaping();
+        // Channels as linear functions of world position, so their gradients are known
+        let gradients = ChannelGradients {
+            continentalness: Vec3::new(0.004, 0.001, -0.003),
+            erosion: Vec3::new(-0.002, 0.0, 0.005),
+            peaks_valleys: Vec3::ZERO,
+        };
+        let channels_at = |p: Vec3| NoiseChannels {
+            continentalness: gradients.continentalness.dot(p) - 0.4,
+            erosion: gradients.erosion.dot(p) - 0.5,
+            peaks_valleys: 0.0,
+        };
+        const H: f32 = 0.01;
+        for spline in [&shaping.offset, &shaping.jaggedness] {
+            for i in 0..400 {
+                let p = Vec3::new(i as f32 * 0.53, (i % 7) as f32, (i % 23) as f32 * 7.1);
+                let (v, gradient) = spline.eval_gradient(&channels_at(p), &gradients);
+                assert!((v - spline.eval(&channels_at(p))).abs() < 1e-4);
+                let numeric = Vec3::from_array(std::array::from_fn(|axis| {
+                    let offset = Vec3::AXES[axis] * H;
+                    (spline.eval(&channels_at(p + offset)) - spline.eval(&channels_at(p - offset))) / (2.0 * H)
+                }));
+                assert!(
+                    (gradient - numeric).length() < 1e-2 * (1.0 + numeric.length()),
+                    "at {p}: {gradient} vs {numeric}"
+                );
+            }
+        }
+    }
+}
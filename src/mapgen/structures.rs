@@ -0,0 +1,341 @@
+// Surface decorations (trees, boulders, voxel templates) that may straddle chunk borders
+use super::generators::{solid_material, Generator};
+use super::noise::{hash_u32, lattice_hash3, unit_from_hash, HashRng};
+use super::sampling::{lattice_spacing, DensityLattice};
+use super::surface::slope_at;
+use crate::{get_zxy_index, CS, CS_P};
+use anyhow::{bail, Result};
+use glam::IVec3;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// One `[[structures]]` entry. Anchors are seeded per chunk column, dropped onto the highest
+/// surface (solid voxel under air) between `min_y` and `max_y`, and kept if that voxel's
+/// surface material is in `on`, the ground is no steeper than `max_slope` and it is not
+/// under water.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StructureConfig {
+    #[serde(default)]
+    pub name: String,
+    /// Average anchors per chunk column; the fraction is a probability.
+    pub per_chunk: f32,
+    /// Surface materials the structure may stand on; empty allows any.
+    #[serde(default)]
+    pub on: Vec<u8>,
+    /// Steepest ground in degrees the structure may stand on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_slope: Option<f32>,
+    /// Vertical search range for the surface.
+    #[serde(default = "default_min_y")]
+    pub min_y: f32,
+    #[serde(default = "default_max_y")]
+    pub max_y: f32,
+    /// Voxels the structure is lowered into the ground.
+    #[serde(default)]
+    pub sink: i32,
+    pub shape: StructureShape,
+}
+
+fn default_min_y() -> f32 {
+    -128.0
+}
+
+fn default_max_y() -> f32 {
+    128.0
+}
+
+/// Structures only fill air, so they never cut into terrain or each other.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StructureShape {
+    /// Single-voxel trunk with a ragged spherical canopy around its top.
+    Tree {
+        trunk_material: u8,
+        leaf_material: u8,
+        /// Inclusive range.
+        trunk_height: [u32; 2],
+        canopy_radius: f32,
+    },
+    /// Ellipsoid resting on the anchor.
+    Boulder { material: u8, radius: [f32; 2] },
+    /// Explicit voxels. `layers` go bottom to top, each a list of rows along +Z whose
+    /// characters run along +X. `.` and spaces are empty; other characters look up `palette`.
+    /// The template is centered on the anchor in X / Z and its bottom layer sits on the surface.
+    Template {
+        palette: HashMap<String, u8>,
+        layers: Vec<Vec<String>>,
+    },
+}
+
+impl StructureConfig {
+    pub fn validate(&self) -> Result<()> {
+        let name = &self.name;
+        anyhow::ensure!(self.min_y < self.max_y, "Structure '{name}' has an empty min_y..max_y range");
+        match &self.shape {
+            StructureShape::Tree { trunk_height, canopy_radius, .. } => {
+                anyhow::ensure!(trunk_height[0] <= trunk_height[1], "Structure '{name}' has trunk_height min > max");
+                anyhow::ensure!(*canopy_radius >= 0.0, "Structure '{name}' has a negative canopy_radius");
+            }
+            StructureShape::Boulder { radius, .. } => {
+                anyhow::ensure!(0.0 < radius[0] && radius[0] <= radius[1], "Structure '{name}' needs 0 < radius min <= max");
+            }
+            StructureShape::Template { palette, layers } => {
+                for key in palette.keys() {
+                    anyhow::ensure!(key.chars().count() == 1, "Structure '{name}' palette key '{key}' is not one character");
+                }
+                for ch in layers.iter().flatten().flat_map(|row| row.chars()) {
+                    if ch != '.' && ch != ' ' && !palette.contains_key(ch.encode_utf8(&mut [0; 4]) as &str) {
+                        bail!("Structure '{name}' template uses '{ch}', which is not in its palette");
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Furthest horizontal distance from the anchor any voxel can reach.
+    fn reach(&self) -> i32 {
+        match &self.shape {
+            StructureShape::Tree { canopy_radius, .. } => canopy_radius.ceil() as i32,
+            StructureShape::Boulder { radius, .. } => radius[1].ceil() as i32,
+            StructureShape::Template { layers, .. } => {
+                let depth = layers.iter().map(Vec::len).max().unwrap_or(0);
+                let width = layers.iter().flatten().map(|r| r.chars().count()).max().unwrap_or(0);
+                depth.max(width) as i32 / 2 + 1
+            }
+        }
+    }
+
+    /// Highest voxel above the anchor.
+    fn height(&self) -> i32 {
+        let above = match &self.shape {
+            StructureShape::Tree { trunk_height, canopy_radius, .. } => trunk_height[1] as i32 + canopy_radius.ceil() as i32,
+            StructureShape::Boulder { radius, .. } => radius[1].ceil() as i32 + 1,
+            StructureShape::Template { layers, .. } => layers.len() as i32,
+        };
+        above - self.sink
+    }
+
+    /// Voxels relative to the anchor (the surface voxel), in a fixed order.
+    fn voxels(&self, rng: &mut HashRng, anchor: IVec3, seed: u32) -> Vec<(IVec3, u8)> {
+        let mut out = Vec::new();
+        let base = IVec3::new(0, 1 - self.sink, 0);
+        match &self.shape {
+            StructureShape::Tree {
+                trunk_material,
+                leaf_material,
+                trunk_height,
+                canopy_radius,
+            } => {
+                let height = trunk_height[0] + (rng.unit() * (trunk_height[1] - trunk_height[0] + 1) as f32) as u32;
+                let top = base + IVec3::Y * (height as i32 - 1);
+                for y in 0..height as i32 {
+                    out.push((base + IVec3::Y * y, *trunk_material));
+                }
+                let r = *canopy_radius;
+                let ri = r.ceil() as i32;
+                for y in -ri..=ri {
+                    for z in -ri..=ri {
+                        for x in -ri..=ri {
+                            let offset = IVec3::new(x, y, z);
+                            let d = offset.as_vec3().length();
+                            // Ragged edge: outer shell voxels are dropped by a world-position hash
+                            let world = anchor + top + offset;
+                            let edge = lattice_hash3(world.x, world.y, world.z, seed);
+                            if d <= r - 1.0 || (d <= r && unit_from_hash(edge) < 0.6) {
+                                out.push((top + offset, *leaf_material));
+                            }
+                        }
+                    }
+                }
+            }
+            StructureShape::Boulder { material, radius } => {
+                let rx = rng.range(radius[0], radius[1]);
+                let ry = rng.range(radius[0], radius[1]) * 0.75;
+                let rz = rng.range(radius[0], radius[1]);
+                let (ix, iy, iz) = (rx.ceil() as i32, ry.ceil() as i32, rz.ceil() as i32);
+                for y in -iy..=iy {
+                    for z in -iz..=iz {
+                        for x in -ix..=ix {
+                            let (fx, fy, fz) = (x as f32 / rx, y as f32 / ry, z as f32 / rz);
+                            if fx * fx + fy * fy + fz * fz <= 1.0 {
+                                out.push((base + IVec3::new(x, y, z), *material));
+                            }
+                        }
+                    }
+                }
+            }
+            StructureShape::Template { palette, layers } => {
+                let depth = layers.iter().map(Vec::len).max().unwrap_or(0) as i32;
+                let width = layers.iter().flatten().map(|r| r.chars().count()).max().unwrap_or(0) as i32;
+                for (y, layer) in layers.iter().enumerate() {
+                    for (z, row) in layer.iter().enumerate() {
+                        for (x, ch) in row.chars().enumerate() {
+                            if let Some(&material) = palette.get(ch.encode_utf8(&mut [0; 4]) as &str) {
+                                let offset = IVec3::new(x as i32 - width / 2, y as i32, z as i32 - depth / 2);
+                                out.push((base + offset, material));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Highest solid voxel with air above it at or below `max_y`, with its surface material.
+fn find_surface<G: Generator + ?Sized>(generator: &G, s: &StructureConfig, wx: i32, wz: i32) -> Option<(i32, u8)> {
+    let cfg = generator.config();
+    let (fx, fz) = (wx as f32, wz as f32);
+    let top = s.max_y.floor() as i32 + 1;
+    // Same lattice nodes as the chunks, so the surface found here is the generated one
+    let lattice = lattice_spacing(cfg.sampling.as_ref()).map(|spacing| {
+        let bottom = s.min_y.ceil() as i32;
+        DensityLattice::new(generator, spacing, IVec3::new(wx, bottom, wz), IVec3::new(wx, top, wz))
+    });
+    let density = |y: i32| match &lattice {
+        Some(lattice) => lattice.sample(wx, y, wz),
+        None => generator.generate_density(fx, y as f32, fz),
+    };
+    let mut air_above = false;
+    let mut y = top;
+    while y as f32 >= s.min_y {
+        let solid = density(y) > cfg.density_threshold;
+        if solid && air_above && (y as f32) <= s.max_y {
+            let biome = generator.biomes().map(|b| b.biome_at(fx, fz));
+            let material = solid_material(generator, biome, fx, y as f32, fz, 0);
+            return match &cfg.water {
+                // The highest surface is the sea floor: nothing grows underwater
+                Some(water) if (y + 1) as f32 <= water.sea_level => None,
+                Some(water) => Some((y, water.shore(y as f32, 0, material))),
+                None => Some((y, material)),
+            };
+        }
+        air_above = !solid;
+        y -= 1;
+    }
+    None
+}
+
+/// Writes every structure anchored within reach of this chunk into its padded voxels and
+/// returns how many voxels became solid. Anchors depend only on the seed and chunk column,
+/// and structures are applied in world order, so neighbouring chunks agree on shared voxels.
+/// In a wrapped world, anchors are seeded and placed by their position folded into the world.
+pub fn place_structures<G: Generator + ?Sized>(voxels: &mut [u8], chunk_pos: IVec3, generator: &G) -> usize {
+    let cfg = generator.config();
+    let fold = |v: IVec3| cfg.wrap.as_ref().map_or(v, |w| IVec3::new(w.voxel(v.x), v.y, w.voxel(v.z)));
+    // World position of padded index 0
+    let base = chunk_pos * CS as i32 - IVec3::ONE;
+    let cs = CS as i32;
+    let mut placed = 0;
+
+    for (index, structure) in cfg.structures.iter().enumerate() {
+        let seed = cfg.seed.wrapping_add(hash_u32(index as u32, 0x57C));
+        // Skip chunks no anchor of this structure can reach vertically
+        let top = structure.max_y.ceil() as i32 + structure.height();
+        let bottom = structure.min_y.floor() as i32 - structure.sink - structure.reach();
+        if base.y > top || base.y + CS_P as i32 <= bottom {
+            continue;
+        }
+
+        let r = structure.reach();
+        let reach = (r + 1 + cs - 1) / cs;
+        for cz in chunk_pos.z - reach..=chunk_pos.z + reach {
+            for cx in chunk_pos.x - reach..=chunk_pos.x + reach {
+                let column = IVec3::new(cx, 0, cz);
+                let home = cfg.wrap.as_ref().map_or(column, |w| w.chunk(column));
+                let mut rng = HashRng::new(home.x, 0, home.z, seed);
+                let count = structure.per_chunk.floor() as u32 + u32::from(rng.unit() < structure.per_chunk.fract());
+                for _ in 0..count {
+                    let wx = cx * cs + (rng.unit() * cs as f32) as i32;
+                    let wz = cz * cs + (rng.unit() * cs as f32) as i32;
+                    let folded = fold(IVec3::new(wx, 0, wz));
+                    // Per-anchor stream so shape randomness doesn't depend on rejected anchors
+                    let mut shape_rng = HashRng::new(folded.x, 1, folded.z, seed);
+
+                    // Cheap rejection before searching for the surface
+                    if wx + r < base.x || wx - r >= base.x + CS_P as i32 || wz + r < base.z || wz - r >= base.z + CS_P as i32 {
+                        continue;
+                    }
+                    let Some((wy, surface)) = find_surface(generator, structure, folded.x, folded.z) else {
+                        continue;
+                    };
+                    if !structure.on.is_empty() && !structure.on.contains(&surface) {
+                        continue;
+                    }
+                    if let Some(max_slope) = structure.max_slope
+                        && slope_at(generator, folded.x as f32, wy as f32, folded.z as f32) > max_slope
+                    {
+                        continue;
+                    }
+
+                    let anchor = IVec3::new(wx, wy, wz);
+                    for (offset, material) in structure.voxels(&mut shape_rng, fold(anchor), seed) {
+                        let local = anchor + offset - base;
+                        if local.cmplt(IVec3::ZERO).any() || local.cmpge(IVec3::splat(CS_P as i32)).any() {
+                            continue;
+                        }
+                        let i = get_zxy_index(local.x as usize, local.y as usize, local.z as usize);
+                        if voxels[i] == 0 {
+                            voxels[i] = material;
+                            placed += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    placed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::analytic::FlatConfig;
+    use crate::mapgen::config::MapGenConfig;
+    use crate::mapgen::generators::{generate_chunk_voxels, generator_from_config};
+
+    #[test]
+    fn structures_across_a_border_match_in_both_chunks() {
+        #[derive(Deserialize)]
+        struct Structures {
+            structures: Vec<StructureConfig>,
+        }
+        let Structures { structures } = toml::from_str(
+            r#"
+            [[structures]]
+            per_chunk = 24.0
+            shape = { kind = "tree", trunk_material = 6, leaf_material = 3, trunk_height = [3, 6], canopy_radius = 4.0 }
+
+            [[structures]]
+            per_chunk = 6.0
+            shape = { kind = "boulder", material = 2, radius = [2.0, 5.0] }
+            "#,
+        )
+        .unwrap();
+        let config = MapGenConfig {
+            generator: Some("flat".to_string()),
+            flat: Some(FlatConfig { height: 10.0 }),
+            structures,
+            ..MapGenConfig::default()
+        };
+        let generator = generator_from_config(config).unwrap();
+        let (west, _) = generate_chunk_voxels(IVec3::new(0, 0, 3), &*generator);
+        let (east, _) = generate_chunk_voxels(IVec3::new(1, 0, 3), &*generator);
+
+        // West's last layer is east's padding and west's padding east's first layer
+        let mut structure_voxels = 0;
+        for z in 0..CS_P {
+            for y in 0..CS_P {
+                for (w, e) in [(CS, 0), (CS + 1, 1)] {
+                    let voxel = west[get_zxy_index(w, y, z)];
+                    assert_eq!(east[get_zxy_index(e, y, z)], voxel, "x {w}/{e}, y {y}, z {z}");
+                    // Flat ground fills y <= 10, at padded y <= 11
+                    structure_voxels += (y > 11 && voxel != 0) as usize;
+                }
+            }
+        }
+        assert!(structure_voxels > 0, "no structure reaches across the border");
+    }
+}
@@ -0,0 +1,140 @@
+// Surface material rules keyed on depth below the nearest air, height, slope and biome
+use super::biome::BiomeConfig;
+use super::config::MaterialThresholds;
+use super::generators::Generator;
+use anyhow::Result;
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+/// One `[[surface_rules]]` entry. Rules are tried in order and the first one whose
+/// conditions all hold picks the material; unset conditions always hold.
+///
+/// ```toml
+/// [[surface_rules]]    # grass on gentle slopes only
+/// material = 3
+/// max_depth = 0
+/// max_slope = 45.0
+///
+/// [[surface_rules]]    # three voxels of soil under it
+/// material = 2
+/// max_depth = 3
+///
+/// [[surface_rules]]    # stone below
+/// material = 1
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SurfaceRule {
+    #[serde(default)]
+    pub name: String,
+    pub material: u8,
+    /// Solid voxels between this one and the nearest air above; 0 is the surface voxel.
+    #[serde(default)]
+    pub min_depth: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<u32>,
+    /// Inclusive world Y range.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_y: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_y: Option<f32>,
+    /// Steepness in degrees (0 flat, 90 vertical), from the generator's density gradient.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_slope: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_slope: Option<f32>,
+    /// Biome names the rule applies in; empty applies everywhere.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub biomes: Vec<String>,
+}
+
+impl SurfaceRule {
+    pub fn validate(&self) -> Result<()> {
+        let name = &self.name;
+        if let Some(max_depth) = self.max_depth {
+            anyhow::ensure!(self.min_depth <= max_depth, "Surface rule '{name}' has min_depth > max_depth");
+        }
+        if let (Some(lo), Some(hi)) = (self.min_y, self.max_y) {
+            anyhow::ensure!(lo <= hi, "Surface rule '{name}' has min_y > max_y");
+        }
+        if let (Some(lo), Some(hi)) = (self.min_slope, self.max_slope) {
+            anyhow::ensure!(lo <= hi, "Surface rule '{name}' has min_slope > max_slope");
+        }
+        Ok(())
+    }
+
+    fn needs_slope(&self) -> bool {
+        self.min_slope.is_some() || self.max_slope.is_some()
+    }
+
+    /// Every condition except the slope, which is costly and checked last.
+    fn matches_cheap(&self, wy: f32, depth: u32, biome: Option<&BiomeConfig>) -> bool {
+        depth >= self.min_depth
+            && self.max_depth.is_none_or(|max| depth <= max)
+            && self.min_y.is_none_or(|min| wy >= min)
+            && self.max_y.is_none_or(|max| wy <= max)
+            && (self.biomes.is_empty() || biome.is_some_and(|b| self.biomes.contains(&b.name)))
+    }
+
+    fn matches_slope(&self, slope: f32) -> bool {
+        self.min_slope.is_none_or(|min| slope >= min) && self.max_slope.is_none_or(|max| slope <= max)
+    }
+}
+
+impl MaterialThresholds {
+    /// The legacy height bands: stone below `stone_max`, grass below `grass_max`, dirt
+    /// above, regardless of depth.
+    pub fn material(&self, wy: f32) -> u8 {
+        if wy <= (self.stone_max - 1) as f32 {
+            1
+        } else if wy <= (self.grass_max - 1) as f32 {
+            3
+        } else {
+            2
+        }
+    }
+}
+
+/// Voxels below the surface the rules can tell apart; deeper voxels all look alike.
+pub fn max_depth(rules: &[SurfaceRule]) -> u32 {
+    rules
+        .iter()
+        .map(|r| r.max_depth.map_or(r.min_depth, |max| max + 1))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Angle in degrees between the surface a density gradient describes and the horizontal.
+pub fn steepness(gradient: Vec3) -> f32 {
+    gradient.x.hypot(gradient.z).atan2(gradient.y.abs()).to_degrees()
+}
+
+/// Terrain steepness in degrees at a world position.
+pub fn slope_at<G: Generator + ?Sized>(generator: &G, wx: f32, wy: f32, wz: f32) -> f32 {
+    steepness(generator.generate_density_and_gradient(wx, wy, wz).1)
+}
+
+/// Material from the first matching rule, or `None` when no rule matches.
+pub fn select_material<G: Generator + ?Sized>(
+    rules: &[SurfaceRule],
+    generator: &G,
+    biome: Option<&BiomeConfig>,
+    wx: f32,
+    wy: f32,
+    wz: f32,
+    depth: u32,
+) -> Option<u8> {
+    let mut slope = None;
+    for rule in rules {
+        if !rule.matches_cheap(wy, depth, biome) {
+            continue;
+        }
+        if rule.needs_slope() {
+            let slope = *slope.get_or_insert_with(|| slope_at(generator, wx, wy, wz));
+            if !rule.matches_slope(slope) {
+                continue;
+            }
+        }
+        return Some(rule.material);
+    }
+    None
+}
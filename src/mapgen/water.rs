@@ -0,0 +1,172 @@
+// Sea level water fill, beaches and ice
+use super::biome::BiomeConfig;
+use serde::{Deserialize, Serialize};
+
+/// `[water]` section. Air at or below `sea_level` becomes `material`, an ordinary opaque
+/// voxel, so the mesher culls faces between water and terrain like any other material.
+/// Cave-carved voxels stay dry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaterConfig {
+    pub sea_level: f32,
+    #[serde(default = "default_water_material")]
+    pub material: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beach: Option<BeachConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ice: Option<IceConfig>,
+}
+
+fn default_water_material() -> u8 {
+    4
+}
+
+/// Near-surface voxels from `below` under to `above` over the sea level turn to `material`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BeachConfig {
+    pub material: u8,
+    #[serde(default = "default_beach_above")]
+    pub above: f32,
+    #[serde(default = "default_beach_below")]
+    pub below: f32,
+    /// How many voxels under the surface the band reaches.
+    #[serde(default = "default_beach_depth")]
+    pub depth: u32,
+}
+
+fn default_beach_above() -> f32 {
+    2.0
+}
+
+fn default_beach_below() -> f32 {
+    4.0
+}
+
+fn default_beach_depth() -> u32 {
+    3
+}
+
+/// The top water layer freezes in the listed biomes, and exposed terrain at or above
+/// `min_y` is capped with ice.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IceConfig {
+    pub material: u8,
+    #[serde(default)]
+    pub biomes: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_y: Option<f32>,
+}
+
+impl WaterConfig {
+    /// Material for an air voxel, `None` above the sea.
+    pub fn fill(&self, wy: f32, biome: Option<&BiomeConfig>) -> Option<u8> {
+        if wy > self.sea_level {
+            return None;
+        }
+        if let Some(ice) = &self.ice {
+            let frozen = biome.is_some_and(|b| ice.biomes.contains(&b.name));
+            if frozen && wy + 1.0 > self.sea_level {
+                return Some(ice.material);
+            }
+        }
+        Some(self.material)
+    }
+
+    /// Replaces `material` of a solid voxel with `depth` solid voxels above it where the
+    /// beach band or the ice cap applies.
+    pub fn shore(&self, wy: f32, depth: u32, material: u8) -> u8 {
+        if let Some(ice) = &self.ice
+            && depth == 0
+            && ice.min_y.is_some_and(|min_y| wy >= min_y)
+        {
+            return ice.material;
+        }
+        if let Some(beach) = &self.beach
+            && depth < beach.depth
+            && wy >= self.sea_level - beach.below
+            && wy <= self.sea_level + beach.above
+        {
+            return beach.material;
+        }
+        material
+    }
+
+    /// Voxels below the surface whose material the shore rules can depend on.
+    pub fn max_depth(&self) -> u32 {
+        self.beach.as_ref().map_or(1, |b| b.depth.max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::analytic::FlatConfig;
+    use crate::mapgen::config::MapGenConfig;
+    use crate::mapgen::generators::generate_chunk_voxels;
+    use crate::mapgen::registry::GeneratorRegistry;
+    use crate::{get_zxy_index, CS_P};
+    use glam::IVec3;
+
+    fn water() -> WaterConfig {
+        toml::from_str(
+            "sea_level = 10.0\n\
+             beach = { material = 7, above = 2.0, below = 4.0, depth = 2 }\n\
+             ice = { material = 5, biomes = [\"tundra\"], min_y = 30.0 }",
+        )
+        .unwrap()
+    }
+
+    fn biome(name: &str) -> BiomeConfig {
+        toml::from_str(&format!("name = \"{name}\"\ntemperature = 0.0\nhumidity = 0.0\nsurface = 3\nsubsurface = 2"))
+            .unwrap()
+    }
+
+    #[test]
+    fn air_fills_up_to_sea_level_and_freezes_on_top() {
+        let water = water();
+        assert_eq!(water.fill(10.5, None), None);
+        assert_eq!(water.fill(10.0, None), Some(4));
+        assert_eq!(water.fill(-50.0, None), Some(4));
+        let (tundra, desert) = (biome("tundra"), biome("desert"));
+        assert_eq!(water.fill(9.5, Some(&tundra)), Some(5));
+        assert_eq!(water.fill(9.0, Some(&tundra)), Some(4));
+        assert_eq!(water.fill(9.5, Some(&desert)), Some(4));
+    }
+
+    #[test]
+    fn shores_take_beach_and_ice_materials() {
+        let water = water();
+        // The beach band covers 6..=12 and the top two solid voxels
+        assert_eq!(water.shore(6.0, 1, 2), 7);
+        assert_eq!(water.shore(12.0, 0, 2), 7);
+        assert_eq!(water.shore(5.0, 0, 2), 2);
+        assert_eq!(water.shore(13.0, 0, 2), 2);
+        assert_eq!(water.shore(8.0, 2, 2), 2);
+        // Ice caps exposed terrain from min_y up, above the beach
+        assert_eq!(water.shore(30.0, 0, 2), 5);
+        assert_eq!(water.shore(30.0, 1, 2), 2);
+        assert_eq!(water.max_depth(), 2);
+    }
+
+    #[test]
+    fn flat_terrain_under_the_sea_is_flooded() {
+        let config = MapGenConfig {
+            generator: Some("flat".to_string()),
+            flat: Some(FlatConfig { height: 8.0 }),
+            water: Some(water()),
+            ..MapGenConfig::default()
+        };
+        let generator = GeneratorRegistry::default().build(config).unwrap();
+        let (voxels, _) = generate_chunk_voxels(IVec3::ZERO, &*generator);
+        let column: Vec<u8> = (0..CS_P).map(|y| voxels[get_zxy_index(20, y, 30)]).collect();
+        let top = column.iter().rposition(|&m| m != 0 && m != 4).unwrap();
+        assert!(column[..=top].iter().all(|&m| m != 0 && m != 4), "{column:?}");
+        // Padded index y is world y - 1
+        for (y, &m) in column.iter().enumerate().skip(top + 1) {
+            let wy = y as f32 - 1.0;
+            assert_eq!(m, if wy <= 10.0 { 4 } else { 0 }, "at {wy}: {column:?}");
+        }
+        assert_eq!(column[top], 7, "{column:?}");
+        assert_eq!(column[top - 1], 7, "{column:?}");
+        assert_ne!(column[top - 2], 7, "{column:?}");
+    }
+}
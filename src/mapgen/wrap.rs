@@ -0,0 +1,267 @@
+// Worlds that wrap around on X and Z
+use super::biome::BiomeMap;
+use super::config::{LayerKind, MapGenConfig, NoiseLayer};
+use super::generators::Generator;
+use super::graph::GraphNode;
+use super::interval::Interval;
+use super::noise::NoiseBasis;
+use super::sampling::lattice_spacing;
+use crate::CS;
+use anyhow::{bail, Result};
+use glam::{IVec3, Vec3};
+use serde::{Deserialize, Serialize};
+
+/// `[wrap]` section: the world repeats every `chunks` chunks along X and Z, so walking off
+/// one edge comes back in at the other, like on a (doughnut-shaped) planet.
+///
+/// ```toml
+/// [wrap]
+/// chunks = 16
+/// ```
+///
+/// Every noise layer is tiled to the world size, rounding its `scale` to fit a whole number
+/// of lattice cells, and chunk positions fold into `0..chunks`, so the chunks on either side
+/// of the seam generate each other's padding exactly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WrapConfig {
+    pub chunks: u32,
+}
+
+impl WrapConfig {
+    /// World width along X and Z in voxels.
+    pub fn size(&self) -> i32 {
+        self.chunks as i32 * CS as i32
+    }
+
+    /// Chunk position with X and Z folded into `0..chunks`.
+    pub fn chunk(&self, chunk: IVec3) -> IVec3 {
+        let n = self.chunks as i32;
+        IVec3::new(chunk.x.rem_euclid(n), chunk.y, chunk.z.rem_euclid(n))
+    }
+
+    /// World voxel X or Z folded into `0..size`.
+    pub fn voxel(&self, w: i32) -> i32 {
+        w.rem_euclid(self.size())
+    }
+
+    /// World X or Z folded into `0..size`.
+    pub fn coord(&self, w: f32) -> f32 {
+        w.rem_euclid(self.size() as f32)
+    }
+
+    /// Checks the world size against `config`, which builds the generator named `generator`.
+    pub fn validate(&self, config: &MapGenConfig, generator: &str) -> Result<()> {
+        anyhow::ensure!(
+            (1..=1024).contains(&self.chunks),
+            "[wrap] chunks must be within 1..=1024, got {}",
+            self.chunks
+        );
+        anyhow::ensure!(generator != "heightmap", "[wrap] needs noise terrain; heightmap images don't tile");
+        anyhow::ensure!(
+            config.erosion_sim.is_none(),
+            "[wrap] can't be combined with [erosion_sim], which erodes a bounded region"
+        );
+        // Lattice nodes must land on the same voxels on both sides of the seam
+        if let Some(spacing) = lattice_spacing(config.sampling.as_ref()) {
+            anyhow::ensure!(
+                self.size() % spacing as i32 == 0,
+                "[wrap] world width {} isn't a multiple of [sampling] spacing {spacing}",
+                self.size()
+            );
+        }
+        Ok(())
+    }
+
+    /// Rewrites the noise of `config` to repeat over the world width: Perlin bases become
+    /// `NoiseBasis::PeriodicPerlin` and scales shift to the nearest size that fits.
+    pub fn tile(&self, config: &mut MapGenConfig) -> Result<()> {
+        for (name, layer) in [
+            ("continentalness", &mut config.continentalness),
+            ("erosion", &mut config.erosion),
+            ("peaks_valleys", &mut config.peaks_valleys),
+            ("climate.temperature", &mut config.climate.temperature),
+            ("climate.humidity", &mut config.climate.humidity),
+        ] {
+            self.tile_noise_layer(layer, name)?;
+        }
+
+        for layer in &mut config.layers {
+            let name = format!("layers \"{}\"", layer.name);
+            if layer.kind == LayerKind::Worley {
+                bail!("[wrap] can't tile worley noise ({name})");
+            }
+            layer.basis = self.periodic(layer.basis, &mut layer.scale, layer.lacunarity, &name)?;
+            if let Some(warp) = &mut layer.warp {
+                warp.basis = self.periodic(warp.basis, &mut warp.scale, warp.lacunarity, &format!("{name} warp"))?;
+            }
+        }
+
+        if let Some(graph) = &mut config.graph {
+            for (node, noise) in graph.nodes.iter_mut().filter_map(|(node, n)| match n {
+                GraphNode::Noise(noise) => Some((node, noise)),
+                _ => None,
+            }) {
+                let name = format!("graph node \"{node}\"");
+                noise.basis = self.periodic(noise.basis, &mut noise.scale, noise.lacunarity, &name)?;
+                if let Some(warp) = &mut noise.warp {
+                    warp.basis = self.periodic(warp.basis, &mut warp.scale, warp.lacunarity, &format!("{name} warp"))?;
+                }
+            }
+        }
+
+        // Both depths tile; `CaveCarver` blends them rather than interpolating the scale
+        if let Some(caves) = &mut config.caves {
+            if let Some(cheese) = &mut caves.cheese {
+                let deep = self.periodic(cheese.basis, &mut cheese.deep_scale, 2.0, "caves.cheese")?;
+                cheese.basis = self.periodic(cheese.basis, &mut cheese.scale, 2.0, "caves.cheese")?;
+                cheese.deep_basis = (deep != cheese.basis).then_some(deep);
+            }
+            if let Some(spaghetti) = &mut caves.spaghetti {
+                let deep = self.periodic(spaghetti.basis, &mut spaghetti.deep_scale, 2.0, "caves.spaghetti")?;
+                spaghetti.basis = self.periodic(spaghetti.basis, &mut spaghetti.scale, 2.0, "caves.spaghetti")?;
+                spaghetti.deep_basis = (deep != spaghetti.basis).then_some(deep);
+            }
+        }
+        Ok(())
+    }
+
+    fn tile_noise_layer(&self, layer: &mut NoiseLayer, name: &str) -> Result<()> {
+        layer.basis = self.periodic(layer.basis, &mut layer.scale, layer.lacunarity, name)?;
+        if let Some(warp) = &mut layer.warp {
+            warp.basis = self.periodic(warp.basis, &mut warp.scale, warp.lacunarity, &format!("{name} warp"))?;
+        }
+        Ok(())
+    }
+
+    // Periodic basis for noise of feature size `scale`, which moves to the nearest size
+    // that fits the world width a whole number of times. Octaves only repeat with it when
+    // `lacunarity` is a whole number.
+    fn periodic(&self, basis: NoiseBasis, scale: &mut f32, lacunarity: f32, name: &str) -> Result<NoiseBasis> {
+        if basis == NoiseBasis::Simplex {
+            bail!("[wrap] can't tile simplex noise ({name}); use the perlin basis");
+        }
+        anyhow::ensure!(
+            lacunarity >= 1.0 && lacunarity.fract() == 0.0,
+            "[wrap] needs a whole-number lacunarity to tile {name}, got {lacunarity}"
+        );
+        let size = self.size() as f32;
+        let period = (size / *scale).round().max(1.0);
+        *scale = size / period;
+        Ok(NoiseBasis::PeriodicPerlin { period: period as u32 })
+    }
+}
+
+/// Evaluates `inner` at positions folded into the wrapped world, so every density lookup
+/// (lattice nodes, surface searches, gradients) agrees across the seam.
+pub struct WrappedGenerator {
+    inner: Box<dyn Generator>,
+    wrap: WrapConfig,
+}
+
+impl WrappedGenerator {
+    pub fn new(inner: Box<dyn Generator>, wrap: WrapConfig) -> Self {
+        Self { inner, wrap }
+    }
+}
+
+impl Generator for WrappedGenerator {
+    fn config(&self) -> &MapGenConfig {
+        self.inner.config()
+    }
+
+    fn biomes(&self) -> Option<&BiomeMap> {
+        self.inner.biomes()
+    }
+
+    fn generate_density(&self, wx: f32, wy: f32, wz: f32) -> f32 {
+        self.inner.generate_density(self.wrap.coord(wx), wy, self.wrap.coord(wz))
+    }
+
+    fn generate_column(&self, wx: f32, wz: f32, ys: &[f32], out: &mut [f32]) {
+        self.inner.generate_column(self.wrap.coord(wx), self.wrap.coord(wz), ys, out);
+    }
+
+    fn generate_density_and_gradient(&self, wx: f32, wy: f32, wz: f32) -> (f32, Vec3) {
+        self.inner.generate_density_and_gradient(self.wrap.coord(wx), wy, self.wrap.coord(wz))
+    }
+
+    fn density_bounds(&self, lo: Vec3, hi: Vec3) -> Option<Interval> {
+        // A box reaching over the seam covers the whole width on that axis
+        let size = self.wrap.size() as f32;
+        let fold = |lo: f32, hi: f32| {
+            let start = self.wrap.coord(lo);
+            if start + (hi - lo) < size { (start, start + (hi - lo)) } else { (0.0, size) }
+        };
+        let ((lx, hx), (lz, hz)) = (fold(lo.x, hi.x), fold(lo.z, hi.z));
+        self.inner.density_bounds(Vec3::new(lx, lo.y, lz), Vec3::new(hx, hi.y, hz))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::analytic::FlatConfig;
+    use crate::mapgen::caves::CaveCarver;
+    use crate::mapgen::generators::generate_chunk_voxels;
+    use crate::mapgen::registry::GeneratorRegistry;
+    use crate::{get_zxy_index, CS_P};
+
+    // Flat ground at y = 30 with caves whose scales change over the chunk's height
+    fn cave_world() -> MapGenConfig {
+        MapGenConfig {
+            generator: Some("flat".to_string()),
+            flat: Some(FlatConfig { height: 30.0 }),
+            caves: Some(
+                toml::from_str(
+                    "surface_y = 30.0\n\
+                     depth_range = 40.0\n\
+                     surface_margin = 0\n\
+                     cheese = { scale = 14.0, deep_scale = 30.0, threshold = 0.2 }\n\
+                     spaghetti = { scale = 20.0, deep_scale = 45.0, width = 0.1 }",
+                )
+                .unwrap(),
+            ),
+            wrap: Some(WrapConfig { chunks: 3 }),
+            ..MapGenConfig::default()
+        }
+    }
+
+    #[test]
+    fn seam_chunks_share_their_border_voxels() {
+        let generator = GeneratorRegistry::default().build(cave_world()).unwrap();
+        let chunk = |x, z| generate_chunk_voxels(IVec3::new(x, 0, z), &*generator).0;
+        let (origin, east, south) = (chunk(0, 0), chunk(2, 0), chunk(0, 2));
+
+        // The far chunk's last layer is chunk 0's padding, and its padding chunk 0's first layer
+        let mut materials = [false; 2];
+        for a in 0..CS_P {
+            for y in 0..CS_P {
+                for (far, near) in [(CS, 0), (CS + 1, 1)] {
+                    let voxel = origin[get_zxy_index(near, y, a)];
+                    assert_eq!(east[get_zxy_index(far, y, a)], voxel, "x seam at y {y}, z {a}");
+                    assert_eq!(south[get_zxy_index(a, y, far)], origin[get_zxy_index(a, y, near)], "z seam at x {a}, y {y}");
+                    materials[(voxel != 0) as usize] = true;
+                }
+            }
+        }
+        assert_eq!(materials, [true, true], "the seam should cut through terrain");
+    }
+
+    #[test]
+    fn tiled_caves_repeat_over_the_world_width() {
+        let mut config = cave_world();
+        let wrap = config.wrap.clone().unwrap();
+        wrap.tile(&mut config).unwrap();
+        let carver = CaveCarver::new(config.seed, config.caves.unwrap());
+        let size = wrap.size() as f32;
+        let mut carved = 0;
+        for i in 0..2000 {
+            let (x, y, z) = ((i * 37 % 186) as f32, (i % 60 - 20) as f32, (i * 53 % 186) as f32);
+            let here = carver.carves(x, y, z, 10);
+            assert_eq!(carver.carves(x + size, y, z, 10), here, "x {x}, y {y}, z {z}");
+            assert_eq!(carver.carves(x, y, z - size, 10), here, "x {x}, y {y}, z {z}");
+            carved += here as usize;
+        }
+        assert!(carved > 0);
+    }
+}
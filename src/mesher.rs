@@ -42,6 +42,22 @@ impl MeshData {
     }
 }
 
+/// Builds the opaque mask for a padded chunk laid out with `get_zxy_index`.
+/// Each u64 covers the Z column at `y * CS_P + x`.
+pub fn build_opaque_mask(voxels: &[u8], opaque_mask: &mut [u64]) {
+    debug_assert_eq!(opaque_mask.len(), CS_P2);
+
+    for (column, mask) in voxels.chunks_exact(CS_P).zip(opaque_mask.iter_mut()) {
+        let mut bits = 0u64;
+        for (z, &v) in column.iter().enumerate() {
+            if v != 0 {
+                bits |= 1u64 << z;
+            }
+        }
+        *mask = bits;
+    }
+}
+
 #[inline]
 fn get_axis_index(axis: usize, a: usize, b: usize, c: usize) -> usize {
     if axis == 0 {
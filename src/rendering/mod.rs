@@ -1 +1,2 @@
 pub mod chunk_renderer;
+pub mod palette;
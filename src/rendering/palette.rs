@@ -0,0 +1,30 @@
+// Material palette shared by the viewers and the exporters.
+// Mirrors `colorLookup` in the viewer shaders: material ID `n` uses entry `n - 1`, 0 is air.
+
+pub const COLOR_LOOKUP: [[f32; 3]; 8] = [
+    [0.2, 0.659, 0.839],
+    [0.302, 0.302, 0.302],
+    [0.278, 0.600, 0.141],
+    [0.1, 0.1, 0.6],
+    [0.1, 0.6, 0.6],
+    [0.6, 0.1, 0.6],
+    [0.6, 0.6, 0.1],
+    [0.6, 0.1, 0.1],
+];
+
+pub fn material_color(material: u8) -> Option<[f32; 3]> {
+    if material == 0 {
+        return None;
+    }
+    COLOR_LOOKUP.get(material as usize - 1).copied()
+}
+
+/// The viewers write `colorLookup` straight to a non-sRGB framebuffer, so the values are
+/// effectively sRGB-encoded. Formats that expect linear color need this conversion.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}